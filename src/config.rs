@@ -36,16 +36,19 @@ pub struct ConfigIn {
 pub struct PartialPluginContext {
     cpupool: Arc<CpuPool>,
     core: Rc<RefCell<tokio_core::reactor::Core>>,
+    poll_interval: Duration,
 }
 
 impl PartialPluginContext {
     pub fn new(
         cpupool: Arc<CpuPool>,
         core: Rc<RefCell<tokio_core::reactor::Core>>,
+        poll_interval: Duration,
     ) -> PartialPluginContext {
         PartialPluginContext {
             cpupool: cpupool,
             core: core,
+            poll_interval: poll_interval,
         }
     }
 
@@ -55,6 +58,7 @@ impl PartialPluginContext {
             config: config,
             cpupool: self.cpupool.clone(),
             core: self.core.clone(),
+            poll_interval: self.poll_interval,
         }
     }
 }
@@ -20,6 +20,13 @@ extern crate tokio_io;
 extern crate tokio_timer;
 extern crate tokio_core;
 extern crate num_cpus;
+extern crate regex;
+extern crate tokio_uds;
+extern crate flate2;
+extern crate libc;
+extern crate native_tls;
+extern crate tokio_tls;
+extern crate tokio_signal;
 
 #[macro_use]
 extern crate serde_derive;
@@ -137,12 +137,13 @@ fn run() -> Result<()> {
     let core = Rc::new(RefCell::new(Core::new()?));
 
     let (input, output) = {
-        let partial_context = PartialPluginContext::new(cpupool.clone(), core.clone());
+        let partial_context =
+            PartialPluginContext::new(cpupool.clone(), core.clone(), config.poll_interval);
         setup_plugins(setups, &config, &plugins, &partial_context)?
     };
 
     let poller = Poller::new(input.clone(), output.clone());
-    let updater = Updater::new(input.clone(), cpupool.clone());
+    let updater = Updater::new(input.clone(), cpupool.clone(), config.update_interval);
 
     let ref mut core = core.try_borrow_mut()?;
 
@@ -0,0 +1,115 @@
+use super::MetricId;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+/// Whether the sample is the rate of a counter, as calculated by `Deltas`.
+///
+/// Rates are reported with a unit per second, like `bytes/s`.
+pub fn is_rate(id: &MetricId) -> bool {
+    id.tag("unit").map(|unit| unit.ends_with("/s")).unwrap_or(false)
+}
+
+/// Tracks monotonically increasing counters, like the ones found in procfs, and calculates how
+/// much they changed since the last update.
+///
+/// Counters which disappear between updates are forgotten, and counters which decrease are
+/// considered to have been reset.
+#[derive(Debug)]
+pub struct Deltas<K> {
+    previous: HashMap<K, u64>,
+    updated: Option<Instant>,
+}
+
+impl<K: Hash + Eq + Clone> Deltas<K> {
+    pub fn new() -> Deltas<K> {
+        Deltas {
+            previous: HashMap::new(),
+            updated: None,
+        }
+    }
+
+    /// Update all counters, returning the elapsed time in seconds and how much each counter
+    /// increased since the last update.
+    ///
+    /// Returns `None` for the first update, since there is nothing to compare against.
+    pub fn update<I>(&mut self, now: Instant, values: I) -> Option<(f64, HashMap<K, u64>)>
+    where
+        I: IntoIterator<Item = (K, u64)>,
+    {
+        let mut next = HashMap::new();
+        let mut deltas = HashMap::new();
+
+        for (key, value) in values {
+            if let Some(previous) = self.previous.get(&key) {
+                if value >= *previous {
+                    deltas.insert(key.clone(), value - previous);
+                }
+            }
+
+            next.insert(key, value);
+        }
+
+        self.previous = next;
+
+        let elapsed = self.updated.map(|updated| {
+            let elapsed = now.duration_since(updated);
+            elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+        });
+
+        self.updated = Some(now);
+
+        elapsed.map(|elapsed| (elapsed, deltas))
+    }
+
+    /// Update all counters, returning the per-second rate of each.
+    pub fn rates<I>(&mut self, now: Instant, values: I) -> Vec<(K, f64)>
+    where
+        I: IntoIterator<Item = (K, u64)>,
+    {
+        match self.update(now, values) {
+            Some((elapsed, deltas)) if elapsed > 0f64 => {
+                deltas
+                    .into_iter()
+                    .map(|(key, delta)| (key, delta as f64 / elapsed))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn rates() {
+        let mut d: Deltas<&'static str> = Deltas::new();
+        let now = Instant::now();
+
+        assert!(d.rates(now, vec![("a", 10), ("b", 100)]).is_empty());
+
+        let mut rates = d.rates(now + Duration::from_secs(2), vec![("a", 20), ("b", 50)]);
+        rates.sort_by(|a, b| a.0.cmp(b.0));
+
+        // b was reset, and is only picked up again next update.
+        assert_eq!(vec![("a", 5f64)], rates);
+    }
+
+    #[test]
+    fn forget() {
+        let mut d: Deltas<&'static str> = Deltas::new();
+        let now = Instant::now();
+
+        d.update(now, vec![("a", 10)]);
+        d.update(now, vec![("b", 10)]);
+
+        let (_, deltas) = d.update(now, vec![("a", 20), ("b", 20)]).unwrap();
+        assert_eq!(None, deltas.get("a"));
+        assert_eq!(Some(&10), deltas.get("b"));
+    }
+}
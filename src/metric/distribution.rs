@@ -0,0 +1,86 @@
+use histogram::Histogram;
+use std::f64;
+
+/// Values are stored in the histogram with this many fractional digits.
+const SCALE: f64 = 1000f64;
+
+/// Aggregated distribution of recorded values, reported as a set of statistics.
+pub struct Distribution {
+    histogram: Histogram,
+    count: f64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Distribution {
+    pub fn new() -> Distribution {
+        Distribution {
+            histogram: Histogram::new(),
+            count: 0f64,
+            sum: 0f64,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Record a value, where the weight is how many times it should be counted.
+    pub fn record(&mut self, value: f64, weight: f64) {
+        // values out of range for the histogram only affect percentiles.
+        let scaled = (value.max(0f64) * SCALE).round() as u64;
+        let _ = self.histogram.increment_by(scaled, weight.round().max(1f64) as u64);
+
+        self.count += weight;
+        self.sum += value * weight;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Statistics for the recorded values, named `count`, `min`, `max`, `mean` and `p<N>` for
+    /// each requested percentile.
+    ///
+    /// Percentiles are approximated, and negative values are counted as zero.
+    pub fn stats(&self, percentiles: &[f64]) -> Vec<(String, f64)> {
+        let mut stats = vec![
+            ("count".to_owned(), self.count),
+            ("min".to_owned(), self.min),
+            ("max".to_owned(), self.max),
+            ("mean".to_owned(), self.sum / self.count),
+        ];
+
+        for p in percentiles {
+            if let Ok(value) = self.histogram.percentile(*p) {
+                stats.push((format!("p{}", p), value as f64 / SCALE));
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stat(stats: &[(String, f64)], name: &str) -> f64 {
+        stats.iter().find(|s| s.0 == name).unwrap().1
+    }
+
+    #[test]
+    fn stats() {
+        let mut d = Distribution::new();
+
+        for i in 1..101 {
+            d.record(i as f64, 1f64);
+        }
+
+        d.record(1000f64, 2f64);
+
+        let stats = d.stats(&[50f64, 99f64]);
+        assert_eq!(102f64, stat(&stats, "count"));
+        assert_eq!(1f64, stat(&stats, "min"));
+        assert_eq!(1000f64, stat(&stats, "max"));
+        assert_eq!(7050f64 / 102f64, stat(&stats, "mean"));
+        assert!((stat(&stats, "p50") - 51f64).abs() < 2f64);
+    }
+}
@@ -18,6 +18,14 @@ impl MetricIdBuilder {
         self
     }
 
+    pub fn tags(mut self, tags: &[(&str, &str)]) -> MetricIdBuilder {
+        for &(key, value) in tags {
+            self.tags.push((key.to_owned(), value.to_owned()));
+        }
+
+        self
+    }
+
     pub fn resource(mut self, key: &str, value: &str) -> MetricIdBuilder {
         self.resource.push((key.to_owned(), value.to_owned()));
         self
@@ -55,6 +63,23 @@ impl MetricId {
             resource: Vec::new(),
         }
     }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
+
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// Get the value of the first tag with the given name.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|t| t.0 == name).map(|t| t.1.as_str())
+    }
+
+    pub fn resource(&self) -> &[(String, String)] {
+        &self.resource
+    }
 }
 
 impl fmt::Display for MetricId {
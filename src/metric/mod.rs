@@ -3,10 +3,14 @@ pub mod metric_id;
 pub mod counter;
 pub mod gauge;
 pub mod meter;
+pub mod deltas;
+pub mod distribution;
 
 pub use self::metric_id::MetricId;
 pub use self::metric_id::MetricIdBuilder;
 pub use self::gauge::Gauge;
+pub use self::deltas::{Deltas, is_rate};
+pub use self::distribution::Distribution;
 
 pub fn key(key: &str) -> MetricIdBuilder {
     return MetricId::new_with_key(key);
@@ -0,0 +1,78 @@
+//! Parser for the machine readable Apache `server-status?auto` page.
+//!
+//! ```text
+//! Total Accesses: 131
+//! BusyWorkers: 1
+//! Scoreboard: _W___....
+//! ```
+
+/// Names of the scoreboard states, by their character.
+const SCOREBOARD: &'static [(char, &'static str)] = &[
+    ('_', "waiting"),
+    ('S', "starting"),
+    ('R', "reading"),
+    ('W', "sending"),
+    ('K', "keepalive"),
+    ('D', "dns"),
+    ('C', "closing"),
+    ('L', "logging"),
+    ('G', "finishing"),
+    ('I', "idle-cleanup"),
+    ('.', "open"),
+];
+
+/// Parse the `name: value` fields of the page.
+pub fn parse_server_status(input: &str) -> Vec<(String, String)> {
+    input
+        .lines()
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((line[..colon].trim().to_owned(), line[colon + 1..].trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Count the workers in every state of the scoreboard, including states without workers.
+pub fn scoreboard_states(scoreboard: &str) -> Vec<(&'static str, u64)> {
+    SCOREBOARD
+        .iter()
+        .map(|&(c, name)| (name, scoreboard.chars().filter(|s| *s == c).count() as u64))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SERVER_STATUS: &'static str = "localhost\n\
+        ServerVersion: Apache/2.4.25 (Debian)\n\
+        Total Accesses: 131\n\
+        Total kBytes: 156\n\
+        CPULoad: .0225\n\
+        Uptime: 1234\n\
+        BusyWorkers: 1\n\
+        IdleWorkers: 74\n\
+        Scoreboard: _W___K....\n";
+
+    #[test]
+    fn test_parse_server_status() {
+        let fields = parse_server_status(SERVER_STATUS);
+
+        assert_eq!(8, fields.len());
+        assert_eq!(("ServerVersion".to_owned(), "Apache/2.4.25 (Debian)".to_owned()), fields[0]);
+        assert_eq!(("CPULoad".to_owned(), ".0225".to_owned()), fields[3]);
+        assert_eq!(("Scoreboard".to_owned(), "_W___K....".to_owned()), fields[7]);
+    }
+
+    #[test]
+    fn test_scoreboard_states() {
+        let states = scoreboard_states("_W___K....");
+
+        assert_eq!(SCOREBOARD.len(), states.len());
+        assert!(states.contains(&("waiting", 4)));
+        assert!(states.contains(&("sending", 1)));
+        assert!(states.contains(&("keepalive", 1)));
+        assert!(states.contains(&("open", 4)));
+        assert!(states.contains(&("reading", 0)));
+    }
+}
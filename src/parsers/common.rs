@@ -4,7 +4,52 @@ use std::str::{self, FromStr};
 named!(pub type_u64<u64>,
        map_res!(map_res!(digit, str::from_utf8), FromStr::from_str));
 
+named!(pub type_i64<i64>,
+       map_res!(map_res!(recognize!(pair!(opt!(tag!("-")), digit)), str::from_utf8),
+                FromStr::from_str));
+
+named!(pub type_f64<f64>,
+       map_res!(map_res!(recognize!(tuple!(
+                    opt!(alt!(tag!("-") | tag!("+"))),
+                    digit,
+                    opt!(pair!(tag!("."), digit)),
+                    opt!(tuple!(alt!(tag!("e") | tag!("E")), opt!(alt!(tag!("-") | tag!("+"))), digit)))),
+                         str::from_utf8),
+                FromStr::from_str));
+
+named!(pub type_hex_u64<u64>,
+       map_res!(map_res!(hex_digit, str::from_utf8), |s| u64::from_str_radix(s, 16)));
+
+// A single whitespace separated field, decoded as an owned string.
+named!(pub type_string<String>,
+       map!(map_res!(is_not!(" \t\r\n"), str::from_utf8), str::to_owned));
+
+// A file containing a single unsigned value, like most of /proc/sys.
+named!(pub single_u64<u64>,
+       do_parse!(value: type_u64 >> opt!(line_ending) >> (value)));
+
+named!(pub single_i64<i64>,
+       do_parse!(value: type_i64 >> opt!(line_ending) >> (value)));
+
 #[cfg(test)]
 mod test {
     use super::*;
+
+    #[test]
+    fn test_type_i64() {
+        assert_eq!(-2, type_i64(b"-2 ").to_full_result().unwrap());
+        assert_eq!(42, type_i64(b"42 ").to_full_result().unwrap());
+    }
+
+    #[test]
+    fn test_type_hex_u64() {
+        assert_eq!(0x113c, type_hex_u64(b"0000113c ").to_full_result().unwrap());
+    }
+
+    #[test]
+    fn test_type_f64() {
+        assert_eq!(0.25, type_f64(b"0.25 ").to_full_result().unwrap());
+        assert_eq!(12f64, type_f64(b"12 ").to_full_result().unwrap());
+        assert_eq!(-1.5e-3, type_f64(b"-1.5e-3 ").to_full_result().unwrap());
+    }
 }
@@ -0,0 +1,44 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// Contents of /proc/sys/fs/file-nr
+#[derive(Debug, Default, PartialEq)]
+pub struct FileNr {
+    /// allocated file handles
+    pub allocated: u64,
+    /// allocated but unused file handles (always zero since 2.6)
+    pub free: u64,
+    /// maximum number of file handles
+    pub max: u64,
+}
+
+impl FileNr {
+    pub fn used(&self) -> u64 {
+        self.allocated - self.free
+    }
+}
+
+named!(pub parse_file_nr<FileNr>,
+       do_parse!(
+           allocated: type_u64 >> space >>
+           free: type_u64 >> space >>
+           max: type_u64 >> line_ending >>
+           (FileNr {
+               allocated: allocated,
+               free: free,
+               max: max,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_nr() {
+        let text = b"4256\t0\t9223372036854775807\n";
+        let file_nr = parse_file_nr(text).to_full_result().unwrap();
+        assert_eq!(4256, file_nr.allocated);
+        assert_eq!(9223372036854775807, file_nr.max);
+    }
+}
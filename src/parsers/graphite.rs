@@ -0,0 +1,48 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// A single line in the Graphite plaintext protocol: `<path> <value> [<timestamp>]`
+#[derive(Debug, Default, PartialEq)]
+pub struct GraphiteLine {
+    pub path: String,
+    pub value: f64,
+    /// seconds since the epoch
+    pub timestamp: Option<u64>,
+}
+
+// Parse a single line, including its line ending.
+named!(pub parse_graphite_line<GraphiteLine>,
+       do_parse!(
+           opt!(space) >>
+           path: type_string >> space >>
+           value: type_f64 >>
+           timestamp: opt!(preceded!(space, type_u64)) >>
+           opt!(space) >> line_ending >>
+           (GraphiteLine {
+               path: path,
+               value: value,
+               timestamp: timestamp,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_graphite_line() {
+        let line = parse_graphite_line(b"servers.web01.cpu.load 0.75 1500000000\n")
+            .to_full_result()
+            .unwrap();
+
+        assert_eq!("servers.web01.cpu.load", line.path);
+        assert_eq!(0.75, line.value);
+        assert_eq!(Some(1500000000), line.timestamp);
+
+        let line = parse_graphite_line(b"foo.bar -12\n").to_full_result().unwrap();
+        assert_eq!(-12f64, line.value);
+        assert_eq!(None, line.timestamp);
+
+        assert!(parse_graphite_line(b"foo.bar baz\n").to_full_result().is_err());
+    }
+}
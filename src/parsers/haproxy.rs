@@ -0,0 +1,94 @@
+//! Parser for the HAProxy CSV statistics, as served by the stats page with `;csv` or by the
+//! `show stat` command of the stats socket.
+//!
+//! ```text
+//! # pxname,svname,qcur,qmax,scur,...
+//! http-in,FRONTEND,,,3,...
+//! ```
+
+use std::collections::HashMap;
+
+/// A single row of statistics, for a frontend, backend, server or listener.
+#[derive(Debug, PartialEq)]
+pub struct StatRow {
+    pub proxy: String,
+    pub server: String,
+    /// non-empty fields, by their header name.
+    pub fields: HashMap<String, String>,
+}
+
+impl StatRow {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+pub fn parse_stats_csv(input: &str) -> Result<Vec<StatRow>, String> {
+    let mut lines = input.lines();
+
+    let header = match lines.next() {
+        Some(line) if line.starts_with('#') => line[1..].trim(),
+        _ => return Err("missing csv header".to_owned()),
+    };
+
+    let names: Vec<&str> = header.split(',').collect();
+
+    if names.len() < 2 || names[0] != "pxname" || names[1] != "svname" {
+        return Err(format!("unexpected csv header: {}", header));
+    }
+
+    let mut rows = Vec::new();
+
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(',').collect();
+
+        if values.len() < 2 {
+            return Err(format!("short csv line: {}", line));
+        }
+
+        let fields = names
+            .iter()
+            .zip(values.iter())
+            .skip(2)
+            .filter(|&(name, value)| !name.is_empty() && !value.is_empty())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        rows.push(StatRow {
+            proxy: values[0].to_owned(),
+            server: values[1].to_owned(),
+            fields: fields,
+        });
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STATS: &'static str = "# pxname,svname,qcur,qmax,scur,smax,slim,stot,bin,bout,status,type,\n\
+        http-in,FRONTEND,,,3,10,2000,120,5120,10240,OPEN,0,\n\
+        app,web1,0,0,1,4,,60,2048,4096,UP,2,\n\
+        app,BACKEND,0,0,1,4,200,60,2048,4096,UP,1,\n";
+
+    #[test]
+    fn test_parse_stats_csv() {
+        let rows = parse_stats_csv(STATS).unwrap();
+
+        assert_eq!(3, rows.len());
+        assert_eq!("http-in", rows[0].proxy);
+        assert_eq!("FRONTEND", rows[0].server);
+        assert_eq!(None, rows[0].get("qcur"));
+        assert_eq!(Some("3"), rows[0].get("scur"));
+        assert_eq!(Some("UP"), rows[1].get("status"));
+        assert_eq!(Some("1"), rows[2].get("type"));
+
+        assert!(parse_stats_csv("<html></html>").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Parser for the InfluxDB line protocol.
+//!
+//! `<measurement>[,<tag>=<value>...] <field>=<value>[,<field>=<value>...] [<timestamp>]`
+//!
+//! Escaping rules differ between the parts of a line, which is why this is written by hand.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfluxValue {
+    Float(f64),
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl InfluxValue {
+    /// The numeric value of the field, strings have none.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            InfluxValue::Float(value) => Some(value),
+            InfluxValue::Integer(value) => Some(value as f64),
+            InfluxValue::Boolean(value) => Some(if value { 1f64 } else { 0f64 }),
+            InfluxValue::String(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct InfluxLine {
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, InfluxValue)>,
+    /// nanoseconds since the epoch
+    pub timestamp: Option<i64>,
+}
+
+/// Read until one of the unescaped terminators, removing escapes.
+///
+/// Returns the unescaped text and the remaining input, starting at the terminator.
+fn take_escaped<'a>(input: &'a str, terminators: &[char]) -> (String, &'a str) {
+    let mut out = String::new();
+    let mut it = input.char_indices();
+
+    while let Some((i, c)) = it.next() {
+        if c == '\\' {
+            match it.next() {
+                Some((_, next)) if terminators.contains(&next) || next == '\\' => out.push(next),
+                Some((_, next)) => {
+                    out.push(c);
+                    out.push(next);
+                }
+                None => out.push(c),
+            }
+
+            continue;
+        }
+
+        if terminators.contains(&c) {
+            return (out, &input[i..]);
+        }
+
+        out.push(c);
+    }
+
+    (out, "")
+}
+
+fn parse_field_value(input: &str) -> Result<(InfluxValue, &str), String> {
+    if input.starts_with('"') {
+        let mut out = String::new();
+        let mut it = input.char_indices().skip(1);
+
+        while let Some((i, c)) = it.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, next)) = it.next() {
+                        out.push(next);
+                    }
+                }
+                '"' => return Ok((InfluxValue::String(out), &input[i + 1..])),
+                c => out.push(c),
+            }
+        }
+
+        return Err("unterminated string".to_owned());
+    }
+
+    let end = input.find(|c| c == ',' || c == ' ').unwrap_or(input.len());
+    let (value, rest) = input.split_at(end);
+
+    let value = match value {
+        "t" | "T" | "true" | "True" | "TRUE" => InfluxValue::Boolean(true),
+        "f" | "F" | "false" | "False" | "FALSE" => InfluxValue::Boolean(false),
+        v if v.ends_with('i') => {
+            InfluxValue::Integer(v[..v.len() - 1].parse::<i64>().map_err(|e| e.to_string())?)
+        }
+        v => InfluxValue::Float(v.parse::<f64>().map_err(|e| e.to_string())?),
+    };
+
+    Ok((value, rest))
+}
+
+/// Parse a single line, without its line ending.
+pub fn parse_influx_line(line: &str) -> Result<InfluxLine, String> {
+    let (measurement, mut rest) = take_escaped(line.trim_left(), &[',', ' ']);
+
+    if measurement.is_empty() {
+        return Err("missing measurement".to_owned());
+    }
+
+    let mut tags = Vec::new();
+
+    while rest.starts_with(',') {
+        let (key, r) = take_escaped(&rest[1..], &['=', ',', ' ']);
+
+        if !r.starts_with('=') {
+            return Err(format!("missing value for tag: {}", key));
+        }
+
+        let (value, r) = take_escaped(&r[1..], &[',', ' ']);
+        tags.push((key, value));
+        rest = r;
+    }
+
+    let mut fields = Vec::new();
+    rest = rest.trim_left();
+
+    loop {
+        let (key, r) = take_escaped(rest, &['=', ',', ' ']);
+
+        if key.is_empty() || !r.starts_with('=') {
+            return Err(format!("bad field: {}", key));
+        }
+
+        let (value, r) = parse_field_value(&r[1..])?;
+        fields.push((key, value));
+
+        if r.starts_with(',') {
+            rest = &r[1..];
+            continue;
+        }
+
+        rest = r;
+        break;
+    }
+
+    let timestamp = match rest.trim() {
+        "" => None,
+        t => Some(t.parse::<i64>().map_err(|e| e.to_string())?),
+    };
+
+    Ok(InfluxLine {
+        measurement: measurement,
+        tags: tags,
+        fields: fields,
+        timestamp: timestamp,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_influx_line() {
+        let line = parse_influx_line(
+            "cpu,host=server\\ 01,region=eu usage=0.5,count=10i,up=t,msg=\"hi \\\"there\\\"\" 1500000000000000000",
+        ).unwrap();
+
+        assert_eq!("cpu", line.measurement);
+        assert_eq!(
+            vec![
+                ("host".to_owned(), "server 01".to_owned()),
+                ("region".to_owned(), "eu".to_owned()),
+            ],
+            line.tags
+        );
+        assert_eq!(4, line.fields.len());
+        assert_eq!(("usage".to_owned(), InfluxValue::Float(0.5)), line.fields[0]);
+        assert_eq!(("count".to_owned(), InfluxValue::Integer(10)), line.fields[1]);
+        assert_eq!(("up".to_owned(), InfluxValue::Boolean(true)), line.fields[2]);
+        assert_eq!(
+            ("msg".to_owned(), InfluxValue::String("hi \"there\"".to_owned())),
+            line.fields[3]
+        );
+        assert_eq!(Some(1500000000000000000), line.timestamp);
+    }
+
+    #[test]
+    fn test_parse_influx_line_minimal() {
+        let line = parse_influx_line("load value=1").unwrap();
+        assert_eq!("load", line.measurement);
+        assert!(line.tags.is_empty());
+        assert_eq!(None, line.timestamp);
+    }
+
+    #[test]
+    fn test_parse_influx_line_invalid() {
+        assert!(parse_influx_line("").is_err());
+        assert!(parse_influx_line("cpu").is_err());
+        assert!(parse_influx_line("cpu,host usage=1").is_err());
+        assert!(parse_influx_line("cpu usage=abc").is_err());
+        assert!(parse_influx_line("cpu usage=1 notatimestamp").is_err());
+    }
+}
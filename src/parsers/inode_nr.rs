@@ -0,0 +1,39 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// Contents of /proc/sys/fs/inode-nr
+#[derive(Debug, Default, PartialEq)]
+pub struct InodeNr {
+    /// allocated inodes
+    pub nr_inodes: u64,
+    /// allocated inodes which are free
+    pub nr_free_inodes: u64,
+}
+
+impl InodeNr {
+    pub fn used(&self) -> u64 {
+        self.nr_inodes - self.nr_free_inodes
+    }
+}
+
+named!(pub parse_inode_nr<InodeNr>,
+       do_parse!(
+           nr_inodes: type_u64 >> space >>
+           nr_free_inodes: type_u64 >> line_ending >>
+           (InodeNr {
+               nr_inodes: nr_inodes,
+               nr_free_inodes: nr_free_inodes,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_inode_nr() {
+        let inode_nr = parse_inode_nr(b"124805\t34402\n").to_full_result().unwrap();
+        assert_eq!(124805, inode_nr.nr_inodes);
+        assert_eq!(90403, inode_nr.used());
+    }
+}
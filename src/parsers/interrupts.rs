@@ -0,0 +1,134 @@
+use nom::{line_ending, not_line_ending, space};
+use std::str;
+
+use parsers::common::*;
+
+/// A single line of /proc/interrupts or /proc/softirqs
+#[derive(Debug, Default, PartialEq)]
+pub struct Interrupt {
+    /// irq number, or name for architecture specific interrupts (like LOC or NMI)
+    pub irq: String,
+    /// per-cpu counts, in the same order as `Interrupts::cpus`
+    pub counts: Vec<u64>,
+    /// chip, hardware irq and the devices using the irq (not present in softirqs)
+    pub description: Option<String>,
+}
+
+impl Interrupt {
+    pub fn total(&self) -> u64 {
+        self.counts.iter().fold(0, |a, b| a + b)
+    }
+
+    /// The name of the devices attached to the irq, or the description of the interrupt.
+    pub fn name(&self) -> String {
+        let description = match self.description {
+            Some(ref description) => description,
+            None => return self.irq.clone(),
+        };
+
+        let parts: Vec<&str> = description.split_whitespace().collect();
+
+        // numbered irqs are described as `<chip> <hwirq>-<trigger> <devices>`, or
+        // `<chip>-<trigger> <devices>` on older kernels.
+        if self.irq.chars().all(|c| c.is_digit(10)) {
+            match parts.len() {
+                0 | 1 => {}
+                2 => return parts[1].to_owned(),
+                _ => return parts[2..].join(" "),
+            }
+        }
+
+        parts.join(" ")
+    }
+}
+
+/// Contents of /proc/interrupts or /proc/softirqs
+#[derive(Debug, Default, PartialEq)]
+pub struct Interrupts {
+    /// ids of the cpus which are online, in column order
+    pub cpus: Vec<u64>,
+    pub interrupts: Vec<Interrupt>,
+}
+
+named!(cpu_header<Vec<u64> >,
+       do_parse!(
+           opt!(space) >>
+           cpus: many1!(terminated!(preceded!(tag!("CPU"), type_u64), opt!(space))) >>
+           line_ending >>
+           (cpus)));
+
+named!(interrupt_line<Interrupt>,
+       do_parse!(
+           opt!(space) >>
+           irq: map_res!(is_not!(": \t\r\n"), str::from_utf8) >> char!(':') >>
+           counts: many0!(preceded!(space, type_u64)) >>
+           opt!(space) >>
+           description: map_res!(not_line_ending, str::from_utf8) >>
+           line_ending >>
+           (Interrupt {
+               irq: irq.to_owned(),
+               counts: counts,
+               description: match description.trim() {
+                   "" => None,
+                   d => Some(d.to_owned()),
+               },
+           })));
+
+named!(pub parse_interrupts<Interrupts>,
+       do_parse!(
+           cpus: cpu_header >>
+           interrupts: many0!(interrupt_line) >>
+           (Interrupts {
+               cpus: cpus,
+               interrupts: interrupts,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_interrupts() {
+        let text = b"           CPU0       CPU1       CPU3       \n  \
+                     0:         20          0          0   IO-APIC   2-edge      timer\n \
+                     24:    1234567         12          0  PCI-MSI 524288-edge      eth0-TxRx-0\n \
+                     25:          3    7654321          0  PCI-MSI 524289-edge      eth0-TxRx-1\n\
+                     NMI:          0          0          0   Non-maskable interrupts\n\
+                     LOC:   12345678   12345677   12345676   Local timer interrupts\n\
+                     ERR:          0\n\
+                     MIS:          0\n";
+
+        let interrupts = parse_interrupts(text).to_full_result().unwrap();
+        assert_eq!(vec![0, 1, 3], interrupts.cpus);
+        assert_eq!(7, interrupts.interrupts.len());
+
+        let eth0 = &interrupts.interrupts[1];
+        assert_eq!("24", eth0.irq);
+        assert_eq!("eth0-TxRx-0", eth0.name());
+        assert_eq!(vec![1234567, 12, 0], eth0.counts);
+        assert_eq!(1234579, eth0.total());
+
+        let loc = &interrupts.interrupts[4];
+        assert_eq!("LOC", loc.irq);
+        assert_eq!("Local timer interrupts", loc.name());
+
+        let err = &interrupts.interrupts[5];
+        assert_eq!(vec![0], err.counts);
+        assert_eq!(None, err.description);
+    }
+
+    #[test]
+    fn test_parse_softirqs() {
+        let text = b"                    CPU0       CPU1       \n          \
+                     HI:          0          1\n       \
+                     TIMER:     123456     234567\n      \
+                     NET_TX:         10         20\n      \
+                     NET_RX:     300000        400\n";
+
+        let softirqs = parse_interrupts(text).to_full_result().unwrap();
+        assert_eq!(vec![0, 1], softirqs.cpus);
+        assert_eq!(4, softirqs.interrupts.len());
+        assert_eq!("NET_RX", softirqs.interrupts[3].name());
+        assert_eq!(vec![300000, 400], softirqs.interrupts[3].counts);
+    }
+}
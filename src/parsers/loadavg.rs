@@ -0,0 +1,47 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// Contents of /proc/loadavg
+#[derive(Debug, Default, PartialEq)]
+pub struct LoadAvg {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+    /// currently runnable scheduling entities
+    pub running: u64,
+    /// scheduling entities (processes and threads) that currently exist
+    pub total: u64,
+    /// most recently allocated pid
+    pub last_pid: u64,
+}
+
+named!(pub parse_loadavg<LoadAvg>,
+       do_parse!(
+           one: type_f64 >> space >>
+           five: type_f64 >> space >>
+           fifteen: type_f64 >> space >>
+           running: type_u64 >> tag!("/") >>
+           total: type_u64 >> space >>
+           last_pid: type_u64 >> line_ending >>
+           (LoadAvg {
+               one: one,
+               five: five,
+               fifteen: fifteen,
+               running: running,
+               total: total,
+               last_pid: last_pid,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_loadavg() {
+        let loadavg = parse_loadavg(b"0.20 0.18 0.12 1/80 11206\n").to_full_result().unwrap();
+        assert_eq!(0.18, loadavg.five);
+        assert_eq!(80, loadavg.total);
+        assert_eq!(11206, loadavg.last_pid);
+    }
+}
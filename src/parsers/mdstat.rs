@@ -0,0 +1,304 @@
+use nom::{alpha, line_ending, not_line_ending, space};
+use std::str;
+
+use parsers::common::*;
+
+/// A member device of an md array.
+#[derive(Debug, Default, PartialEq)]
+pub struct MdDevice {
+    pub name: String,
+    /// role number of the device in the array
+    pub index: u64,
+    /// flags like F (faulty), S (spare), W (write-mostly) and R (replacement)
+    pub flags: Vec<String>,
+}
+
+impl MdDevice {
+    pub fn faulty(&self) -> bool {
+        self.flags.iter().any(|f| f == "F")
+    }
+
+    pub fn spare(&self) -> bool {
+        self.flags.iter().any(|f| f == "S")
+    }
+}
+
+/// Progress of an ongoing resync, recovery, reshape or check.
+#[derive(Debug, Default, PartialEq)]
+pub struct MdProgress {
+    /// the kind of operation in progress, like resync or recovery
+    pub action: String,
+    pub percent: f64,
+    /// completed blocks
+    pub done: u64,
+    /// total blocks
+    pub total: u64,
+    /// estimated time to finish in minutes
+    pub finish: f64,
+    /// speed in kilobytes per second
+    pub speed: u64,
+}
+
+/// A single array in /proc/mdstat
+#[derive(Debug, Default, PartialEq)]
+pub struct MdArray {
+    pub name: String,
+    /// active or inactive
+    pub state: String,
+    pub read_only: bool,
+    /// raid level, not available for inactive arrays
+    pub personality: Option<String>,
+    pub devices: Vec<MdDevice>,
+    /// size in 1k blocks
+    pub blocks: u64,
+    /// number of devices the array is configured with
+    pub raid_disks: Option<u64>,
+    /// number of devices that are in sync
+    pub in_sync_disks: Option<u64>,
+    pub progress: Option<MdProgress>,
+}
+
+impl MdArray {
+    pub fn degraded(&self) -> bool {
+        match (self.raid_disks, self.in_sync_disks) {
+            (Some(raid), Some(in_sync)) => in_sync < raid,
+            _ => false,
+        }
+    }
+}
+
+enum MdToken {
+    ReadOnly,
+    Personality(String),
+    Device(MdDevice),
+}
+
+enum MdDetail {
+    Progress(MdProgress),
+    Other,
+}
+
+named!(md_device<MdDevice>,
+       do_parse!(
+           name: map_res!(is_not!("[ \t\r\n"), str::from_utf8) >>
+           char!('[') >> index: type_u64 >> char!(']') >>
+           flags: many0!(delimited!(char!('('), map_res!(alpha, str::from_utf8), char!(')'))) >>
+           (MdDevice {
+               name: name.to_owned(),
+               index: index,
+               flags: flags.into_iter().map(str::to_owned).collect(),
+           })));
+
+named!(md_token<MdToken>,
+       alt!(
+           delimited!(char!('('), take_until!("read-only"), tag!("read-only)")) => {
+               |_| MdToken::ReadOnly
+           } |
+           md_device => { MdToken::Device } |
+           type_string => { MdToken::Personality }));
+
+named!(md_header<(String, String, Vec<MdToken>)>,
+       do_parse!(
+           name: type_string >> space >> char!(':') >> space >>
+           state: map_res!(alpha, str::from_utf8) >>
+           tokens: many0!(preceded!(space, md_token)) >>
+           line_ending >>
+           ((name, state.to_owned(), tokens))));
+
+// The [raid_disks/in_sync_disks] [UU_] suffix of the status line.
+named!(md_disks<(u64, u64)>,
+       do_parse!(
+           char!('[') >> raid: type_u64 >> char!('/') >> in_sync: type_u64 >> char!(']') >>
+           (raid, in_sync)));
+
+named!(md_status<(u64, &[u8])>,
+       do_parse!(
+           space >> blocks: type_u64 >> tag!(" blocks") >>
+           rest: not_line_ending >> line_ending >>
+           ((blocks, rest))));
+
+named!(md_progress<MdProgress>,
+       do_parse!(
+           opt!(terminated!(delimited!(char!('['), is_not!("]"), char!(']')), space)) >>
+           action: map_res!(alpha, str::from_utf8) >>
+           opt!(space) >> char!('=') >> opt!(space) >>
+           percent: type_f64 >> char!('%') >> space >>
+           char!('(') >> done: type_u64 >> char!('/') >> total: type_u64 >> char!(')') >>
+           space >> tag!("finish=") >> finish: type_f64 >> tag!("min") >>
+           space >> tag!("speed=") >> speed: type_u64 >> tag!("K/sec") >>
+           opt!(space) >>
+           (MdProgress {
+               action: action.to_owned(),
+               percent: percent,
+               done: done,
+               total: total,
+               finish: finish,
+               speed: speed,
+           })));
+
+named!(md_detail<MdDetail>,
+       do_parse!(
+           space >>
+           detail: alt!(
+               md_progress => { MdDetail::Progress } |
+               is_not!("\r\n") => { |_| MdDetail::Other }) >>
+           line_ending >>
+           (detail)));
+
+named!(md_array<MdArray>,
+       do_parse!(
+           header: md_header >>
+           status: opt!(md_status) >>
+           details: many0!(md_detail) >>
+           opt!(space) >> line_ending >>
+           (build_array(header, status, details))));
+
+named!(pub parse_mdstat<Vec<MdArray> >,
+       do_parse!(
+           tag!("Personalities :") >> not_line_ending >> line_ending >>
+           arrays: many0!(md_array) >>
+           opt!(do_parse!(tag!("unused devices:") >> not_line_ending >> line_ending >> ())) >>
+           (arrays)));
+
+/// Find the [raid_disks/in_sync_disks] pair in the tail of a status line.
+fn find_disks(rest: &[u8]) -> Option<(u64, u64)> {
+    (0..rest.len())
+        .filter(|i| rest[*i] == b'[')
+        .filter_map(|i| md_disks(&rest[i..]).to_full_result().ok())
+        .next()
+}
+
+fn build_array(
+    header: (String, String, Vec<MdToken>),
+    status: Option<(u64, &[u8])>,
+    details: Vec<MdDetail>,
+) -> MdArray {
+    let (name, state, tokens) = header;
+
+    let mut array = MdArray {
+        name: name,
+        state: state,
+        ..MdArray::default()
+    };
+
+    for token in tokens {
+        match token {
+            MdToken::ReadOnly => array.read_only = true,
+            MdToken::Personality(personality) => array.personality = Some(personality),
+            MdToken::Device(device) => array.devices.push(device),
+        }
+    }
+
+    if let Some((blocks, rest)) = status {
+        array.blocks = blocks;
+
+        if let Some((raid, in_sync)) = find_disks(rest) {
+            array.raid_disks = Some(raid);
+            array.in_sync_disks = Some(in_sync);
+        }
+    }
+
+    for detail in details {
+        if let MdDetail::Progress(progress) = detail {
+            array.progress = Some(progress);
+        }
+    }
+
+    array
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_mdstat_resync() {
+        let text = b"Personalities : [raid1] \n\
+                     md0 : active raid1 sdb1[1] sda1[0]\n      \
+                     976630464 blocks super 1.2 [2/2] [UU]\n      \
+                     [==>..................]  resync = 12.6% (123456789/976630464) \
+                     finish=83.1min speed=171012K/sec\n      \
+                     bitmap: 8/8 pages [32KB], 65536KB chunk\n\
+                     \n\
+                     unused devices: <none>\n";
+
+        let arrays = parse_mdstat(text).to_full_result().unwrap();
+        assert_eq!(1, arrays.len());
+
+        let md0 = &arrays[0];
+        assert_eq!("md0", md0.name);
+        assert_eq!("active", md0.state);
+        assert_eq!(Some("raid1".to_owned()), md0.personality);
+        assert_eq!(2, md0.devices.len());
+        assert_eq!(976630464, md0.blocks);
+        assert!(!md0.degraded());
+
+        let progress = md0.progress.as_ref().unwrap();
+        assert_eq!("resync", progress.action);
+        assert_eq!(12.6, progress.percent);
+        assert_eq!(171012, progress.speed);
+    }
+
+    #[test]
+    fn test_parse_mdstat_recovery() {
+        let text = b"Personalities : [raid1] [raid6] [raid5] [raid4] \n\
+                     md1 : active raid1 sdb2[1] sda2[0]\n      \
+                     136448 blocks [2/2] [UU]\n      \n\
+                     md2 : active raid1 sdc3[2] sda3[0]\n      \
+                     129596288 blocks [2/1] [U_]\n      \
+                     [===>.................]  recovery = 19.0% (24700032/129596288) \
+                     finish=22.5min speed=77526K/sec\n      \n\
+                     unused devices: <none>\n";
+
+        let arrays = parse_mdstat(text).to_full_result().unwrap();
+        assert_eq!(2, arrays.len());
+        assert!(arrays[0].progress.is_none());
+
+        let md2 = &arrays[1];
+        assert_eq!("md2", md2.name);
+        assert_eq!(Some(2), md2.raid_disks);
+        assert_eq!(Some(1), md2.in_sync_disks);
+        assert!(md2.degraded());
+
+        let progress = md2.progress.as_ref().unwrap();
+        assert_eq!("recovery", progress.action);
+        assert_eq!(24700032, progress.done);
+        assert_eq!(129596288, progress.total);
+        assert_eq!(22.5, progress.finish);
+    }
+
+    #[test]
+    fn test_parse_mdstat_degraded() {
+        let text = b"Personalities : [raid6] [raid5] [raid4] [raid0] \n\
+                     md0 : active (auto-read-only) raid5 sdd1[3](S) sdc1[2](F) sdb1[1] sda1[0]\n      \
+                     1953519872 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [UU_]\n      \
+                     bitmap: 1/8 pages [4KB], 65536KB chunk\n\
+                     \n\
+                     md1 : active raid0 sdf1[1] sde1[0]\n      \
+                     1953257472 blocks super 1.2 512k chunks\n\
+                     \n\
+                     md127 : inactive sdg1[0](S)\n      \
+                     976630488 blocks super 1.2\n\
+                     \n\
+                     unused devices: <none>\n";
+
+        let arrays = parse_mdstat(text).to_full_result().unwrap();
+        assert_eq!(3, arrays.len());
+
+        let md0 = &arrays[0];
+        assert!(md0.read_only);
+        assert!(md0.degraded());
+        assert_eq!(Some("raid5".to_owned()), md0.personality);
+        assert_eq!(1, md0.devices.iter().filter(|d| d.faulty()).count());
+        assert_eq!(1, md0.devices.iter().filter(|d| d.spare()).count());
+
+        let md1 = &arrays[1];
+        assert_eq!(None, md1.raid_disks);
+        assert!(!md1.degraded());
+
+        let md127 = &arrays[2];
+        assert_eq!("inactive", md127.state);
+        assert_eq!(None, md127.personality);
+        assert!(md127.devices[0].spare());
+    }
+}
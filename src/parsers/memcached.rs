@@ -0,0 +1,48 @@
+//! Parser for the response to the memcached `stats` command.
+//!
+//! ```text
+//! STAT pid 1234
+//! STAT curr_connections 10
+//! END
+//! ```
+
+/// Parse `STAT <name> <value>` lines into name and value pairs, up to the terminating `END`.
+///
+/// Returns `None` if the terminating `END` has not been seen yet.
+pub fn parse_stats(input: &str) -> Option<Vec<(String, String)>> {
+    let mut stats = Vec::new();
+
+    for line in input.lines() {
+        let line = line.trim();
+
+        if line == "END" {
+            return Some(stats);
+        }
+
+        let mut parts = line.splitn(3, ' ');
+
+        if let (Some("STAT"), Some(name), Some(value)) = (parts.next(), parts.next(), parts.next()) {
+            stats.push((name.to_owned(), value.to_owned()));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        assert_eq!(None, parse_stats("STAT pid 1234\r\n"));
+
+        assert_eq!(
+            Some(vec![
+                ("pid".to_owned(), "1234".to_owned()),
+                ("version".to_owned(), "1.5.2".to_owned()),
+            ]),
+            parse_stats("STAT pid 1234\r\nSTAT version 1.5.2\r\nEND\r\n")
+        );
+    }
+}
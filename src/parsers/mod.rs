@@ -1,3 +1,27 @@
 pub mod stat;
 pub mod mounts;
 pub mod common;
+pub mod file_nr;
+pub mod inode_nr;
+pub mod pid_max;
+pub mod loadavg;
+pub mod swaps;
+pub mod mdstat;
+pub mod rpc_stats;
+pub mod mountstats;
+pub mod interrupts;
+pub mod numa;
+pub mod nf_conntrack;
+pub mod uptime;
+pub mod utmp;
+pub mod os_release;
+pub mod graphite;
+pub mod influx;
+pub mod nagios;
+pub mod statsd;
+pub mod prometheus;
+pub mod redis;
+pub mod memcached;
+pub mod nginx;
+pub mod apache;
+pub mod haproxy;
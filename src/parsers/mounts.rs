@@ -2,7 +2,7 @@ use std::u8;
 use std::str::{self, FromStr};
 use nom::digit;
 
-fn decode_path(input: &str) -> Result<String, ()> {
+pub fn decode_path(input: &str) -> Result<String, ()> {
     let mut it = input.chars();
     let mut out = String::new();
 
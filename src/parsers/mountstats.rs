@@ -0,0 +1,152 @@
+use nom::{line_ending, not_line_ending, space};
+use std::str;
+
+use parsers::common::*;
+use parsers::mounts::decode_path;
+
+/// Statistics for a single NFS operation on a mount.
+#[derive(Debug, Default, PartialEq)]
+pub struct MountOp {
+    pub name: String,
+    pub ops: u64,
+    pub transmissions: u64,
+    pub major_timeouts: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// cumulative time spent queued for transmission, in milliseconds
+    pub queue: u64,
+    /// cumulative round trip time, in milliseconds
+    pub rtt: u64,
+    /// cumulative time from submission until completion, in milliseconds
+    pub execute: u64,
+}
+
+/// A single mount in /proc/self/mountstats
+#[derive(Debug, Default, PartialEq)]
+pub struct MountStats {
+    pub device: String,
+    pub mountpoint: String,
+    pub fstype: String,
+    /// per-operation statistics, only available for NFS mounts
+    pub ops: Vec<MountOp>,
+}
+
+enum MountDetail {
+    PerOp,
+    Values(String, Vec<u64>),
+    Other,
+}
+
+named!(path<String>,
+       map_res!(map_res!(is_not!(" \t\r\n"), str::from_utf8), decode_path));
+
+named!(mount_header<(String, String, String)>,
+       do_parse!(
+           tag!("device ") >> device: path >>
+           tag!(" mounted on ") >> mountpoint: path >>
+           tag!(" with fstype ") >> fstype: type_string >>
+           not_line_ending >> line_ending >>
+           ((device, mountpoint, fstype))));
+
+named!(mount_values<MountDetail>,
+       do_parse!(
+           name: map_res!(is_not!(": \t\r\n"), str::from_utf8) >> char!(':') >>
+           values: many1!(preceded!(space, type_u64)) >>
+           opt!(space) >>
+           (MountDetail::Values(name.to_owned(), values))));
+
+named!(mount_detail<MountDetail>,
+       do_parse!(
+           space >>
+           detail: alt!(
+               tag!("per-op statistics") => { |_| MountDetail::PerOp } |
+               mount_values |
+               is_not!("\r\n") => { |_| MountDetail::Other }) >>
+           line_ending >>
+           (detail)));
+
+named!(mount_stats<MountStats>,
+       do_parse!(
+           header: mount_header >>
+           details: many0!(mount_detail) >>
+           many0!(line_ending) >>
+           (build_mount(header, details))));
+
+named!(pub parse_mountstats<Vec<MountStats> >, many0!(mount_stats));
+
+fn build_mount(header: (String, String, String), details: Vec<MountDetail>) -> MountStats {
+    let (device, mountpoint, fstype) = header;
+
+    let mut ops = Vec::new();
+    let mut per_op = false;
+
+    for detail in details {
+        match detail {
+            MountDetail::PerOp => per_op = true,
+            MountDetail::Values(ref name, ref v) if per_op && v.len() >= 8 => {
+                ops.push(MountOp {
+                    name: name.clone(),
+                    ops: v[0],
+                    transmissions: v[1],
+                    major_timeouts: v[2],
+                    bytes_sent: v[3],
+                    bytes_received: v[4],
+                    queue: v[5],
+                    rtt: v[6],
+                    execute: v[7],
+                });
+            }
+            _ => {}
+        }
+    }
+
+    MountStats {
+        device: device,
+        mountpoint: mountpoint,
+        fstype: fstype,
+        ops: ops,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountstats() {
+        let text = b"device rootfs mounted on / with fstype rootfs\n\
+                     device proc mounted on /proc with fstype proc\n\
+                     device nas:/export/build mounted on /mnt/build\\040farm with fstype nfs4 statvers=1.1\n\
+                     \topts:\trw,vers=4.1,rsize=1048576,wsize=1048576,proto=tcp\n\
+                     \tage:\t4242\n\
+                     \tevents:\t1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27\n\
+                     \tbytes:\t100 200 0 0 300 400 5 6\n\
+                     \tRPC iostats version: 1.0  p/v: 100003/4 (nfs)\n\
+                     \txprt:\ttcp 0 1 2 0 0 3000 3000 0 3500 0 2 100 50\n\
+                     \tper-op statistics\n\
+                     \t        NULL: 0 0 0 0 0 0 0 0\n\
+                     \t        READ: 1000 1002 0 160000 1048000000 40 3000 3200\n\
+                     \t       WRITE: 500 500 0 524288000 72000 10 2500 2600 0\n\
+                     \n\
+                     device tmpfs mounted on /run with fstype tmpfs\n";
+
+        let mounts = parse_mountstats(text).to_full_result().unwrap();
+        assert_eq!(4, mounts.len());
+        assert_eq!(0, mounts[0].ops.len());
+
+        let nfs = &mounts[2];
+        assert_eq!("nas:/export/build", nfs.device);
+        assert_eq!("/mnt/build farm", nfs.mountpoint);
+        assert_eq!("nfs4", nfs.fstype);
+        assert_eq!(3, nfs.ops.len());
+
+        let read = &nfs.ops[1];
+        assert_eq!("READ", read.name);
+        assert_eq!(1000, read.ops);
+        assert_eq!(1002, read.transmissions);
+        assert_eq!(3000, read.rtt);
+        assert_eq!(3200, read.execute);
+
+        assert_eq!("tmpfs", mounts[3].fstype);
+    }
+}
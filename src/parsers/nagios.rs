@@ -0,0 +1,131 @@
+//! Parser for the output of Nagios plugins.
+//!
+//! `<text> | <label>=<value>[<uom>];[warn];[crit];[min];[max] ...`
+//!
+//! Performance data can follow the first line, and the first `|` of any subsequent line.
+
+/// A single performance data entry.
+#[derive(Debug, Default, PartialEq)]
+pub struct PerfData {
+    pub label: String,
+    pub value: f64,
+    /// unit of measurement, like s, %, B or c
+    pub unit: Option<String>,
+}
+
+/// Split a performance data section into entries, respecting quoted labels.
+fn split_perfdata(input: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    out.push(current);
+                    current = String::new();
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        out.push(current);
+    }
+
+    out
+}
+
+/// Parse a single entry, entries with unknown (U) or malformed values are skipped.
+fn parse_perfdata_entry(entry: &str) -> Option<PerfData> {
+    let eq = entry.rfind('=')?;
+    let label = entry[..eq].trim_matches('\'').replace("''", "'");
+
+    let value = entry[eq + 1..].split(';').next().unwrap_or("");
+
+    let split = value
+        .find(|c: char| !(c.is_digit(10) || c == '.' || c == '-' || c == '+' || c == 'e'))
+        .unwrap_or(value.len());
+
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+
+    Some(PerfData {
+        label: label,
+        value: number,
+        unit: match unit {
+            "" => None,
+            unit => Some(unit.to_owned()),
+        },
+    })
+}
+
+/// Parse all performance data in the output of a plugin.
+pub fn parse_nagios_perfdata(output: &str) -> Vec<PerfData> {
+    let mut sections = Vec::new();
+    let mut lines = output.lines();
+
+    if let Some(first) = lines.next() {
+        if let Some(pipe) = first.find('|') {
+            sections.push(&first[pipe + 1..]);
+        }
+    }
+
+    let mut in_perfdata = false;
+
+    for line in lines {
+        if in_perfdata {
+            sections.push(line);
+            continue;
+        }
+
+        if let Some(pipe) = line.find('|') {
+            sections.push(&line[pipe + 1..]);
+            in_perfdata = true;
+        }
+    }
+
+    sections
+        .into_iter()
+        .flat_map(split_perfdata)
+        .filter_map(|entry| parse_perfdata_entry(&entry))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_nagios_perfdata() {
+        let output = "DISK OK - free space: / 3326 MB (56%); | /=2643MB;5948;5958;0;5968 \
+                      'data dir'=80%;90;95\n\
+                      / 15272 MB (77%);\n\
+                      /boot 68 MB (69%); | /boot=68MB;88;93;0;98\n\
+                      /home=69357MB;253404;253464;0;253484 time=U\n";
+
+        let perfdata = parse_nagios_perfdata(output);
+        assert_eq!(4, perfdata.len());
+
+        assert_eq!("/", perfdata[0].label);
+        assert_eq!(2643f64, perfdata[0].value);
+        assert_eq!(Some("MB".to_owned()), perfdata[0].unit);
+
+        assert_eq!("data dir", perfdata[1].label);
+        assert_eq!(Some("%".to_owned()), perfdata[1].unit);
+
+        assert_eq!("/boot", perfdata[2].label);
+        assert_eq!("/home", perfdata[3].label);
+    }
+
+    #[test]
+    fn test_parse_nagios_no_perfdata() {
+        assert!(parse_nagios_perfdata("PING OK - Packet loss = 0%\n").is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// Contents of /proc/net/stat/nf_conntrack
+///
+/// A header with the name of every field, followed by one line of hexadecimal values per cpu.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConntrackStat {
+    pub fields: Vec<String>,
+    pub cpus: Vec<Vec<u64>>,
+}
+
+impl ConntrackStat {
+    /// Sum of the given field over all cpus.
+    pub fn total(&self, field: &str) -> Option<u64> {
+        self.fields.iter().position(|f| f == field).map(|index| {
+            self.cpus.iter().filter_map(|values| values.get(index)).fold(
+                0,
+                |a, b| a + b,
+            )
+        })
+    }
+}
+
+named!(conntrack_header<Vec<String> >,
+       do_parse!(
+           fields: many1!(terminated!(type_string, opt!(space))) >> line_ending >>
+           (fields)));
+
+named!(conntrack_cpu<Vec<u64> >,
+       do_parse!(
+           values: many1!(terminated!(type_hex_u64, opt!(space))) >> line_ending >>
+           (values)));
+
+named!(pub parse_nf_conntrack<ConntrackStat>,
+       do_parse!(
+           fields: conntrack_header >>
+           cpus: many0!(conntrack_cpu) >>
+           (ConntrackStat {
+               fields: fields,
+               cpus: cpus,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_nf_conntrack() {
+        let text = b"entries  searched found new invalid ignore delete delete_list insert \
+                     insert_failed drop early_drop icmp_error  expect_new expect_create \
+                     expect_delete search_restart\n\
+                     0000002a  00000000 00000000 00000000 00000bb4 0000113c 00000000 00000000 \
+                     00000000 00000001 00000002 00000000 00000000  00000000 00000000 00000000 \
+                     000005c6\n\
+                     0000002a  00000000 00000000 00000000 00000010 00000f00 00000000 00000000 \
+                     00000000 00000003 00000000 00000004 00000000  00000000 00000000 00000000 \
+                     00000010\n";
+
+        let stat = parse_nf_conntrack(text).to_full_result().unwrap();
+        assert_eq!(17, stat.fields.len());
+        assert_eq!(2, stat.cpus.len());
+        assert_eq!(Some(0xbb4 + 0x10), stat.total("invalid"));
+        assert_eq!(Some(4), stat.total("insert_failed"));
+        assert_eq!(Some(2), stat.total("drop"));
+        assert_eq!(Some(4), stat.total("early_drop"));
+        assert_eq!(None, stat.total("missing"));
+    }
+}
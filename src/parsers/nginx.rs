@@ -0,0 +1,90 @@
+//! Parser for the nginx `stub_status` page.
+//!
+//! ```text
+//! Active connections: 291
+//! server accepts handled requests
+//!  16630948 16630948 31070465
+//! Reading: 6 Writing: 179 Waiting: 106
+//! ```
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StubStatus {
+    pub active: u64,
+    pub accepts: u64,
+    pub handled: u64,
+    pub requests: u64,
+    pub reading: u64,
+    pub writing: u64,
+    pub waiting: u64,
+}
+
+fn number(input: Option<&str>, what: &str) -> Result<u64, String> {
+    match input {
+        Some(value) => value.parse::<u64>().map_err(|_| format!("bad {}: {}", what, value)),
+        None => Err(format!("missing {}", what)),
+    }
+}
+
+pub fn parse_stub_status(input: &str) -> Result<StubStatus, String> {
+    let mut status = StubStatus::default();
+    let mut lines = input.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    match lines.next() {
+        Some(line) if line.starts_with("Active connections:") => {
+            status.active = number(line.split(':').nth(1).map(str::trim), "active connections")?;
+        }
+        _ => return Err("missing active connections".to_owned()),
+    }
+
+    if lines.next() != Some("server accepts handled requests") {
+        return Err("missing server counters".to_owned());
+    }
+
+    {
+        let mut counters = lines.next().unwrap_or("").split_whitespace();
+        status.accepts = number(counters.next(), "accepts")?;
+        status.handled = number(counters.next(), "handled")?;
+        status.requests = number(counters.next(), "requests")?;
+    }
+
+    let mut states = lines.next().unwrap_or("").split_whitespace();
+
+    while let (Some(name), value) = (states.next(), states.next()) {
+        match name {
+            "Reading:" => status.reading = number(value, "reading")?,
+            "Writing:" => status.writing = number(value, "writing")?,
+            "Waiting:" => status.waiting = number(value, "waiting")?,
+            _ => {}
+        }
+    }
+
+    Ok(status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STUB_STATUS: &'static str = "Active connections: 291 \n\
+        server accepts handled requests\n \
+        16630948 16630948 31070465 \n\
+        Reading: 6 Writing: 179 Waiting: 106 \n";
+
+    #[test]
+    fn test_parse_stub_status() {
+        assert_eq!(
+            Ok(StubStatus {
+                active: 291,
+                accepts: 16630948,
+                handled: 16630948,
+                requests: 31070465,
+                reading: 6,
+                writing: 179,
+                waiting: 106,
+            }),
+            parse_stub_status(STUB_STATUS)
+        );
+
+        assert!(parse_stub_status("<html>not found</html>").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use nom::{line_ending, space};
+use std::str;
+
+use parsers::common::*;
+
+/// A single field in /sys/devices/system/node/node*/meminfo
+#[derive(Debug, Default, PartialEq)]
+pub struct NodeMemInfo {
+    pub node: u64,
+    pub field: String,
+    /// value in bytes, or a plain count for fields without unit (like HugePages_Total)
+    pub value: u64,
+    pub bytes: bool,
+}
+
+named!(node_meminfo_line<NodeMemInfo>,
+       do_parse!(
+           tag!("Node") >> space >> node: type_u64 >> space >>
+           field: map_res!(is_not!(": \t\r\n"), str::from_utf8) >> char!(':') >> space >>
+           value: type_u64 >>
+           unit: opt!(preceded!(space, tag!("kB"))) >>
+           opt!(space) >> line_ending >>
+           (NodeMemInfo {
+               node: node,
+               field: field.to_owned(),
+               value: match unit {
+                   Some(_) => value * 1024,
+                   None => value,
+               },
+               bytes: unit.is_some(),
+           })));
+
+named!(pub parse_node_meminfo<Vec<NodeMemInfo> >, many0!(node_meminfo_line));
+
+named!(numastat_line<(String, u64)>,
+       do_parse!(
+           field: type_string >> space >> value: type_u64 >> line_ending >>
+           ((field, value))));
+
+// Contents of /sys/devices/system/node/node*/numastat
+named!(pub parse_numastat<Vec<(String, u64)> >, many0!(numastat_line));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_node_meminfo() {
+        let text = b"Node 1 MemTotal:       16305164 kB\n\
+                     Node 1 MemFree:         1234567 kB\n\
+                     Node 1 HugePages_Total:     16\n";
+
+        let meminfo = parse_node_meminfo(text).to_full_result().unwrap();
+        assert_eq!(3, meminfo.len());
+        assert_eq!(1, meminfo[0].node);
+        assert_eq!("MemTotal", meminfo[0].field);
+        assert_eq!(16305164 * 1024, meminfo[0].value);
+        assert_eq!(16, meminfo[2].value);
+        assert!(!meminfo[2].bytes);
+    }
+
+    #[test]
+    fn test_parse_numastat() {
+        let text = b"numa_hit 123456\n\
+                     numa_miss 12\n\
+                     numa_foreign 0\n\
+                     interleave_hit 4096\n\
+                     local_node 123400\n\
+                     other_node 68\n";
+
+        let numastat = parse_numastat(text).to_full_result().unwrap();
+        assert_eq!(6, numastat.len());
+        assert_eq!(("numa_miss".to_owned(), 12), numastat[1]);
+    }
+}
@@ -0,0 +1,86 @@
+use nom::{line_ending, not_line_ending};
+use std::str;
+
+/// A single line in /etc/os-release
+enum OsReleaseLine {
+    Value(String, String),
+    Other,
+}
+
+/// Remove surrounding quotes and shell escapes from a value.
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    let inner = match (value.chars().next(), value.chars().last()) {
+        (Some('"'), Some('"')) |
+        (Some('\''), Some('\'')) if value.len() >= 2 => &value[1..value.len() - 1],
+        _ => value,
+    };
+
+    let mut out = String::new();
+    let mut it = inner.chars();
+
+    while let Some(c) = it.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = it.next() {
+                    out.push(c);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+named!(os_release_value<OsReleaseLine>,
+       do_parse!(
+           key: map_res!(is_a!("ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_"), str::from_utf8) >>
+           char!('=') >>
+           value: map_res!(not_line_ending, str::from_utf8) >>
+           (OsReleaseLine::Value(key.to_owned(), unquote(value)))));
+
+named!(os_release_line<OsReleaseLine>,
+       do_parse!(
+           line: alt!(
+               os_release_value |
+               not_line_ending => { |_| OsReleaseLine::Other }) >>
+           line_ending >>
+           (line)));
+
+// Contents of /etc/os-release, as a list of key-value pairs.
+named!(pub parse_os_release<Vec<(String, String)> >,
+       map!(many0!(os_release_line), |lines: Vec<OsReleaseLine>| {
+           lines.into_iter().filter_map(|line| match line {
+               OsReleaseLine::Value(key, value) => Some((key, value)),
+               OsReleaseLine::Other => None,
+           }).collect()
+       }));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_os_release() {
+        let text = b"NAME=\"Debian GNU/Linux\"\n\
+                     # a comment\n\
+                     \n\
+                     VERSION_ID=\"9\"\n\
+                     ID=debian\n\
+                     PRETTY_NAME='Debian GNU/Linux 9 \\'stretch\\''\n";
+
+        let values = parse_os_release(text).to_full_result().unwrap();
+
+        assert_eq!(
+            vec![
+                ("NAME".to_owned(), "Debian GNU/Linux".to_owned()),
+                ("VERSION_ID".to_owned(), "9".to_owned()),
+                ("ID".to_owned(), "debian".to_owned()),
+                ("PRETTY_NAME".to_owned(), "Debian GNU/Linux 9 'stretch'".to_owned()),
+            ],
+            values
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use parsers::common::*;
+
+named!(pub parse_pid_max<u64>, call!(single_u64));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pid_max() {
+        assert_eq!(32768, parse_pid_max(b"32768\n").to_full_result().unwrap());
+    }
+}
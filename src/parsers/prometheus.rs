@@ -0,0 +1,299 @@
+//! Parser for the Prometheus text exposition format.
+//!
+//! Label values have their own escaping rules, which is why this is written by hand.
+
+use std::collections::HashMap;
+use std::f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Untyped,
+}
+
+impl MetricType {
+    fn parse(input: &str) -> MetricType {
+        match input {
+            "counter" => MetricType::Counter,
+            "gauge" => MetricType::Gauge,
+            "histogram" => MetricType::Histogram,
+            "summary" => MetricType::Summary,
+            _ => MetricType::Untyped,
+        }
+    }
+}
+
+/// A single series in an exposition.
+#[derive(Debug, PartialEq)]
+pub struct PromSample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    /// milliseconds since the epoch
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Exposition {
+    pub help: HashMap<String, String>,
+    pub types: HashMap<String, MetricType>,
+    pub samples: Vec<PromSample>,
+}
+
+impl Exposition {
+    /// Get the type of the family the given series name belongs to.
+    ///
+    /// Histograms and summaries are exposed as several series with suffixes, like `_bucket`.
+    pub fn metric_type(&self, name: &str) -> MetricType {
+        if let Some(t) = self.types.get(name) {
+            return *t;
+        }
+
+        for suffix in &["_bucket", "_sum", "_count"] {
+            if name.ends_with(suffix) {
+                let family = &name[..name.len() - suffix.len()];
+
+                match self.types.get(family) {
+                    Some(&MetricType::Histogram) |
+                    Some(&MetricType::Summary) => return self.types[family],
+                    _ => {}
+                }
+            }
+        }
+
+        MetricType::Untyped
+    }
+}
+
+fn parse_value(input: &str) -> Result<f64, String> {
+    match input {
+        "+Inf" | "Inf" => Ok(f64::INFINITY),
+        "-Inf" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
+        value => value.parse::<f64>().map_err(|e| format!("bad value: {}: {}", value, e)),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == ':'
+}
+
+/// Parse the labels of a series, with the input starting after the opening brace.
+///
+/// Returns the labels and the input following the closing brace.
+fn parse_labels(mut input: &str) -> Result<(Vec<(String, String)>, &str), String> {
+    let mut labels = Vec::new();
+
+    loop {
+        input = input.trim_left();
+
+        if input.starts_with('}') {
+            return Ok((labels, &input[1..]));
+        }
+
+        let end = input.find(|c| !is_name_char(c)).unwrap_or(input.len());
+        let name = &input[..end];
+
+        if name.is_empty() {
+            return Err("expected label name".to_owned());
+        }
+
+        input = input[end..].trim_left();
+
+        if !input.starts_with("=\"") {
+            return Err(format!("expected value for label: {}", name));
+        }
+
+        let mut value = String::new();
+        let mut it = input.char_indices().skip(2);
+        let mut rest = None;
+
+        while let Some((i, c)) = it.next() {
+            match c {
+                '\\' => {
+                    match it.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, c)) => value.push(c),
+                        None => break,
+                    }
+                }
+                '"' => {
+                    rest = Some(&input[i + 1..]);
+                    break;
+                }
+                c => value.push(c),
+            }
+        }
+
+        input = match rest {
+            Some(rest) => rest.trim_left(),
+            None => return Err(format!("unterminated value for label: {}", name)),
+        };
+
+        labels.push((name.to_owned(), value));
+
+        if input.starts_with(',') {
+            input = &input[1..];
+        }
+    }
+}
+
+fn parse_sample(line: &str) -> Result<PromSample, String> {
+    let end = line.find(|c| !is_name_char(c)).unwrap_or(line.len());
+    let name = &line[..end];
+
+    if name.is_empty() {
+        return Err("expected metric name".to_owned());
+    }
+
+    let mut rest = line[end..].trim_left();
+    let mut labels = Vec::new();
+
+    if rest.starts_with('{') {
+        let (l, r) = parse_labels(&rest[1..])?;
+        labels = l;
+        rest = r;
+    }
+
+    let mut parts = rest.split_whitespace();
+
+    let value = match parts.next() {
+        Some(value) => parse_value(value)?,
+        None => return Err(format!("missing value for: {}", name)),
+    };
+
+    let timestamp = match parts.next() {
+        Some(timestamp) => Some(timestamp.parse::<i64>().map_err(|e| {
+            format!("bad timestamp: {}: {}", timestamp, e)
+        })?),
+        None => None,
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("trailing data for: {}", name));
+    }
+
+    Ok(PromSample {
+        name: name.to_owned(),
+        labels: labels,
+        value: value,
+        timestamp: timestamp,
+    })
+}
+
+/// Parse a full exposition.
+///
+/// Fails on the first malformed line, since an exposition is typically produced as a whole.
+pub fn parse_exposition(input: &str) -> Result<Exposition, String> {
+    let mut exposition = Exposition::default();
+
+    for (n, line) in input.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('#') {
+            let mut parts = line[1..].trim_left().splitn(3, char::is_whitespace);
+
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("HELP"), Some(name), help) => {
+                    exposition.help.insert(
+                        name.to_owned(),
+                        help.unwrap_or("").to_owned(),
+                    );
+                }
+                (Some("TYPE"), Some(name), Some(t)) => {
+                    exposition.types.insert(name.to_owned(), MetricType::parse(t.trim()));
+                }
+                // other comments are ignored.
+                _ => {}
+            }
+
+            continue;
+        }
+
+        exposition.samples.push(parse_sample(line).map_err(|e| {
+            format!("line {}: {}", n + 1, e)
+        })?);
+    }
+
+    Ok(exposition)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXPOSITION: &'static str = r#"
+# HELP http_requests_total The total number of HTTP requests.
+# TYPE http_requests_total counter
+http_requests_total{method="post",code="200"} 1027 1395066363000
+http_requests_total{method="post",code="400"}    3 1395066363000
+
+# A comment
+metric_without_timestamp_and_labels 12.47
+msdos_file_access_time_seconds{path="C:\\DIR\\FILE.TXT",error="Cannot find file:\n\"FILE.TXT\""} 1.458255915e9
+
+# TYPE http_request_duration_seconds histogram
+http_request_duration_seconds_bucket{le="0.05"} 24054
+http_request_duration_seconds_bucket{le="+Inf"} 144320
+http_request_duration_seconds_sum 53423
+http_request_duration_seconds_count 144320
+
+# TYPE rpc_duration_seconds summary
+rpc_duration_seconds{quantile="0.5",} 4773
+rpc_duration_seconds_count 2693
+"#;
+
+    #[test]
+    fn test_parse_exposition() {
+        let e = parse_exposition(EXPOSITION).unwrap();
+
+        assert_eq!(10, e.samples.len());
+
+        assert_eq!(
+            Some(&"The total number of HTTP requests.".to_owned()),
+            e.help.get("http_requests_total")
+        );
+
+        let first = &e.samples[0];
+        assert_eq!("http_requests_total", first.name);
+        assert_eq!(
+            vec![
+                ("method".to_owned(), "post".to_owned()),
+                ("code".to_owned(), "200".to_owned()),
+            ],
+            first.labels
+        );
+        assert_eq!(1027f64, first.value);
+        assert_eq!(Some(1395066363000), first.timestamp);
+
+        let msdos = &e.samples[3];
+        assert_eq!(("path".to_owned(), "C:\\DIR\\FILE.TXT".to_owned()), msdos.labels[0]);
+        assert_eq!(
+            ("error".to_owned(), "Cannot find file:\n\"FILE.TXT\"".to_owned()),
+            msdos.labels[1]
+        );
+
+        let inf = &e.samples[5];
+        assert_eq!(("le".to_owned(), "+Inf".to_owned()), inf.labels[0]);
+
+        assert_eq!(MetricType::Counter, e.metric_type("http_requests_total"));
+        assert_eq!(MetricType::Histogram, e.metric_type("http_request_duration_seconds_bucket"));
+        assert_eq!(MetricType::Summary, e.metric_type("rpc_duration_seconds_count"));
+        assert_eq!(MetricType::Untyped, e.metric_type("metric_without_timestamp_and_labels"));
+    }
+
+    #[test]
+    fn test_parse_exposition_invalid() {
+        assert!(parse_exposition("foo{bar=\"baz\" 1\n").is_err());
+        assert!(parse_exposition("foo{bar=baz} 1\n").is_err());
+        assert!(parse_exposition("foo\n").is_err());
+        assert!(parse_exposition("foo bar\n").is_err());
+    }
+}
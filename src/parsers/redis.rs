@@ -0,0 +1,95 @@
+//! Parser for the output of the Redis INFO command.
+//!
+//! ```text
+//! # Server
+//! redis_version:4.0.1
+//! # Keyspace
+//! db0:keys=10,expires=2,avg_ttl=3600
+//! ```
+
+/// A single field of the INFO output.
+#[derive(Debug, PartialEq)]
+pub struct InfoField {
+    /// lowercase section the field belongs to, like `memory`
+    pub section: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// Parse the fields of INFO output, lines which are not `name:value` are skipped.
+pub fn parse_info(input: &str) -> Vec<InfoField> {
+    let mut section = String::new();
+    let mut fields = Vec::new();
+
+    for line in input.lines() {
+        let line = line.trim();
+
+        if line.starts_with('#') {
+            section = line[1..].trim().to_lowercase();
+            continue;
+        }
+
+        if let Some(colon) = line.find(':') {
+            fields.push(InfoField {
+                section: section.clone(),
+                name: line[..colon].to_owned(),
+                value: line[colon + 1..].to_owned(),
+            });
+        }
+    }
+
+    fields
+}
+
+/// Parse the value of a keyspace field, like `keys=10,expires=2,avg_ttl=3600`.
+pub fn parse_keyspace(value: &str) -> Vec<(String, u64)> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+
+            match (parts.next(), parts.next().and_then(|v| v.parse::<u64>().ok())) {
+                (Some(name), Some(value)) => Some((name.to_owned(), value)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let fields = parse_info("# Server\r\nredis_version:4.0.1\r\n\r\n# Keyspace\r\ndb0:keys=1\r\n");
+
+        assert_eq!(
+            vec![
+                InfoField {
+                    section: "server".to_owned(),
+                    name: "redis_version".to_owned(),
+                    value: "4.0.1".to_owned(),
+                },
+                InfoField {
+                    section: "keyspace".to_owned(),
+                    name: "db0".to_owned(),
+                    value: "keys=1".to_owned(),
+                },
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn test_parse_keyspace() {
+        assert_eq!(
+            vec![
+                ("keys".to_owned(), 10),
+                ("expires".to_owned(), 2),
+                ("avg_ttl".to_owned(), 3600),
+            ],
+            parse_keyspace("keys=10,expires=2,avg_ttl=3600")
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use nom::{line_ending, space};
+use std::collections::HashMap;
+
+use parsers::common::*;
+
+/// Contents of /proc/net/rpc/nfs or /proc/net/rpc/nfsd
+///
+/// Both consist of lines with a label, followed by a list of values.
+#[derive(Debug, Default, PartialEq)]
+pub struct RpcStats {
+    pub lines: HashMap<String, Vec<f64>>,
+}
+
+impl RpcStats {
+    pub fn get(&self, label: &str) -> Option<&[f64]> {
+        self.lines.get(label).map(|v| v.as_slice())
+    }
+
+    /// Per-procedure counters for every protocol version present, like `proc3` and `proc4`.
+    ///
+    /// The first value on every proc line is the number of procedures that follow, and is skipped.
+    pub fn procedures(&self) -> Vec<(u64, &[f64])> {
+        let mut out: Vec<_> = self.lines
+            .iter()
+            .filter(|&(label, _)| label.starts_with("proc"))
+            .filter_map(|(label, values)| {
+                label["proc".len()..].parse::<u64>().ok().map(|version| {
+                    (version, values.get(1..).unwrap_or(&[]))
+                })
+            })
+            .collect();
+
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        out
+    }
+
+    /// Per-operation counters of NFSv4 compounds on the server (`proc4ops`).
+    pub fn operations(&self) -> Option<&[f64]> {
+        self.get("proc4ops").map(|values| values.get(1..).unwrap_or(&[]))
+    }
+}
+
+named!(rpc_line<(String, Vec<f64>)>,
+       do_parse!(
+           label: type_string >>
+           values: many0!(preceded!(space, type_f64)) >>
+           opt!(space) >> line_ending >>
+           ((label, values))));
+
+named!(pub parse_rpc_stats<RpcStats>,
+       map!(many0!(rpc_line), |lines: Vec<(String, Vec<f64>)>| {
+           RpcStats { lines: lines.into_iter().collect() }
+       }));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rpc_stats_client() {
+        let text = b"net 0 0 0 0\n\
+                     rpc 28213 5 0\n\
+                     proc3 22 0 1244 4 300 1100 0 200 10 4 0 0 0 5 0 1 0 0 36 20 2 2 0\n\
+                     proc4 3 0 10 20\n";
+
+        let stats = parse_rpc_stats(text).to_full_result().unwrap();
+        assert_eq!(Some(&[28213f64, 5f64, 0f64][..]), stats.get("rpc"));
+
+        let procedures = stats.procedures();
+        assert_eq!(2, procedures.len());
+        assert_eq!(3, procedures[0].0);
+        assert_eq!(22, procedures[0].1.len());
+        assert_eq!(1244f64, procedures[0].1[1]);
+        assert_eq!(&[0f64, 10f64, 20f64][..], procedures[1].1);
+    }
+
+    #[test]
+    fn test_parse_rpc_stats_server() {
+        let text = b"rc 0 4217 95016\n\
+                     fh 0 0 0 0 0\n\
+                     io 1063383040 217903104\n\
+                     th 8 0 0.000 0.000 0.000 0.000 0.000 0.000 0.000 0.000 0.000 0.000\n\
+                     net 99233 0 99233 11\n\
+                     rpc 99233 1 0 1 0\n\
+                     proc4 2 2 99231\n\
+                     proc4ops 4 0 0 0 1200\n";
+
+        let stats = parse_rpc_stats(text).to_full_result().unwrap();
+        assert_eq!(Some(&[99233f64, 1f64, 0f64, 1f64, 0f64][..]), stats.get("rpc"));
+        assert_eq!(Some(&[0f64, 0f64, 0f64, 1200f64][..]), stats.operations());
+    }
+}
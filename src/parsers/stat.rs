@@ -1,4 +1,4 @@
-use nom::{line_ending, space};
+use nom::{line_ending, not_line_ending, space};
 
 use parsers::common::*;
 
@@ -68,6 +68,18 @@ named!(pub parse_stat_cpu<StatCpu>,
                    guest: guest,
                    guest_nice: guest_nice } }));
 
+named!(stat_btime<Option<u64> >,
+       do_parse!(tag!("btime") >> space >> btime: type_u64 >> line_ending >> (Some(btime))));
+
+named!(stat_other<Option<u64> >,
+       do_parse!(not_line_ending >> line_ending >> (None)));
+
+// Find the boot time (btime), in seconds since the epoch.
+named!(pub parse_stat_btime<Option<u64> >,
+       map!(many0!(alt!(stat_btime | stat_other)), |lines: Vec<Option<u64>>| {
+           lines.into_iter().filter_map(|l| l).next()
+       }));
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -78,4 +90,16 @@ mod test {
         let cpu = parse_stat_cpu(cpu_text).to_full_result().unwrap();
         assert_eq!(347703, cpu.user);
     }
+
+    #[test]
+    fn test_parse_stat_btime() {
+        let text = b"cpu  347703 107 67084 8538266 10258 0 8753 0 0 0\n\
+                     intr 9140412 9 0 0 0\n\
+                     ctxt 16364447\n\
+                     btime 1493801542\n\
+                     processes 44917\n";
+
+        let btime = parse_stat_btime(text).to_full_result().unwrap();
+        assert_eq!(Some(1493801542), btime);
+    }
 }
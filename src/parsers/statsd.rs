@@ -0,0 +1,110 @@
+use nom::line_ending;
+use std::str;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsdKind {
+    Counter,
+    Gauge,
+    Timer,
+    Histogram,
+    Distribution,
+    Set,
+}
+
+/// A single StatsD line: `<name>:<value>|<kind>[|@<rate>][|#<tag>[:<value>],...]`
+#[derive(Debug, PartialEq)]
+pub struct StatsdLine {
+    pub name: String,
+    /// raw value, sets take arbitrary strings and gauges can be signed to indicate a delta.
+    pub value: String,
+    pub kind: StatsdKind,
+    pub sample_rate: Option<f64>,
+    /// DogStatsD tags, tags without a value have an empty value.
+    pub tags: Vec<(String, String)>,
+}
+
+fn split_tag(tag: &str) -> (String, String) {
+    match tag.find(':') {
+        Some(i) => (tag[..i].to_owned(), tag[i + 1..].to_owned()),
+        None => (tag.to_owned(), String::new()),
+    }
+}
+
+named!(statsd_kind<StatsdKind>,
+       alt!(
+           tag!("ms") => { |_| StatsdKind::Timer } |
+           tag!("c") => { |_| StatsdKind::Counter } |
+           tag!("g") => { |_| StatsdKind::Gauge } |
+           tag!("h") => { |_| StatsdKind::Histogram } |
+           tag!("d") => { |_| StatsdKind::Distribution } |
+           tag!("s") => { |_| StatsdKind::Set }
+       ));
+
+named!(statsd_rate<f64>,
+       map_res!(map_res!(preceded!(tag!("|@"), is_not!("|\r\n")), str::from_utf8),
+                str::parse::<f64>));
+
+named!(statsd_tags<Vec<(String, String)> >,
+       preceded!(tag!("|#"),
+                 separated_list!(tag!(","),
+                                 map!(map_res!(is_not!(",|\r\n"), str::from_utf8), split_tag))));
+
+// Parse a single line, including its line ending.
+named!(pub parse_statsd_line<StatsdLine>,
+       do_parse!(
+           name: map_res!(is_not!(":|\r\n"), str::from_utf8) >>
+           tag!(":") >>
+           value: map_res!(is_not!("|\r\n"), str::from_utf8) >>
+           tag!("|") >>
+           kind: statsd_kind >>
+           sample_rate: opt!(statsd_rate) >>
+           tags: opt!(statsd_tags) >>
+           line_ending >>
+           (StatsdLine {
+               name: name.to_owned(),
+               value: value.to_owned(),
+               kind: kind,
+               sample_rate: sample_rate,
+               tags: tags.unwrap_or_else(Vec::new),
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_statsd_line() {
+        let line = parse_statsd_line(b"requests:1|c\n").to_full_result().unwrap();
+        assert_eq!("requests", line.name);
+        assert_eq!("1", line.value);
+        assert_eq!(StatsdKind::Counter, line.kind);
+        assert_eq!(None, line.sample_rate);
+        assert!(line.tags.is_empty());
+
+        let line = parse_statsd_line(b"latency:12.5|ms|@0.1|#env:prod,canary\n")
+            .to_full_result()
+            .unwrap();
+        assert_eq!(StatsdKind::Timer, line.kind);
+        assert_eq!(Some(0.1), line.sample_rate);
+        assert_eq!(
+            vec![
+                ("env".to_owned(), "prod".to_owned()),
+                ("canary".to_owned(), "".to_owned()),
+            ],
+            line.tags
+        );
+
+        let line = parse_statsd_line(b"temp:-2|g|#room:kitchen\n").to_full_result().unwrap();
+        assert_eq!(StatsdKind::Gauge, line.kind);
+        assert_eq!("-2", line.value);
+        assert_eq!(None, line.sample_rate);
+    }
+
+    #[test]
+    fn test_parse_statsd_line_invalid() {
+        assert!(parse_statsd_line(b"requests|c\n").to_full_result().is_err());
+        assert!(parse_statsd_line(b"requests:1\n").to_full_result().is_err());
+        assert!(parse_statsd_line(b"requests:1|x\n").to_full_result().is_err());
+        assert!(parse_statsd_line(b"requests:1|c|@abc\n").to_full_result().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use nom::{line_ending, not_line_ending, space};
+
+use parsers::common::*;
+
+/// A single entry in /proc/swaps
+#[derive(Debug, Default, PartialEq)]
+pub struct Swap {
+    /// device or file backing the swap area
+    pub filename: String,
+    /// partition or file
+    pub kind: String,
+    /// size in kilobytes
+    pub size: u64,
+    /// used in kilobytes
+    pub used: u64,
+    pub priority: i64,
+}
+
+named!(swap_entry<Swap>,
+       do_parse!(
+           filename: type_string >> space >>
+           kind: type_string >> space >>
+           size: type_u64 >> space >>
+           used: type_u64 >> space >>
+           priority: type_i64 >> opt!(space) >> line_ending >>
+           (Swap {
+               filename: filename,
+               kind: kind,
+               size: size,
+               used: used,
+               priority: priority,
+           })));
+
+named!(pub parse_swaps<Vec<Swap> >,
+       do_parse!(
+           tag!("Filename") >> not_line_ending >> line_ending >>
+           swaps: many0!(swap_entry) >>
+           (swaps)));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_swaps() {
+        let text = b"Filename\t\t\t\tType\t\tSize\tUsed\tPriority\n\
+                     /dev/dm-1                               partition\t8388604\t1024\t-2\n\
+                     /swapfile                               file\t\t1048572\t0\t-3\n";
+
+        let swaps = parse_swaps(text).to_full_result().unwrap();
+        assert_eq!(2, swaps.len());
+        assert_eq!("/dev/dm-1", swaps[0].filename);
+        assert_eq!(1024, swaps[0].used);
+        assert_eq!("file", swaps[1].kind);
+        assert_eq!(-3, swaps[1].priority);
+    }
+
+    #[test]
+    fn test_parse_swaps_empty() {
+        let text = b"Filename\t\t\t\tType\t\tSize\tUsed\tPriority\n";
+        assert_eq!(0, parse_swaps(text).to_full_result().unwrap().len());
+    }
+}
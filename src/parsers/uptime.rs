@@ -0,0 +1,32 @@
+use nom::{line_ending, space};
+
+use parsers::common::*;
+
+/// Contents of /proc/uptime
+#[derive(Debug, Default, PartialEq)]
+pub struct Uptime {
+    /// seconds since boot
+    pub uptime: f64,
+    /// seconds spent idle, summed over all cpus
+    pub idle: f64,
+}
+
+named!(pub parse_uptime<Uptime>,
+       do_parse!(
+           uptime: type_f64 >> space >> idle: type_f64 >> line_ending >>
+           (Uptime {
+               uptime: uptime,
+               idle: idle,
+           })));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_uptime() {
+        let uptime = parse_uptime(b"350735.47 234388.90\n").to_full_result().unwrap();
+        assert_eq!(350735.47, uptime.uptime);
+        assert_eq!(234388.90, uptime.idle);
+    }
+}
@@ -0,0 +1,63 @@
+use nom::{le_i16, le_i32};
+
+/// Size of a single `struct utmp` record as written by glibc on Linux.
+pub const UTMP_SIZE: usize = 384;
+
+/// Record type of a normal user session.
+pub const USER_PROCESS: i16 = 7;
+
+/// The parts of a record in /var/run/utmp that are of interest.
+#[derive(Debug, Default, PartialEq)]
+pub struct Utmp {
+    pub kind: i16,
+    pub pid: i32,
+    pub user: String,
+}
+
+/// Decode a fixed size, NUL padded string.
+fn fixed_string(input: &[u8]) -> String {
+    let end = input.iter().position(|b| *b == 0).unwrap_or(input.len());
+    String::from_utf8_lossy(&input[..end]).into_owned()
+}
+
+named!(utmp_record<Utmp>,
+       do_parse!(
+           kind: le_i16 >> take!(2) >>
+           pid: le_i32 >>
+           take!(32) >> take!(4) >>
+           user: take!(32) >>
+           take!(UTMP_SIZE - 76) >>
+           (Utmp {
+               kind: kind,
+               pid: pid,
+               user: fixed_string(user),
+           })));
+
+named!(pub parse_utmp<Vec<Utmp> >, many0!(utmp_record));
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(kind: i16, pid: i32, user: &str) -> Vec<u8> {
+        let mut out = vec![0u8; UTMP_SIZE];
+        out[0] = kind as u8;
+        out[4] = pid as u8;
+        out[44..44 + user.len()].copy_from_slice(user.as_bytes());
+        out
+    }
+
+    #[test]
+    fn test_parse_utmp() {
+        let mut data = Vec::new();
+        data.extend(record(2, 0, "reboot"));
+        data.extend(record(USER_PROCESS, 42, "alice"));
+        data.extend(record(USER_PROCESS, 43, "bob"));
+
+        let records = parse_utmp(&data).to_full_result().unwrap();
+        assert_eq!(3, records.len());
+        assert_eq!(USER_PROCESS, records[1].kind);
+        assert_eq!(42, records[1].pid);
+        assert_eq!("bob", records[2].user);
+    }
+}
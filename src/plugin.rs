@@ -7,7 +7,7 @@ use std::fmt;
 use std::sync::Arc;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use toml;
 use serde;
 use tokio_core;
@@ -53,17 +53,36 @@ impl PluginRegistry {
 pub struct Sample {
     pub metric_id: Arc<MetricId>,
     pub value: f64,
+    /// Milliseconds since the Unix epoch when the value was read.
+    ///
+    /// Inputs which read their values on a thread of their own report the time of the read, so
+    /// that a value which is reported again until the next read keeps its timestamp. For other
+    /// inputs this is the time of the poll cycle which reported the sample.
+    pub timestamp: u64,
 }
 
 impl Sample {
+    /// A sample read now.
     pub fn new(metric_id: Arc<MetricId>, value: f64) -> Sample {
+        Sample::with_timestamp(metric_id, value, unix_millis(SystemTime::now()))
+    }
+
+    /// A sample read at the given time, in milliseconds since the Unix epoch.
+    pub fn with_timestamp(metric_id: Arc<MetricId>, value: f64, timestamp: u64) -> Sample {
         Sample {
             metric_id: metric_id,
             value: value,
+            timestamp: timestamp,
         }
     }
 }
 
+/// Milliseconds since the Unix epoch of the given time.
+pub fn unix_millis(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    since.as_secs() * 1000 + (since.subsec_nanos() / 1_000_000) as u64
+}
+
 pub type Samples = Vec<Sample>;
 
 pub trait InputInstance: fmt::Debug + Send + Sync {
@@ -85,6 +104,9 @@ pub trait InputInstance: fmt::Debug + Send + Sync {
     }
 
     /// Get the duration until the next update should be called.
+    ///
+    /// Rounded up to a multiple of the update interval, and an instance never gets updated more
+    /// often than once per update interval.
     fn next_update(&self) -> Duration {
         Duration::from_millis(0)
     }
@@ -92,6 +114,13 @@ pub trait InputInstance: fmt::Debug + Send + Sync {
 
 pub trait OutputInstance: Send + Sync {
     fn feed(&self, sample: &Sample) -> Result<()>;
+
+    /// Called after all samples of a poll cycle have been fed.
+    ///
+    /// Outputs which batch samples should send them here.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Context used for when setting up a plugin.
@@ -100,6 +129,8 @@ pub struct PluginContext<'a> {
     pub config: &'a toml::Table,
     pub cpupool: Arc<CpuPool>,
     pub core: Rc<RefCell<tokio_core::reactor::Core>>,
+    /// Interval at which samples are polled and fed to outputs.
+    pub poll_interval: Duration,
 }
 
 impl<'a> PluginContext<'a> {
@@ -0,0 +1,161 @@
+//! Input plugin that polls the machine readable Apache `server-status?auto` page.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::apache::*;
+use super::http_poller::{self, StatusFormat};
+
+use std::sync::Arc;
+
+/// Fields reported as gauges, with their unit.
+const GAUGES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("BusyWorkers", "busy-workers", "workers"),
+    ("IdleWorkers", "idle-workers", "workers"),
+    ("ConnsTotal", "connections", "connections"),
+    ("ConnsAsyncWriting", "async-writing", "connections"),
+    ("ConnsAsyncKeepAlive", "async-keepalive", "connections"),
+    ("ConnsAsyncClosing", "async-closing", "connections"),
+    ("Uptime", "uptime", "s"),
+    ("CPULoad", "cpu-load", "%"),
+];
+
+pub struct ApacheStatus {}
+
+impl StatusFormat for ApacheStatus {
+    fn key(&self) -> &'static str {
+        "apache"
+    }
+
+    fn default_target(&self) -> &'static str {
+        "http://127.0.0.1/server-status?auto"
+    }
+
+    fn convert(
+        &self,
+        base: &MetricIdBuilder,
+        page: &str,
+        readings: &mut Vec<(Arc<MetricId>, f64)>,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) -> Result<()> {
+        let fields = parse_server_status(page);
+
+        // the human readable page has no fields, which would otherwise go unnoticed.
+        if !fields.iter().any(|f| f.0 == "Total Accesses" || f.0 == "BusyWorkers") {
+            return Err(ErrorKind::Message("not a server-status?auto page".to_owned()).into());
+        }
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        for &(ref name, ref value) in &fields {
+            match name.as_str() {
+                "Total Accesses" => {
+                    if let Ok(value) = value.parse::<u64>() {
+                        counters.push((id("requests", "requests/s"), value));
+                    }
+                }
+                "Total kBytes" => {
+                    if let Ok(value) = value.parse::<u64>() {
+                        counters.push((id("sent", "bytes/s"), value * 1024));
+                    }
+                }
+                "Scoreboard" => {
+                    for (state, count) in scoreboard_states(value) {
+                        let id = base.clone()
+                            .tag("what", "scoreboard")
+                            .tag("state", state)
+                            .tag("unit", "workers")
+                            .build();
+
+                        readings.push((Arc::new(id), count as f64));
+                    }
+                }
+                name => {
+                    if let Some(&(_, what, unit)) = GAUGES.iter().find(|g| g.0 == name) {
+                        if let Ok(value) = value.parse::<f64>() {
+                            readings.push((id(what, unit), value));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ApacheStatusInput {}
+
+impl Input for ApacheStatusInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        http_poller::setup(ctx.id, ctx.decode_config()?, Box::new(ApacheStatus {}))
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(ApacheStatusInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SERVER_STATUS: &'static str = "localhost\n\
+        ServerVersion: Apache/2.4.25 (Debian)\n\
+        ServerMPM: event\n\
+        Total Accesses: 131\n\
+        Total kBytes: 156\n\
+        CPULoad: .0225\n\
+        Uptime: 1234\n\
+        ReqPerSec: .106159\n\
+        BusyWorkers: 1\n\
+        IdleWorkers: 74\n\
+        ConnsTotal: 2\n\
+        Scoreboard: _W___K....\n";
+
+    #[test]
+    fn test_convert() {
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        let base = key("apache");
+        ApacheStatus {}
+            .convert(&base, SERVER_STATUS, &mut readings, &mut counters)
+            .unwrap();
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        assert!(readings.contains(&(id("busy-workers", "workers"), 1f64)));
+        assert!(readings.contains(&(id("cpu-load", "%"), 0.0225f64)));
+        assert!(readings.contains(&(id("connections", "connections"), 2f64)));
+
+        let keepalive = base.clone()
+            .tag("what", "scoreboard")
+            .tag("state", "keepalive")
+            .tag("unit", "workers")
+            .build();
+        assert!(readings.contains(&(Arc::new(keepalive), 1f64)));
+
+        assert_eq!(
+            vec![(id("requests", "requests/s"), 131), (id("sent", "bytes/s"), 156 * 1024)],
+            counters
+        );
+    }
+
+    #[test]
+    fn test_not_auto() {
+        let result = ApacheStatus {}.convert(
+            &key("apache"),
+            "<html><body>Apache Server Status</body></html>",
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Updates of inputs on a background thread, so that slow updates never hold up the updates of
+//! other inputs.
+
+use errors::*;
+use metric::MetricId;
+use plugin::*;
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::SystemTime;
+
+/// Readings of the last background update, with the time they were read.
+pub struct Readings {
+    last: Mutex<(Vec<(Arc<MetricId>, f64)>, u64)>,
+}
+
+impl Readings {
+    pub fn new() -> Readings {
+        Readings { last: Mutex::new((Vec::new(), 0)) }
+    }
+
+    /// Replace the readings with ones read now.
+    pub fn set(&self, readings: Vec<(Arc<MetricId>, f64)>) -> Result<()> {
+        *self.last.lock()? = (readings, unix_millis(SystemTime::now()));
+        Ok(())
+    }
+
+    /// Samples of the last readings, stamped with the time they were read rather than now, so
+    /// that outputs can tell readings which are reported again apart from new ones.
+    pub fn samples(&self) -> Result<Samples> {
+        let ref last = self.last.lock()?;
+
+        Ok(
+            last.0
+                .iter()
+                .map(|&(ref id, value)| Sample::with_timestamp(id.clone(), value, last.1))
+                .collect(),
+        )
+    }
+}
+
+/// A background thread running the update of an input every time it is triggered.
+pub struct Background {
+    name: String,
+    tx: Mutex<mpsc::SyncSender<()>>,
+}
+
+impl Background {
+    /// Spawn the thread, where failed updates are logged.
+    pub fn spawn<F>(name: &str, mut update: F) -> Result<Background>
+    where
+        F: FnMut() -> Result<()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let thread_name = name.to_owned();
+
+        thread::Builder::new().name(name.to_owned()).spawn(
+            move || for () in rx {
+                if let Err(e) = update() {
+                    warn!("{}: update failed: {}", thread_name, e);
+                }
+            },
+        )?;
+
+        Ok(Background {
+            name: name.to_owned(),
+            tx: Mutex::new(tx),
+        })
+    }
+
+    /// Trigger an update without waiting for it.
+    ///
+    /// At most one update is queued while another one is running, further triggers are skipped.
+    pub fn trigger(&self) -> Result<()> {
+        match self.tx.lock()?.try_send(()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(())) => {
+                debug!("{}: update still in progress, skipping", self.name);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(())) => {
+                Err(ErrorKind::Message(format!("{}: update thread stopped", self.name)).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use metric::key;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_trigger() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+
+        let background = Background::spawn("test", move || {
+            started_tx.send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+            Ok(())
+        }).unwrap();
+
+        background.trigger().unwrap();
+        started_rx.recv().unwrap();
+
+        // one update is queued behind the running one, the rest are skipped.
+        background.trigger().unwrap();
+        background.trigger().unwrap();
+        background.trigger().unwrap();
+
+        release_tx.send(()).unwrap();
+        started_rx.recv().unwrap();
+        release_tx.send(()).unwrap();
+
+        assert!(started_rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_readings() {
+        let readings = Readings::new();
+        let id = Arc::new(key("queue").build());
+
+        readings.set(vec![(id.clone(), 12f64)]).unwrap();
+        let first = readings.samples().unwrap();
+        assert_eq!(12f64, first[0].value);
+
+        // reported again with the time of the read.
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(first[0].timestamp, readings.samples().unwrap()[0].timestamp);
+
+        readings.set(vec![(id, 13f64)]).unwrap();
+        assert!(readings.samples().unwrap()[0].timestamp > first[0].timestamp);
+    }
+}
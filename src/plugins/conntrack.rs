@@ -0,0 +1,221 @@
+//! Input plugin that reports usage of the netfilter connection tracking table.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::common::single_u64;
+use parsers::nf_conntrack::*;
+use super::utils::read_optional_file;
+
+use futures::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Counters in /proc/net/stat/nf_conntrack which are reported as rates.
+const CONNTRACK_COUNTERS: &'static [(&'static str, &'static str)] = &[
+    ("insert_failed", "conntrack-insert-failed"),
+    ("drop", "conntrack-drop"),
+    ("early_drop", "conntrack-early-drop"),
+    ("invalid", "conntrack-invalid"),
+];
+
+#[derive(Debug)]
+struct ConntrackInput {}
+
+impl Input for ConntrackInput {
+    fn setup(&self, _ctx: PluginContext) -> Result<Box<InputInstance>> {
+        Ok(Box::new(ConntrackInputInstance::new(PathBuf::from("/proc"))))
+    }
+}
+
+fn read_u64(path: &Path) -> Result<Option<u64>> {
+    match read_optional_file(path)? {
+        Some(content) => Ok(Some(single_u64(&content).to_full_result()?)),
+        None => Ok(None),
+    }
+}
+
+struct Metrics {
+    /// root of the proc filesystem.
+    proc_root: PathBuf,
+    entries: Arc<MetricId>,
+    max: Arc<MetricId>,
+    utilization: Arc<MetricId>,
+    deltas: Deltas<Arc<MetricId>>,
+    /// the metric to report each counter as.
+    counters: Vec<(&'static str, Arc<MetricId>)>,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    /// Hosts without the conntrack module loaded produce no readings.
+    pub fn scan(&mut self, now: Instant) -> Result<()> {
+        let mut readings = Vec::new();
+
+        let netfilter = self.proc_root.join("sys/net/netfilter");
+        let count = read_u64(&netfilter.join("nf_conntrack_count"))?;
+        let max = read_u64(&netfilter.join("nf_conntrack_max"))?;
+
+        if let (Some(count), Some(max)) = (count, max) {
+            readings.push((self.entries.clone(), count as f64));
+            readings.push((self.max.clone(), max as f64));
+
+            if max > 0 {
+                readings.push((
+                    self.utilization.clone(),
+                    (count as f64 / max as f64) * 100f64,
+                ));
+            }
+        }
+
+        let mut counters = Vec::new();
+
+        if let Some(content) = read_optional_file(self.proc_root.join("net/stat/nf_conntrack"))? {
+            let stat = parse_nf_conntrack(&content).to_full_result()?;
+
+            for &(field, ref id) in &self.counters {
+                if let Some(total) = stat.total(field) {
+                    counters.push((id.clone(), total));
+                }
+            }
+        }
+
+        readings.extend(self.deltas.rates(now, counters));
+        self.readings = readings;
+        Ok(())
+    }
+
+    pub fn update(&mut self) -> Result<()> {
+        self.scan(Instant::now())
+    }
+}
+
+struct ConntrackInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for ConntrackInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConntrackInputInstance")
+    }
+}
+
+impl ConntrackInputInstance {
+    pub fn new(proc_root: PathBuf) -> ConntrackInputInstance {
+        let system = key("system");
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(system.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        let counters = CONNTRACK_COUNTERS
+            .iter()
+            .map(|&(field, what)| (field, id(what, "events/s")))
+            .collect();
+
+        ConntrackInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                proc_root: proc_root,
+                entries: id("conntrack-entries", "entries"),
+                max: id("conntrack-max", "entries"),
+                utilization: id("conntrack-utilization", "%"),
+                deltas: Deltas::new(),
+                counters: counters,
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for ConntrackInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(ConntrackInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{find_system, write_fixture};
+
+    use std::env;
+    use std::fs;
+
+    fn stat(drop: u64) -> String {
+        format!(
+            "entries  searched found new invalid ignore delete delete_list insert \
+             insert_failed drop early_drop icmp_error  expect_new expect_create \
+             expect_delete search_restart\n\
+             0000002a  00000000 00000000 00000000 00000010 0000113c 00000000 00000000 \
+             00000000 00000001 {:08x} 00000000 00000000  00000000 00000000 00000000 \
+             000005c6\n",
+            drop
+        )
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = env::temp_dir().join("sysmon-conntrack-scan");
+        let _ = fs::remove_dir_all(&root);
+
+        write_fixture(&root, "sys/net/netfilter/nf_conntrack_count", "16384\n");
+        write_fixture(&root, "sys/net/netfilter/nf_conntrack_max", "65536\n");
+        write_fixture(&root, "net/stat/nf_conntrack", &stat(10));
+
+        let instance = ConntrackInputInstance::new(root.clone());
+        let mut m = instance.metrics.lock().unwrap();
+        let now = Instant::now();
+
+        // the first scan only has the table, since rates need two samples.
+        m.scan(now).unwrap();
+        assert_eq!(3, m.readings.len());
+        assert_eq!(Some(16384f64), find_system(&m.readings, "conntrack-entries", "entries"));
+        assert_eq!(Some(65536f64), find_system(&m.readings, "conntrack-max", "entries"));
+        assert_eq!(Some(25f64), find_system(&m.readings, "conntrack-utilization", "%"));
+
+        write_fixture(&root, "net/stat/nf_conntrack", &stat(30));
+        m.scan(now + Duration::from_secs(2)).unwrap();
+
+        assert_eq!(3 + 4, m.readings.len());
+        assert_eq!(Some(10f64), find_system(&m.readings, "conntrack-drop", "events/s"));
+        assert_eq!(Some(0f64), find_system(&m.readings, "conntrack-insert-failed", "events/s"));
+
+        // without the module loaded, nothing is reported.
+        let missing = ConntrackInputInstance::new(root.join("missing"));
+        let mut m = missing.metrics.lock().unwrap();
+        m.scan(now).unwrap();
+        assert_eq!(0, m.readings.len());
+    }
+}
@@ -0,0 +1,117 @@
+//! Delivery of batches on a background thread, so that slow or unreachable destinations of
+//! outputs never block polling.
+
+use errors::*;
+
+use std::cmp;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+/// Exponential backoff between attempts to reach a destination.
+#[derive(Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Backoff {
+        Backoff {
+            min: min,
+            max: max,
+            next: min,
+        }
+    }
+
+    /// Get the duration to wait after a failure, which doubles up to the maximum.
+    pub fn fail(&mut self) -> Duration {
+        let current = self.next;
+        self.next = cmp::min(self.next * 2, self.max);
+        current
+    }
+
+    /// Reset after a success.
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+/// A background thread delivering batches in the order they were queued.
+pub struct Worker<T> {
+    name: String,
+    tx: Mutex<mpsc::SyncSender<T>>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    /// Spawn a worker, which queues at most `capacity` batches.
+    pub fn spawn<F>(name: &str, capacity: usize, mut deliver: F) -> Result<Worker<T>>
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+
+        thread::Builder::new().name(name.to_owned()).spawn(
+            move || for batch in rx {
+                deliver(batch);
+            },
+        )?;
+
+        Ok(Worker {
+            name: name.to_owned(),
+            tx: Mutex::new(tx),
+        })
+    }
+
+    /// Queue a batch for delivery.
+    ///
+    /// If the queue is full, because the destination is slow or unreachable, the batch is
+    /// dropped instead of blocking.
+    pub fn send(&self, batch: T) -> Result<()> {
+        match self.tx.lock()?.try_send(batch) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                warn!("{}: delivery queue is full, dropping batch", self.name);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                Err(ErrorKind::Message(format!("{}: delivery thread stopped", self.name)).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(300));
+
+        assert_eq!(Duration::from_millis(100), backoff.fail());
+        assert_eq!(Duration::from_millis(200), backoff.fail());
+        assert_eq!(Duration::from_millis(300), backoff.fail());
+        assert_eq!(Duration::from_millis(300), backoff.fail());
+
+        backoff.reset();
+        assert_eq!(Duration::from_millis(100), backoff.fail());
+    }
+
+    #[test]
+    fn test_worker() {
+        let (tx, rx) = mpsc::channel();
+
+        let worker = Worker::spawn("test", 4, move |batch: Vec<u32>| {
+            tx.send(batch).unwrap();
+        }).unwrap();
+
+        worker.send(vec![1, 2]).unwrap();
+        worker.send(vec![3]).unwrap();
+
+        assert_eq!(vec![1, 2], rx.recv().unwrap());
+        assert_eq!(vec![3], rx.recv().unwrap());
+    }
+}
@@ -0,0 +1,605 @@
+//! Input plugin that reports per-container statistics from the Docker Engine API.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use super::background::{Background, Readings};
+use super::http_client;
+
+use futures::*;
+use futures_cpupool::{Builder, CpuPool};
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct DockerInputConfig {
+    /// Path to the API socket, defaults to /var/run/docker.sock.
+    socket: Option<String>,
+    /// Container labels added as tags.
+    labels: Option<Vec<String>>,
+    /// Timeout in seconds for every API request, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct DockerInput {}
+
+impl Input for DockerInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: DockerInputConfig = ctx.decode_config()?;
+
+        let docker = Docker {
+            socket: PathBuf::from(config.socket.unwrap_or(
+                "/var/run/docker.sock".to_owned(),
+            )),
+            labels: config.labels.unwrap_or_else(Vec::new),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+            cpu: Deltas::new(),
+            counters: Deltas::new(),
+            pool: stats_pool(),
+        };
+
+        Ok(Box::new(DockerInputInstance::new(&ctx.id, docker)?))
+    }
+}
+
+/// Network counters of an interface, with the name and unit of their rate.
+const NETWORK: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("rx_bytes", "network-received", "bytes/s"),
+    ("tx_bytes", "network-sent", "bytes/s"),
+    ("rx_packets", "network-received-packets", "packets/s"),
+    ("tx_packets", "network-sent-packets", "packets/s"),
+    ("rx_errors", "network-receive-errors", "packets/s"),
+    ("tx_errors", "network-send-errors", "packets/s"),
+    ("rx_dropped", "network-receive-drops", "packets/s"),
+    ("tx_dropped", "network-send-drops", "packets/s"),
+];
+
+/// Block IO counters, with the name and unit of the rates of reads and writes.
+const BLKIO: &'static [(&'static str, &'static str, &'static str, &'static str)] = &[
+    ("io_service_bytes_recursive", "blkio-read", "blkio-written", "bytes/s"),
+    ("io_serviced_recursive", "blkio-reads", "blkio-writes", "ops/s"),
+];
+
+/// A running container, as listed by the API.
+#[derive(Debug)]
+struct Container {
+    id: String,
+    name: String,
+    image: String,
+    labels: BTreeMap<String, String>,
+}
+
+impl Container {
+    fn from_json(value: &Value) -> Option<Container> {
+        let id = value.find("Id")?.as_str()?.to_owned();
+
+        // names are prefixed with a slash, and the first one is the primary name.
+        let name = value
+            .find("Names")
+            .and_then(Value::as_array)
+            .and_then(|names| names.first())
+            .and_then(Value::as_str)
+            .map(|name| name.trim_left_matches('/').to_owned())
+            .unwrap_or_else(|| id.chars().take(12).collect());
+
+        let image = value
+            .find("Image")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_owned();
+
+        let labels = value
+            .find("Labels")
+            .and_then(Value::as_object)
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_owned())))
+                    .collect()
+            })
+            .unwrap_or_else(BTreeMap::new);
+
+        Some(Container {
+            id: id,
+            name: name,
+            image: image,
+            labels: labels,
+        })
+    }
+}
+
+/// Path of the statistics of a container, which are returned without waiting for a second
+/// sample since rates are calculated here.
+fn stats_path(id: &str) -> String {
+    format!("/containers/{}/stats?stream=false&one-shot=true", id)
+}
+
+/// Perform a request against the API.
+fn get(socket: &Path, timeout: Duration, path: &str) -> Result<Value> {
+    let stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let response = http_client::request(stream, "docker", "GET", path, &[], &[])?;
+
+    if !response.is_success() {
+        return Err(
+            ErrorKind::Message(format!("{}: unexpected status: {}", path, response.status)).into(),
+        );
+    }
+
+    Ok(serde_json::from_slice(&response.body)?)
+}
+
+/// Number of containers whose statistics are requested at the same time.
+const STATS_WORKERS: usize = 4;
+
+/// Pool of workers on which the statistics of containers are requested.
+fn stats_pool() -> CpuPool {
+    Builder::new()
+        .pool_size(STATS_WORKERS)
+        .name_prefix("docker-stats-")
+        .create()
+}
+
+fn number(value: &Value, pointer: &str) -> Option<u64> {
+    value.pointer(pointer).and_then(Value::as_u64)
+}
+
+struct Docker {
+    socket: PathBuf,
+    labels: Vec<String>,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+    /// cpu time in nanoseconds, reported as a percentage of a single cpu.
+    cpu: Deltas<Arc<MetricId>>,
+    counters: Deltas<Arc<MetricId>>,
+    /// the daemon answers requests for stats slowly, so they are made in parallel.
+    pool: CpuPool,
+}
+
+impl Docker {
+    fn containers(&self) -> Result<Vec<Container>> {
+        match get(&self.socket, self.timeout, "/containers/json")? {
+            Value::Array(entries) => Ok(entries.iter().filter_map(Container::from_json).collect()),
+            _ => Err(ErrorKind::Message("expected a list of containers".to_owned()).into()),
+        }
+    }
+
+    fn base(&self) -> MetricIdBuilder {
+        let mut base = key("docker");
+
+        for (k, v) in &self.tags {
+            base = base.tag(k, v);
+        }
+
+        base
+    }
+
+    fn container_base(&self, container: &Container) -> MetricIdBuilder {
+        let mut base = self.base()
+            .tag("container", &container.name)
+            .tag("image", &container.image);
+
+        for label in &self.labels {
+            if let Some(value) = container.labels.get(label) {
+                base = base.tag(label, value);
+            }
+        }
+
+        base
+    }
+
+    /// Convert the statistics of a single container.
+    fn convert(
+        &self,
+        base: &MetricIdBuilder,
+        stats: &Value,
+        readings: &mut Vec<(Arc<MetricId>, f64)>,
+        cpu: &mut Vec<(Arc<MetricId>, u64)>,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) {
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        if let Some(usage) = number(stats, "/cpu_stats/cpu_usage/total_usage") {
+            cpu.push((id("cpu-usage", "%"), usage));
+        }
+
+        if let Some(usage) = number(stats, "/memory_stats/usage") {
+            // page cache can be reclaimed, so it is not counted as used like `docker stats`.
+            let cache = number(stats, "/memory_stats/stats/cache")
+                .or_else(|| number(stats, "/memory_stats/stats/inactive_file"))
+                .unwrap_or(0);
+
+            readings.push((id("memory-usage", "bytes"), usage.saturating_sub(cache) as f64));
+        }
+
+        if let Some(limit) = number(stats, "/memory_stats/limit") {
+            readings.push((id("memory-limit", "bytes"), limit as f64));
+        }
+
+        if let Some(networks) = stats.find("networks").and_then(Value::as_object) {
+            for (interface, counts) in networks {
+                let base = base.clone().tag("interface", interface);
+
+                for &(field, what, unit) in NETWORK {
+                    if let Some(value) = counts.find(field).and_then(Value::as_u64) {
+                        let id = base.clone().tag("what", what).tag("unit", unit).build();
+                        counters.push((Arc::new(id), value));
+                    }
+                }
+            }
+        }
+
+        for &(field, read, write, unit) in BLKIO {
+            let entries = match stats.pointer(&format!("/blkio_stats/{}", field)) {
+                Some(&Value::Array(ref entries)) => entries,
+                _ => continue,
+            };
+
+            let (mut reads, mut writes) = (0u64, 0u64);
+
+            for entry in entries {
+                let value = entry.find("value").and_then(Value::as_u64).unwrap_or(0);
+
+                match entry.find("op").and_then(Value::as_str).map(str::to_lowercase) {
+                    Some(ref op) if op == "read" => reads += value,
+                    Some(ref op) if op == "write" => writes += value,
+                    _ => {}
+                }
+            }
+
+            counters.push((id(read, unit), reads));
+            counters.push((id(write, unit), writes));
+        }
+    }
+
+    /// Get the statistics of the containers in parallel, within a single timeout.
+    ///
+    /// Returns the statistics by the index of the container, containers which fail or don't
+    /// respond in time are skipped.
+    fn stats(&self, containers: &[Container]) -> Vec<(usize, Value)> {
+        let (tx, rx) = mpsc::channel();
+        let deadline = Instant::now() + self.timeout;
+
+        for (index, container) in containers.iter().enumerate() {
+            let tx = tx.clone();
+            let socket = self.socket.clone();
+            let timeout = self.timeout;
+            let path = stats_path(&container.id);
+
+            self.pool
+                .spawn_fn(move || {
+                    // nobody waits for requests still queued after the deadline.
+                    if Instant::now() < deadline {
+                        let _ = tx.send((index, get(&socket, timeout, &path)));
+                    }
+
+                    Ok::<(), ()>(())
+                })
+                .forget();
+        }
+
+        drop(tx);
+
+        let mut stats = Vec::new();
+
+        loop {
+            let now = Instant::now();
+
+            let received = match deadline > now {
+                true => rx.recv_timeout(deadline - now),
+                false => Err(RecvTimeoutError::Timeout),
+            };
+
+            match received {
+                Ok((index, Ok(value))) => stats.push((index, value)),
+                // containers can stop between being listed and queried.
+                Ok((index, Err(e))) => {
+                    debug!("{}: failed to get stats: {}", containers[index].name, e)
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    debug!("{}: timed out getting stats", self.socket.display());
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        stats
+    }
+
+    /// Report statistics of all running containers.
+    ///
+    /// Series are rebuilt on every update, and the counters of containers which are gone are
+    /// forgotten, so that container churn doesn't leave series behind.
+    pub fn scan(&mut self, now: Instant) -> Vec<(Arc<MetricId>, f64)> {
+        let mut readings = Vec::new();
+        let mut cpu = Vec::new();
+        let mut counters = Vec::new();
+
+        let up = match self.containers() {
+            Ok(containers) => {
+                for (index, stats) in self.stats(&containers) {
+                    let base = self.container_base(&containers[index]);
+                    self.convert(&base, &stats, &mut readings, &mut cpu, &mut counters);
+                }
+
+                1f64
+            }
+            Err(e) => {
+                warn!("{}: failed to list containers: {}", self.socket.display(), e);
+                0f64
+            }
+        };
+
+        for (id, rate) in self.cpu.rates(now, cpu) {
+            readings.push((id, rate / 1e7));
+        }
+
+        readings.extend(self.counters.rates(now, counters));
+        readings.push((Arc::new(self.base().tag("what", "up").build()), up));
+        readings
+    }
+}
+
+struct DockerInputInstance {
+    /// readings of the last scan.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for DockerInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DockerInputInstance")
+    }
+}
+
+impl DockerInputInstance {
+    pub fn new(id: &str, mut docker: Docker) -> Result<DockerInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("docker-{}", id), move || {
+            results.set(docker.scan(Instant::now()))
+        })?;
+
+        Ok(DockerInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_millis(1000),
+        })
+    }
+}
+
+impl InputInstance for DockerInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// The API is queried on a thread of its own, so a slow daemon never holds up the updates of
+    /// other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(DockerInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::process;
+    use std::sync::Mutex;
+    use std::thread;
+
+    type Routes = Arc<Mutex<HashMap<String, String>>>;
+
+    /// Serve canned JSON responses by request path on a Unix socket, until the test ends.
+    fn serve(path: &Path, routes: Routes) {
+        let listener = UnixListener::bind(path).unwrap();
+
+        thread::spawn(move || for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+            }
+
+            let path = request.split_whitespace().nth(1).unwrap_or("").to_owned();
+
+            let response = match routes.lock().unwrap().get(&path) {
+                Some(body) => {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                }
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned(),
+            };
+
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        });
+    }
+
+    fn stats(cpu: u64, rx: u64) -> String {
+        format!(
+            r#"{{
+                "cpu_stats": {{"cpu_usage": {{"total_usage": {}}}}},
+                "memory_stats": {{"usage": 104857600, "limit": 1073741824,
+                                  "stats": {{"cache": 20971520}}}},
+                "networks": {{"eth0": {{"rx_bytes": {}, "tx_bytes": 0}}}},
+                "blkio_stats": {{"io_service_bytes_recursive": [
+                    {{"major": 8, "minor": 0, "op": "Read", "value": 4096}},
+                    {{"major": 8, "minor": 0, "op": "Write", "value": 8192}},
+                    {{"major": 8, "minor": 0, "op": "Total", "value": 12288}}
+                ]}}
+            }}"#,
+            cpu,
+            rx
+        )
+    }
+
+    const WEB: &'static str = r#"{"Id": "aaa", "Names": ["/web"], "Image": "nginx:1.13",
+        "Labels": {"com.example.team": "infra", "other": "x"}}"#;
+
+    const DB: &'static str = r#"{"Id": "bbb", "Names": ["/db"], "Image": "postgres:10"}"#;
+
+    #[test]
+    fn test_scan() {
+        let path = env::temp_dir().join(format!("sysmon-docker-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let routes: Routes = Arc::new(Mutex::new(HashMap::new()));
+
+        {
+            let mut routes = routes.lock().unwrap();
+            routes.insert("/containers/json".to_owned(), format!("[{}, {}]", WEB, DB));
+            routes.insert(stats_path("aaa"), stats(1000000000, 1000));
+            routes.insert(stats_path("bbb"), stats(0, 0));
+        }
+
+        serve(&path, routes.clone());
+
+        let mut docker = Docker {
+            socket: path.clone(),
+            labels: vec!["com.example.team".to_owned()],
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            cpu: Deltas::new(),
+            counters: Deltas::new(),
+            pool: stats_pool(),
+        };
+
+        let web = key("docker")
+            .tag("container", "web")
+            .tag("image", "nginx:1.13")
+            .tag("com.example.team", "infra");
+
+        let id = |what: &str, unit: &str| web.clone().tag("what", what).tag("unit", unit).build();
+
+        let now = Instant::now();
+        let readings = docker.scan(now);
+
+        assert_eq!(Some(83886080f64), find(&readings, &id("memory-usage", "bytes")));
+        assert_eq!(Some(1073741824f64), find(&readings, &id("memory-limit", "bytes")));
+        assert_eq!(Some(1f64), find(&readings, &key("docker").tag("what", "up").build()));
+        assert!(readings.iter().any(|r| r.0.to_string().contains("\"db\"")));
+
+        // the db container goes away, and the web container makes progress.
+        {
+            let mut routes = routes.lock().unwrap();
+            routes.insert("/containers/json".to_owned(), format!("[{}]", WEB));
+            routes.insert(stats_path("aaa"), stats(1500000000, 3000));
+        }
+
+        let readings = docker.scan(now + Duration::from_secs(1));
+
+        assert_eq!(Some(50f64), find(&readings, &id("cpu-usage", "%")));
+
+        let received = web.clone()
+            .tag("interface", "eth0")
+            .tag("what", "network-received")
+            .tag("unit", "bytes/s")
+            .build();
+        assert_eq!(Some(2000f64), find(&readings, &received));
+        assert_eq!(Some(0f64), find(&readings, &id("blkio-read", "bytes/s")));
+
+        assert!(!readings.iter().any(|r| r.0.to_string().contains("\"db\"")));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stats_timeout() {
+        let path = env::temp_dir().join(format!("sysmon-docker-slow-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // list the containers, and then never respond to the stats requests.
+        thread::spawn(move || {
+            let mut streams = Vec::new();
+
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+
+                if streams.is_empty() {
+                    let body = format!("[{}, {}]", WEB, DB);
+                    let length = format!("Content-Length: {}\r\n\r\n", body.len());
+                    stream.write_all(b"HTTP/1.1 200 OK\r\n").unwrap();
+                    stream.write_all(length.as_bytes()).unwrap();
+                    stream.write_all(body.as_bytes()).unwrap();
+                }
+
+                streams.push(stream);
+            }
+        });
+
+        let mut docker = Docker {
+            socket: path.clone(),
+            labels: Vec::new(),
+            timeout: Duration::from_millis(500),
+            tags: BTreeMap::new(),
+            cpu: Deltas::new(),
+            counters: Deltas::new(),
+            pool: stats_pool(),
+        };
+
+        let started = Instant::now();
+        let readings = docker.scan(started);
+
+        // both containers are waited on at the same time.
+        assert!(started.elapsed() < Duration::from_millis(900));
+        assert_eq!(Some(1f64), find(&readings, &key("docker").tag("what", "up").build()));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut docker = Docker {
+            socket: PathBuf::from("/nonexistent/docker.sock"),
+            labels: Vec::new(),
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            cpu: Deltas::new(),
+            counters: Deltas::new(),
+            pool: stats_pool(),
+        };
+
+        let up = key("docker").tag("what", "up").build();
+        assert_eq!(vec![(Arc::new(up), 0f64)], docker.scan(Instant::now()));
+    }
+}
@@ -0,0 +1,527 @@
+//! Input plugin that runs an external command and parses its output.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::graphite::*;
+use parsers::influx::*;
+use parsers::nagios::*;
+use super::background::{Background, Readings};
+use super::utils::lines;
+
+use futures::*;
+use libc;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct ExecInputConfig {
+    /// Command to run, the first element is the program.
+    command: Option<Vec<String>>,
+    /// Format of the output, one of json, graphite, influx or nagios. Defaults to json.
+    format: Option<String>,
+    /// Timeout in seconds after which the command is killed. Defaults to 10.
+    timeout: Option<u64>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Graphite,
+    Influx,
+    Nagios,
+}
+
+#[derive(Debug)]
+struct ExecInput {}
+
+impl Input for ExecInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: ExecInputConfig = ctx.decode_config()?;
+
+        let command = match config.command {
+            Some(command) => command,
+            None => return Err(ErrorKind::MissingField("command".to_owned()).into()),
+        };
+
+        if command.is_empty() {
+            return Err(
+                ErrorKind::ConfigField("command".to_owned(), "must not be empty".to_owned()).into(),
+            );
+        }
+
+        let format = match config.format.as_ref().map(String::as_str) {
+            None | Some("json") => Format::Json,
+            Some("graphite") => Format::Graphite,
+            Some("influx") => Format::Influx,
+            Some("nagios") => Format::Nagios,
+            Some(other) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "format".to_owned(),
+                        format!("expected json, graphite, influx or nagios, got: {}", other),
+                    ).into(),
+                )
+            }
+        };
+
+        let exec = Exec {
+            id: ctx.id.clone(),
+            command: command,
+            format: format,
+            timeout: Duration::from_secs(config.timeout.unwrap_or(10)),
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+        };
+
+        Ok(Box::new(ExecInputInstance::new(exec)?))
+    }
+}
+
+/// Kill every process in the group, which the command leads.
+fn kill_group(group: libc::pid_t) {
+    // the group may already be gone, in which case there is nothing left to kill.
+    unsafe {
+        libc::kill(-group, libc::SIGKILL);
+    }
+}
+
+/// The result of running a command to completion.
+struct Output {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+}
+
+struct Exec {
+    id: String,
+    command: Vec<String>,
+    format: Format,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+}
+
+impl Exec {
+    /// Builder for a metric, which includes the tags of the instance.
+    fn metric(&self, key_name: &str) -> MetricIdBuilder {
+        let mut builder = key(key_name);
+
+        for (k, v) in &self.tags {
+            builder = builder.tag(k, v);
+        }
+
+        builder
+    }
+
+    /// Run the command, killing it if it overruns the timeout.
+    ///
+    /// The command runs in a process group of its own, so that anything it spawns is killed with
+    /// it.
+    fn run(&self) -> Result<Output> {
+        let mut command = Command::new(&self.command[0]);
+
+        command
+            .args(&self.command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+
+        // Only async-signal-safe calls are allowed between fork and exec, and setpgid is one.
+        unsafe {
+            command.pre_exec(|| match libc::setpgid(0, 0) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            });
+        }
+
+        let mut child = command.spawn()?;
+        let group = child.id() as libc::pid_t;
+
+        let mut stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => return Err(ErrorKind::Message("no stdout for child".to_owned()).into()),
+        };
+
+        // read stdout on a separate thread, so that a chatty child can't fill up the pipe and
+        // block while we wait for it to exit.
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = tx.send(stdout.read_to_end(&mut buffer).map(|_| buffer));
+        });
+
+        let started = Instant::now();
+        let timed_out = || ErrorKind::Message(format!("timed out after {:?}", self.timeout));
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if started.elapsed() >= self.timeout {
+                kill_group(group);
+                child.wait()?;
+                return Err(timed_out().into());
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        // processes left behind by the command can keep stdout open after it exited, so reading
+        // is bounded by the same deadline.
+        let elapsed = started.elapsed();
+        let remaining = if elapsed < self.timeout {
+            self.timeout - elapsed
+        } else {
+            Duration::from_secs(0)
+        };
+
+        let stdout = match rx.recv_timeout(remaining) {
+            Ok(stdout) => stdout?,
+            Err(_) => {
+                kill_group(group);
+                return Err(timed_out().into());
+            }
+        };
+
+        Ok(Output {
+            status: status,
+            stdout: stdout,
+        })
+    }
+
+    fn parse(&self, output: &Output) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        match self.format {
+            Format::Json => self.parse_json(&output.stdout),
+            Format::Graphite => self.parse_graphite(&output.stdout),
+            Format::Influx => self.parse_influx(&output.stdout),
+            Format::Nagios => self.parse_nagios(output),
+        }
+    }
+
+    /// Parse JSON samples, either a single object or an array of objects like:
+    /// `{"key": "requests", "tags": {"endpoint": "/"}, "value": 42}`.
+    fn parse_json(&self, stdout: &[u8]) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let value: serde_json::Value = serde_json::from_slice(stdout)?;
+
+        let entries = match value {
+            serde_json::Value::Array(entries) => entries,
+            value => vec![value],
+        };
+
+        let mut readings = Vec::new();
+
+        for entry in entries {
+            let object = match entry.as_object() {
+                Some(object) => object,
+                None => return Err(ErrorKind::Message("expected object".to_owned()).into()),
+            };
+
+            let key_name = match object.get("key").and_then(|k| k.as_str()) {
+                Some(key_name) => key_name,
+                None => return Err(ErrorKind::MissingField("key".to_owned()).into()),
+            };
+
+            let value = match object.get("value").and_then(|v| v.as_f64()) {
+                Some(value) => value,
+                None => return Err(ErrorKind::MissingField("value".to_owned()).into()),
+            };
+
+            let mut builder = self.metric(key_name);
+
+            if let Some(tags) = object.get("tags").and_then(|t| t.as_object()) {
+                for (k, v) in tags {
+                    if let Some(v) = v.as_str() {
+                        builder = builder.tag(k, v);
+                    }
+                }
+            }
+
+            readings.push((Arc::new(builder.build()), value));
+        }
+
+        Ok(readings)
+    }
+
+    fn parse_graphite(&self, stdout: &[u8]) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let mut readings = Vec::new();
+
+        for line in lines(stdout) {
+            let line = parse_graphite_line(&line).to_full_result()?;
+            readings.push((Arc::new(self.metric(&line.path).build()), line.value));
+        }
+
+        Ok(readings)
+    }
+
+    fn parse_influx(&self, stdout: &[u8]) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let stdout = String::from_utf8_lossy(stdout);
+        let mut readings = Vec::new();
+
+        for line in stdout.lines() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line = parse_influx_line(line).map_err(ErrorKind::Message)?;
+
+            let mut builder = self.metric(&line.measurement);
+
+            for &(ref k, ref v) in &line.tags {
+                builder = builder.tag(k, v);
+            }
+
+            for &(ref field, ref value) in &line.fields {
+                if let Some(value) = value.as_f64() {
+                    let id = builder.clone().tag("what", field).build();
+                    readings.push((Arc::new(id), value));
+                }
+            }
+        }
+
+        Ok(readings)
+    }
+
+    /// Nagios output is reported as the exit code of the check, and all of its performance data.
+    fn parse_nagios(&self, output: &Output) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut readings = Vec::new();
+
+        if let Some(code) = output.status.code() {
+            let id = self.metric(&self.id).tag("what", "status").build();
+            readings.push((Arc::new(id), code as f64));
+        }
+
+        for perfdata in parse_nagios_perfdata(&stdout) {
+            let mut builder = self.metric(&self.id).tag("what", &perfdata.label);
+
+            if let Some(ref unit) = perfdata.unit {
+                builder = builder.tag("unit", unit);
+            }
+
+            readings.push((Arc::new(builder.build()), perfdata.value));
+        }
+
+        Ok(readings)
+    }
+
+    /// Run the command and parse its output.
+    ///
+    /// Failures never propagate, since that would stop all updates. They are instead logged and
+    /// reported through the exec-failed series.
+    pub fn scan(&self) -> Vec<(Arc<MetricId>, f64)> {
+        let started = Instant::now();
+
+        let result = self.run().and_then(|output| {
+            if self.format != Format::Nagios && !output.status.success() {
+                return Err(
+                    ErrorKind::Message(format!("command failed: {}", output.status)).into(),
+                );
+            }
+
+            self.parse(&output)
+        });
+
+        let elapsed = started.elapsed();
+        let duration = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+        let (mut readings, failed) = match result {
+            Ok(readings) => (readings, 0f64),
+            Err(e) => {
+                warn!("{}: {:?}: {}", self.id, self.command, e);
+                (Vec::new(), 1f64)
+            }
+        };
+
+        let exec = self.metric("exec").tag("instance", &self.id);
+
+        readings.push((
+            Arc::new(exec.clone().tag("what", "exec-duration").tag("unit", "s").build()),
+            duration,
+        ));
+
+        readings.push((Arc::new(exec.tag("what", "exec-failed").build()), failed));
+        readings
+    }
+}
+
+struct ExecInputInstance {
+    /// readings of the last run of the command.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for ExecInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExecInputInstance")
+    }
+}
+
+impl ExecInputInstance {
+    pub fn new(exec: Exec) -> Result<ExecInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("exec-{}", exec.id), move || {
+            results.set(exec.scan())
+        })?;
+
+        Ok(ExecInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_millis(1000),
+        })
+    }
+}
+
+impl InputInstance for ExecInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// The command runs on a thread of its own, so a slow command never holds up the updates of
+    /// other inputs. Until it finishes, the readings of its previous run are reported.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(ExecInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find;
+
+    fn exec(command: &str, format: Format, timeout: Duration) -> Exec {
+        let mut tags = BTreeMap::new();
+        tags.insert("team".to_owned(), "ops".to_owned());
+
+        Exec {
+            id: "check".to_owned(),
+            command: vec!["/bin/sh".to_owned(), "-c".to_owned(), command.to_owned()],
+            format: format,
+            timeout: timeout,
+            tags: tags,
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let e = exec(
+            "echo '[{\"key\": \"queue\", \"tags\": {\"name\": \"jobs\"}, \"value\": 12}]'",
+            Format::Json,
+            Duration::from_secs(10),
+        );
+
+        let readings = e.scan();
+
+        let id = key("queue").tag("team", "ops").tag("name", "jobs").build();
+        assert_eq!(Some(12f64), find(&readings, &id));
+
+        let failed = key("exec")
+            .tag("team", "ops")
+            .tag("instance", "check")
+            .tag("what", "exec-failed")
+            .build();
+        assert_eq!(Some(0f64), find(&readings, &failed));
+    }
+
+    #[test]
+    fn test_nagios() {
+        let e = exec(
+            "echo 'WARNING - load | load1=1.5;1;2 disk=20%'; exit 1",
+            Format::Nagios,
+            Duration::from_secs(10),
+        );
+
+        let readings = e.scan();
+
+        let status = key("check").tag("team", "ops").tag("what", "status").build();
+        assert_eq!(Some(1f64), find(&readings, &status));
+
+        let disk = key("check")
+            .tag("team", "ops")
+            .tag("what", "disk")
+            .tag("unit", "%")
+            .build();
+        assert_eq!(Some(20f64), find(&readings, &disk));
+    }
+
+    #[test]
+    fn test_update() {
+        let e = exec(
+            "sleep 0.2; echo '{\"key\": \"queue\", \"value\": 12}'",
+            Format::Json,
+            Duration::from_secs(10),
+        );
+
+        let instance = ExecInputInstance::new(e).unwrap();
+
+        let started = Instant::now();
+        instance.update().wait().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert_eq!(0, instance.poll().unwrap().len());
+
+        let id = key("queue").tag("team", "ops").build();
+
+        while started.elapsed() < Duration::from_secs(5) {
+            if instance.poll().unwrap().iter().any(|s| *s.metric_id == id) {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("no readings after the command finished");
+    }
+
+    #[test]
+    fn test_timeout_left_behind() {
+        // a process in the background keeps stdout open after the command exited.
+        let e = exec("sleep 10 & echo x", Format::Graphite, Duration::from_millis(200));
+
+        let started = Instant::now();
+        let readings = e.scan();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let failed = key("exec")
+            .tag("team", "ops")
+            .tag("instance", "check")
+            .tag("what", "exec-failed")
+            .build();
+        assert_eq!(Some(1f64), find(&readings, &failed));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let e = exec("sleep 10", Format::Graphite, Duration::from_millis(100));
+
+        let started = Instant::now();
+        let readings = e.scan();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let failed = key("exec")
+            .tag("team", "ops")
+            .tag("instance", "check")
+            .tag("what", "exec-failed")
+            .build();
+        assert_eq!(Some(1f64), find(&readings, &failed));
+    }
+}
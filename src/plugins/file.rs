@@ -0,0 +1,615 @@
+//! Output plugin that appends samples to a file, rotating it by size or age.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+use super::delivery::Worker;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures::{Future, Stream};
+use serde_json;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio_signal::unix::{Signal, SIGHUP};
+
+#[derive(Deserialize, Debug)]
+struct FileOutputConfig {
+    /// Path of the file to append to.
+    path: String,
+    /// Format of the lines, one of json, csv or influx, defaults to json.
+    format: Option<String>,
+    /// Rotate the file before it grows beyond this many bytes.
+    max_size: Option<u64>,
+    /// Rotate the file after this many seconds.
+    rotate_interval: Option<u64>,
+    /// Number of rotated files to keep, defaults to 5.
+    retention: Option<usize>,
+    /// Compress rotated files with gzip.
+    gzip: Option<bool>,
+}
+
+#[derive(Debug)]
+struct FileOutput {}
+
+impl Output for FileOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: FileOutputConfig = ctx.decode_config()?;
+
+        let format = match config.format.as_ref().map(String::as_str) {
+            None | Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            Some("influx") => Format::Influx,
+            Some(format) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "format".to_owned(),
+                        format!("expected json, csv or influx, got: {}", format),
+                    ).into(),
+                )
+            }
+        };
+
+        if config.max_size == Some(0) {
+            return Err(
+                ErrorKind::ConfigField("max_size".to_owned(), "must be at least 1".to_owned())
+                    .into(),
+            );
+        }
+
+        if config.rotate_interval == Some(0) {
+            return Err(
+                ErrorKind::ConfigField(
+                    "rotate_interval".to_owned(),
+                    "must be at least 1".to_owned(),
+                ).into(),
+            );
+        }
+
+        let reopen = Arc::new(AtomicBool::new(false));
+
+        // logrotate moves the file away and sends SIGHUP, after which a new file is opened.
+        {
+            let ref mut core = ctx.core.try_borrow_mut()?;
+            let handle = core.handle();
+            let hangups = core.run(Signal::new(SIGHUP, &handle))?;
+            let flag = reopen.clone();
+
+            handle.spawn(
+                hangups
+                    .for_each(move |_| {
+                        flag.store(true, Ordering::SeqCst);
+                        Ok(())
+                    })
+                    .map_err(|e| warn!("failed to listen for SIGHUP: {}", e)),
+            );
+        }
+
+        let mut writer = Writer::new(
+            PathBuf::from(config.path),
+            format.header(),
+            Rotation {
+                max_size: config.max_size,
+                interval: config.rotate_interval.map(Duration::from_secs),
+                retention: config.retention.unwrap_or(5),
+                gzip: config.gzip.unwrap_or(false),
+            },
+            reopen,
+        );
+
+        let worker = Worker::spawn(&format!("file-{}", ctx.id), 16, move |batch: Vec<u8>| {
+            if let Err(e) = writer.write(&batch) {
+                warn!("{}: failed to write: {}", writer.path.display(), e);
+            }
+        })?;
+
+        Ok(Box::new(FileOutputInstance {
+            id: ctx.id.clone(),
+            format: format,
+            batch: Mutex::new(Vec::new()),
+            worker: worker,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// The objects written by the snoop output, one per line.
+    Json,
+    Csv,
+    /// The InfluxDB line protocol.
+    Influx,
+}
+
+impl Format {
+    /// The line written at the top of every new file.
+    fn header(&self) -> Option<&'static str> {
+        match *self {
+            Format::Csv => Some("timestamp,key,tags,resource,value\n"),
+            _ => None,
+        }
+    }
+
+    /// Format a sample as a line, samples which aren't finite are skipped.
+    fn line(&self, id: &String, sample: &Sample) -> Result<Option<String>> {
+        if !sample.value.is_finite() {
+            return Ok(None);
+        }
+
+        let metric_id = &sample.metric_id;
+
+        let line = match *self {
+            Format::Json => {
+                let serialized = JsonLine {
+                    plugin_id: id,
+                    metric_id: metric_id,
+                    value: &sample.value,
+                    timestamp: &sample.timestamp,
+                };
+
+                format!("{}\n", serde_json::to_string(&serialized)?)
+            }
+            Format::Csv => {
+                let pairs = |tags: &[(String, String)]| -> String {
+                    let pairs: Vec<String> =
+                        tags.iter().map(|&(ref k, ref v)| format!("{}={}", k, v)).collect();
+                    csv_field(&pairs.join(";"))
+                };
+
+                format!(
+                    "{},{},{},{},{}\n",
+                    sample.timestamp,
+                    csv_field(metric_id.key().unwrap_or("")),
+                    pairs(metric_id.tags()),
+                    pairs(metric_id.resource()),
+                    sample.value
+                )
+            }
+            Format::Influx => {
+                let mut line = influx_escape(metric_id.key().unwrap_or("sysmon"), &[',', ' ']);
+
+                for &(ref k, ref v) in metric_id.tags().iter().chain(metric_id.resource()) {
+                    // empty tag values are not allowed by the protocol.
+                    if v.is_empty() {
+                        continue;
+                    }
+
+                    line.push(',');
+                    line.push_str(&influx_escape(k, &[',', '=', ' ']));
+                    line.push('=');
+                    line.push_str(&influx_escape(v, &[',', '=', ' ']));
+                }
+
+                format!("{} value={} {}\n", line, sample.value, sample.timestamp * 1_000_000)
+            }
+        };
+
+        Ok(Some(line))
+    }
+}
+
+/// A sample as a JSON object, like the ones of the snoop output together with the timestamp.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    plugin_id: &'a String,
+    metric_id: &'a MetricId,
+    value: &'a f64,
+    /// Milliseconds since the Unix epoch.
+    timestamp: &'a u64,
+}
+
+/// Quote a field if it contains characters which are part of the CSV syntax.
+fn csv_field(input: &str) -> String {
+    if input.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", input.replace('"', "\"\""))
+    } else {
+        input.to_owned()
+    }
+}
+
+/// Escape the given characters with a backslash.
+fn influx_escape(input: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        // a line can't span multiple lines.
+        let c = if c == '\n' { ' ' } else { c };
+
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+#[derive(Debug)]
+struct Rotation {
+    max_size: Option<u64>,
+    interval: Option<Duration>,
+    /// number of rotated files to keep, the oldest is removed beyond this.
+    retention: usize,
+    gzip: bool,
+}
+
+/// Appends to the file, rotating it as configured.
+///
+/// Rotated files are named after the path with a suffix of `.1` for the most recent, `.2` for the
+/// one before that, and so on, followed by `.gz` when compressed.
+struct Writer {
+    path: PathBuf,
+    header: Option<&'static str>,
+    rotation: Rotation,
+    /// set when the file should be reopened, like after it was moved by logrotate.
+    reopen: Arc<AtomicBool>,
+    file: Option<File>,
+    size: u64,
+    /// when the current file was created.
+    opened: SystemTime,
+}
+
+impl Writer {
+    fn new(
+        path: PathBuf,
+        header: Option<&'static str>,
+        rotation: Rotation,
+        reopen: Arc<AtomicBool>,
+    ) -> Writer {
+        Writer {
+            path: path,
+            header: header,
+            rotation: rotation,
+            reopen: reopen,
+            file: None,
+            size: 0,
+            opened: SystemTime::now(),
+        }
+    }
+
+    /// Path of the rotated file with the given number.
+    fn rotated(&self, n: usize) -> PathBuf {
+        let suffix = if self.rotation.gzip { ".gz" } else { "" };
+        PathBuf::from(format!("{}.{}{}", self.path.display(), n, suffix))
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+
+        let metadata = file.metadata()?;
+        self.size = metadata.len();
+
+        // a file with content is left over from before, like from an earlier run, so that
+        // restarts don't postpone its rotation.
+        self.opened = match self.size {
+            0 => SystemTime::now(),
+            _ => {
+                metadata.created().or_else(|_| metadata.modified()).unwrap_or_else(
+                    |_| SystemTime::now(),
+                )
+            }
+        };
+
+        if self.size == 0 {
+            if let Some(header) = self.header {
+                file.write_all(header.as_bytes())?;
+                self.size = header.len() as u64;
+            }
+        }
+
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Whether the current file should be rotated before appending the given number of bytes.
+    fn should_rotate(&self, len: usize) -> bool {
+        let header = self.header.map(|h| h.len() as u64).unwrap_or(0);
+
+        // a file with nothing but the header has nothing to rotate.
+        if self.size <= header {
+            return false;
+        }
+
+        if let Some(max_size) = self.rotation.max_size {
+            if self.size + len as u64 > max_size {
+                return true;
+            }
+        }
+
+        if let Some(interval) = self.rotation.interval {
+            // a creation time in the future, like after the clock was set back, never expires.
+            if self.opened.elapsed().map(|age| age >= interval).unwrap_or(false) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Move the current file to the first rotated file, shifting older ones.
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+
+        if self.rotation.retention == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+
+        for n in (1..self.rotation.retention).rev() {
+            if let Err(e) = fs::rename(self.rotated(n), self.rotated(n + 1)) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+
+        if self.rotation.gzip {
+            let mut encoder = GzEncoder::new(File::create(self.rotated(1))?, Compression::Default);
+            io::copy(&mut File::open(&self.path)?, &mut encoder)?;
+            encoder.finish()?;
+            fs::remove_file(&self.path)?;
+        } else {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.reopen.swap(false, Ordering::SeqCst) {
+            self.file = None;
+        }
+
+        if self.file.is_some() && self.should_rotate(data.len()) {
+            self.rotate()?;
+        }
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        let result = match self.file {
+            Some(ref mut file) => file.write_all(data),
+            None => return Ok(()),
+        };
+
+        if let Err(e) = result {
+            // start over with a fresh file handle next time.
+            self.file = None;
+            return Err(e.into());
+        }
+
+        self.size += data.len() as u64;
+        Ok(())
+    }
+}
+
+struct FileOutputInstance {
+    id: String,
+    format: Format,
+    batch: Mutex<Vec<u8>>,
+    worker: Worker<Vec<u8>>,
+}
+
+impl OutputInstance for FileOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        if let Some(line) = self.format.line(&self.id, sample)? {
+            self.batch.lock()?.extend(line.into_bytes());
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let batch = mem::replace(&mut *self.batch.lock()?, Vec::new());
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        self.worker.send(batch)
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(FileOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::env;
+    use std::io::Read;
+    use std::process;
+    use std::thread;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sysmon-file-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &PathBuf) -> String {
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    fn writer(path: &PathBuf, header: Option<&'static str>, rotation: Rotation) -> Writer {
+        Writer::new(path.clone(), header, rotation, Arc::new(AtomicBool::new(false)))
+    }
+
+    fn sample(value: f64) -> Sample {
+        let id = key("disk")
+            .tag("what", "used, total")
+            .tag("unit", "")
+            .resource("host", "db 1")
+            .build();
+
+        Sample {
+            metric_id: Arc::new(id),
+            value: value,
+            timestamp: 1500000000123,
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let id = "file".to_owned();
+
+        assert_eq!(
+            Some(
+                "{\"plugin_id\":\"file\",\"metric_id\":{\"key\":\"disk\",\"tags\":\
+                 [[\"what\",\"used, total\"],[\"unit\",\"\"]],\"resource\":[[\"host\",\"db 1\"]]},\
+                 \"value\":1.5,\"timestamp\":1500000000123}\n"
+                    .to_owned()
+            ),
+            Format::Json.line(&id, &sample(1.5)).unwrap()
+        );
+
+        assert_eq!(
+            Some("1500000000123,disk,\"what=used, total;unit=\",host=db 1,1.5\n".to_owned()),
+            Format::Csv.line(&id, &sample(1.5)).unwrap()
+        );
+
+        assert_eq!(
+            Some(
+                "disk,what=used\\,\\ total,host=db\\ 1 value=1.5 1500000000123000000\n".to_owned(),
+            ),
+            Format::Influx.line(&id, &sample(1.5)).unwrap()
+        );
+
+        assert_eq!(None, Format::Influx.line(&id, &sample(::std::f64::NAN)).unwrap());
+        assert_eq!("\"a \"\"b\"\"\"", csv_field("a \"b\""));
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = temp_dir("size");
+        let path = dir.join("metrics.csv");
+
+        let mut writer = writer(
+            &path,
+            Some("h\n"),
+            Rotation {
+                max_size: Some(10),
+                interval: None,
+                retention: 2,
+                gzip: false,
+            },
+        );
+
+        for line in &["a1\n", "a2\n", "b1\n", "b2\n", "c1\n", "c2\n", "d1\n"] {
+            writer.write(line.as_bytes()).unwrap();
+        }
+
+        // every file gets a header, and holds two lines before it exceeds the maximum size.
+        assert_eq!("h\nd1\n", read(&path));
+        assert_eq!("h\nc1\nc2\n", read(&dir.join("metrics.csv.1")));
+        assert_eq!("h\nb1\nb2\n", read(&dir.join("metrics.csv.2")));
+        assert!(!dir.join("metrics.csv.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_by_time_gzip() {
+        let dir = temp_dir("gzip");
+        let path = dir.join("metrics.json");
+
+        let mut writer = writer(
+            &path,
+            None,
+            Rotation {
+                max_size: None,
+                interval: Some(Duration::from_secs(3600)),
+                retention: 1,
+                gzip: true,
+            },
+        );
+
+        writer.write(b"first\n").unwrap();
+        writer.write(b"second\n").unwrap();
+
+        // pretend the file was opened long ago.
+        writer.opened = SystemTime::now() - Duration::from_secs(3600);
+        writer.write(b"third\n").unwrap();
+
+        assert_eq!("third\n", read(&path));
+
+        let mut content = String::new();
+        GzDecoder::new(File::open(dir.join("metrics.json.1.gz")).unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+
+        assert_eq!("first\nsecond\n", content);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_existing() {
+        let dir = temp_dir("existing");
+        let path = dir.join("metrics.json");
+
+        // left over from an earlier run.
+        File::create(&path).unwrap().write_all(b"old\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let started = SystemTime::now();
+
+        let mut writer = writer(
+            &path,
+            None,
+            Rotation {
+                max_size: None,
+                interval: Some(Duration::from_secs(3600)),
+                retention: 1,
+                gzip: false,
+            },
+        );
+
+        writer.write(b"new\n").unwrap();
+        assert!(writer.opened < started);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = temp_dir("reopen");
+        let path = dir.join("metrics.influx");
+
+        let mut writer = writer(
+            &path,
+            None,
+            Rotation {
+                max_size: None,
+                interval: None,
+                retention: 5,
+                gzip: false,
+            },
+        );
+
+        writer.write(b"a\n").unwrap();
+
+        // as done by logrotate, before sending SIGHUP.
+        fs::rename(&path, dir.join("moved")).unwrap();
+        writer.write(b"b\n").unwrap();
+        writer.reopen.store(true, Ordering::SeqCst);
+        writer.write(b"c\n").unwrap();
+
+        assert_eq!("a\nb\n", read(&dir.join("moved")));
+        assert_eq!("c\n", read(&path));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Input plugin that accepts metrics pushed in the Graphite plaintext protocol.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::graphite::*;
+use super::listener::{bind_tcp, bind_udp, Received};
+use super::utils::lines;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Deserialize, Debug)]
+struct GraphiteListenerInputConfig {
+    /// TCP address to bind.
+    tcp: Option<SocketAddr>,
+    /// UDP address to bind.
+    udp: Option<SocketAddr>,
+    /// Templates used to map paths to keys and tags, like `servers.* .host.measurement*`.
+    templates: Option<Vec<String>>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct GraphiteListenerInput {}
+
+impl Input for GraphiteListenerInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: GraphiteListenerInputConfig = ctx.decode_config()?;
+
+        let mut templates = Vec::new();
+
+        for template in config.templates.unwrap_or_else(Vec::new) {
+            templates.push(Template::parse(&template).map_err(|e| {
+                ErrorKind::ConfigField("templates".to_owned(), e)
+            })?);
+        }
+
+        let listener = Arc::new(Mutex::new(GraphiteListener {
+            templates: templates,
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+            received: Received::new("graphite", ctx.id),
+        }));
+
+        let ref mut core = ctx.core.try_borrow_mut()?;
+        let handle = core.handle();
+
+        let tcp = match (config.tcp, config.udp) {
+            (None, None) => {
+                Some("127.0.0.1:2003".parse::<SocketAddr>().map_err(|e| {
+                    ErrorKind::Message(e.to_string())
+                })?)
+            }
+            (tcp, _) => tcp,
+        };
+
+        if let Some(tcp) = tcp {
+            bind_tcp(&handle, &tcp, ingest(listener.clone()))?;
+        }
+
+        if let Some(udp) = config.udp {
+            bind_udp(&handle, &udp, ingest(listener.clone()))?;
+        }
+
+        Ok(Box::new(
+            GraphiteListenerInputInstance { listener: listener },
+        ))
+    }
+}
+
+/// Build a callback which feeds received data into the listener.
+fn ingest(listener: Arc<Mutex<GraphiteListener>>) -> impl Fn(&[u8]) + Clone + 'static {
+    move |buffer: &[u8]| match listener.lock() {
+        Ok(mut listener) => listener.ingest(buffer),
+        Err(e) => warn!("failed to lock listener: {}", e),
+    }
+}
+
+/// A template mapping the components of a path to a key and tags.
+///
+/// Each part of the template names what the corresponding path component is used for.
+/// `measurement` components are joined to form the key, `measurement*` consumes all remaining
+/// components, empty parts are skipped, and any other name becomes a tag.
+#[derive(Debug, PartialEq)]
+struct Template {
+    /// Only paths whose leading components match this filter are mapped, `*` matches anything.
+    filter: Option<Vec<String>>,
+    parts: Vec<String>,
+}
+
+impl Template {
+    /// Parse a template on the form `[<filter> ]<template>`.
+    fn parse(input: &str) -> ::std::result::Result<Template, String> {
+        let mut it = input.split_whitespace();
+
+        let (filter, template) = match (it.next(), it.next(), it.next()) {
+            (Some(template), None, None) => (None, template),
+            (Some(filter), Some(template), None) => (Some(filter), template),
+            _ => return Err(format!("bad template: {}", input)),
+        };
+
+        let parts: Vec<String> = template.split('.').map(str::to_owned).collect();
+
+        if !parts.iter().any(|p| p == "measurement" || p == "measurement*") {
+            return Err(format!("template has no measurement: {}", input));
+        }
+
+        Ok(Template {
+            filter: filter.map(|f| f.split('.').map(str::to_owned).collect()),
+            parts: parts,
+        })
+    }
+
+    fn matches(&self, components: &[&str]) -> bool {
+        match self.filter {
+            None => true,
+            Some(ref filter) => {
+                filter.len() <= components.len() &&
+                    filter.iter().zip(components.iter()).all(
+                        |(f, c)| f == "*" || f == c,
+                    )
+            }
+        }
+    }
+
+    /// Map the components of a path to a key and tags.
+    fn apply(&self, components: &[&str]) -> (String, Vec<(String, String)>) {
+        let mut measurement = Vec::new();
+        let mut tags: Vec<(String, Vec<&str>)> = Vec::new();
+
+        for (i, (part, component)) in self.parts.iter().zip(components.iter()).enumerate() {
+            match part.as_str() {
+                "" => {}
+                "measurement" => measurement.push(*component),
+                "measurement*" => {
+                    measurement.extend(&components[i..]);
+                    break;
+                }
+                tag => {
+                    if let Some(entry) = tags.iter_mut().find(|t| t.0 == tag) {
+                        entry.1.push(component);
+                        continue;
+                    }
+
+                    tags.push((tag.to_owned(), vec![component]));
+                }
+            }
+        }
+
+        let tags = tags.into_iter().map(|(k, v)| (k, v.join("."))).collect();
+        (measurement.join("."), tags)
+    }
+}
+
+struct GraphiteListener {
+    templates: Vec<Template>,
+    tags: BTreeMap<String, String>,
+    received: Received,
+}
+
+impl GraphiteListener {
+    fn metric_id(&self, path: &str) -> MetricIdBuilder {
+        let components: Vec<&str> = path.split('.').collect();
+
+        let (name, path_tags) = match self.templates.iter().find(|t| t.matches(&components)) {
+            Some(template) => template.apply(&components),
+            None => (path.to_owned(), Vec::new()),
+        };
+
+        let mut builder = key(&name);
+
+        for (k, v) in &self.tags {
+            builder = builder.tag(k, v);
+        }
+
+        for (k, v) in path_tags {
+            builder = builder.tag(&k, &v);
+        }
+
+        builder
+    }
+
+    pub fn ingest(&mut self, buffer: &[u8]) {
+        for line in lines(buffer) {
+            match parse_graphite_line(&line).to_full_result() {
+                Ok(line) => {
+                    let id = self.metric_id(&line.path);
+                    self.received.insert(id, line.value);
+                }
+                Err(_) => {
+                    debug!("malformed line: {:?}", String::from_utf8_lossy(&line));
+                    self.received.malformed();
+                }
+            }
+        }
+    }
+}
+
+struct GraphiteListenerInputInstance {
+    listener: Arc<Mutex<GraphiteListener>>,
+}
+
+impl fmt::Debug for GraphiteListenerInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GraphiteListenerInputInstance")
+    }
+}
+
+impl InputInstance for GraphiteListenerInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        Ok(self.listener.lock()?.received.flush())
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(GraphiteListenerInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find_sample;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    fn listener(templates: &[&str]) -> GraphiteListener {
+        GraphiteListener {
+            templates: templates.iter().map(|t| Template::parse(t).unwrap()).collect(),
+            tags: BTreeMap::new(),
+            received: Received::new("graphite", "graphite"),
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let l = listener(&["servers.* .host.measurement*", "region.host.measurement.measurement"]);
+
+        let id = l.metric_id("servers.web01.cpu.load").build();
+        assert_eq!(key("cpu.load").tag("host", "web01").build(), id);
+
+        let id = l.metric_id("eu.db01.disk.used.extra").build();
+        assert_eq!(
+            key("disk.used").tag("region", "eu").tag("host", "db01").build(),
+            id
+        );
+
+        assert!(Template::parse("servers.*.host").is_err());
+        assert!(Template::parse("a b c").is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut l = listener(&[]);
+        l.ingest(b"foo.bar 1 1500000000\nfoo.bar\nfoo.baz 2\n");
+
+        let samples = l.received.flush();
+        assert_eq!(Some(1f64), find_sample(&samples, &key("foo.bar").build()));
+        assert_eq!(Some(2f64), find_sample(&samples, &key("foo.baz").build()));
+
+        let malformed = key("graphite")
+            .tag("instance", "graphite")
+            .tag("what", "malformed-lines")
+            .build();
+        assert_eq!(Some(1f64), find_sample(&samples, &malformed));
+    }
+
+    #[test]
+    fn test_tcp() {
+        let mut core = Core::new().unwrap();
+        let l = Arc::new(Mutex::new(listener(&[])));
+
+        let addr = bind_tcp(
+            &core.handle(),
+            &"127.0.0.1:0".parse().unwrap(),
+            ingest(l.clone()),
+        ).unwrap();
+
+        let mut client = TcpStream::connect(&addr).unwrap();
+        client.write_all(b"not a metric\n\xff\xfe 1\nfoo.bar 42\n").unwrap();
+
+        let mut samples = Vec::new();
+
+        // malformed lines, even ones which aren't UTF-8, shouldn't drop the connection.
+        for _ in 0..100 {
+            core.turn(Some(Duration::from_millis(10)));
+            samples.extend(l.lock().unwrap().received.flush());
+
+            if find_sample(&samples, &key("foo.bar").build()).is_some() {
+                break;
+            }
+        }
+
+        assert_eq!(Some(42f64), find_sample(&samples, &key("foo.bar").build()));
+
+        let malformed = key("graphite")
+            .tag("instance", "graphite")
+            .tag("what", "malformed-lines")
+            .build();
+        let count: f64 = samples
+            .iter()
+            .filter(|s| *s.metric_id == malformed)
+            .map(|s| s.value)
+            .sum();
+        assert_eq!(2f64, count);
+    }
+}
@@ -0,0 +1,195 @@
+//! Input plugin that polls HAProxy statistics, from the CSV stats page or the stats socket.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::haproxy::*;
+use super::http_poller::{self, StatusFormat};
+
+use std::sync::Arc;
+
+/// Fields reported as gauges, with their unit.
+const GAUGES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("qcur", "queued", "requests"),
+    ("scur", "sessions", "sessions"),
+    ("slim", "session-limit", "sessions"),
+    ("weight", "weight", "weight"),
+    ("act", "active-servers", "servers"),
+    ("bck", "backup-servers", "servers"),
+    ("rtime", "response-time", "ms"),
+];
+
+/// Fields which are counters, reported as rates.
+const COUNTERS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("stot", "sessions", "sessions/s"),
+    ("bin", "received", "bytes/s"),
+    ("bout", "sent", "bytes/s"),
+    ("dreq", "denied-requests", "requests/s"),
+    ("dresp", "denied-responses", "responses/s"),
+    ("ereq", "request-errors", "requests/s"),
+    ("econ", "connection-errors", "connections/s"),
+    ("eresp", "response-errors", "responses/s"),
+    ("wretr", "retries", "retries/s"),
+    ("wredis", "redispatches", "redispatches/s"),
+    ("chkfail", "check-failures", "checks/s"),
+    ("req_tot", "requests", "requests/s"),
+    ("hrsp_1xx", "responses-1xx", "responses/s"),
+    ("hrsp_2xx", "responses-2xx", "responses/s"),
+    ("hrsp_3xx", "responses-3xx", "responses/s"),
+    ("hrsp_4xx", "responses-4xx", "responses/s"),
+    ("hrsp_5xx", "responses-5xx", "responses/s"),
+    ("hrsp_other", "responses-other", "responses/s"),
+];
+
+/// Name of the type of a row.
+fn row_type(row: &StatRow) -> &'static str {
+    match row.get("type") {
+        Some("0") => "frontend",
+        Some("1") => "backend",
+        Some("2") => "server",
+        Some("3") => "listener",
+        _ => "unknown",
+    }
+}
+
+/// Whether a status means the proxy or server is available, like `UP`, `UP 1/3` or `OPEN`.
+fn is_up(status: &str) -> bool {
+    status.starts_with("UP") || status == "OPEN" || status == "no check"
+}
+
+pub struct Haproxy {}
+
+impl StatusFormat for Haproxy {
+    fn key(&self) -> &'static str {
+        "haproxy"
+    }
+
+    fn default_target(&self) -> &'static str {
+        "http://127.0.0.1/haproxy?stats;csv"
+    }
+
+    fn socket_request(&self) -> Option<&'static [u8]> {
+        Some(b"show stat\n")
+    }
+
+    fn convert(
+        &self,
+        base: &MetricIdBuilder,
+        page: &str,
+        readings: &mut Vec<(Arc<MetricId>, f64)>,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) -> Result<()> {
+        for row in parse_stats_csv(page).map_err(ErrorKind::Message)? {
+            let row_base = base.clone()
+                .tag("proxy", &row.proxy)
+                .tag("server", &row.server)
+                .tag("type", row_type(&row));
+
+            let id = |what: &str, unit: &str| {
+                Arc::new(row_base.clone().tag("what", what).tag("unit", unit).build())
+            };
+
+            if let Some(status) = row.get("status") {
+                let up = if is_up(status) { 1f64 } else { 0f64 };
+                readings.push((Arc::new(row_base.clone().tag("what", "status").build()), up));
+            }
+
+            for &(field, what, unit) in GAUGES {
+                if let Some(value) = row.get(field).and_then(|v| v.parse::<f64>().ok()) {
+                    readings.push((id(what, unit), value));
+                }
+            }
+
+            for &(field, what, unit) in COUNTERS {
+                if let Some(value) = row.get(field).and_then(|v| v.parse::<u64>().ok()) {
+                    counters.push((id(what, unit), value));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct HaproxyInput {}
+
+impl Input for HaproxyInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        http_poller::setup(ctx.id, ctx.decode_config()?, Box::new(Haproxy {}))
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(HaproxyInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STATS: &'static str = "# pxname,svname,qcur,qmax,scur,smax,slim,stot,bin,bout,dreq,\
+        dresp,ereq,econ,eresp,wretr,wredis,status,weight,act,bck,chkfail,chkdown,lastchg,\
+        downtime,qlimit,pid,iid,sid,throttle,lbtot,tracked,type,rate,rate_lim,rate_max,\
+        check_status,check_code,check_duration,hrsp_1xx,hrsp_2xx,hrsp_3xx,hrsp_4xx,hrsp_5xx,\
+        hrsp_other,hanafail,req_rate,req_rate_max,req_tot,\n\
+        http-in,FRONTEND,,,3,10,2000,120,5120,10240,0,0,1,,,,,OPEN,,,,,,,,,1,2,0,,,,0,\
+        1,0,5,,,,0,100,5,10,1,0,,1,5,116,\n\
+        app,web1,0,0,1,4,,60,2048,4096,,0,,0,0,0,0,UP,1,1,0,0,0,100,0,,1,3,1,,60,,2,\
+        0,,3,L4OK,,0,0,55,2,3,0,0,0,,,,,\n\
+        app,web2,0,0,0,3,,0,0,0,,0,,5,0,0,0,DOWN,1,1,0,3,1,10,10,,1,3,2,,0,,2,\
+        0,,0,L4CON,,0,0,0,0,0,0,0,0,,,,,\n\
+        app,BACKEND,0,0,1,4,200,60,2048,4096,0,0,,5,0,0,0,UP,2,2,0,,1,100,0,,1,3,0,,60,,1,\
+        0,,3,,,,0,55,2,3,0,0,,,,60,\n";
+
+    #[test]
+    fn test_convert() {
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        let base = key("haproxy");
+        Haproxy {}
+            .convert(&base, STATS, &mut readings, &mut counters)
+            .unwrap();
+
+        let row = |proxy: &str, server: &str, kind: &str| {
+            base.clone()
+                .tag("proxy", proxy)
+                .tag("server", server)
+                .tag("type", kind)
+        };
+
+        let status = |proxy: &str, server: &str, kind: &str| {
+            Arc::new(row(proxy, server, kind).tag("what", "status").build())
+        };
+
+        assert!(readings.contains(&(status("http-in", "FRONTEND", "frontend"), 1f64)));
+        assert!(readings.contains(&(status("app", "web1", "server"), 1f64)));
+        assert!(readings.contains(&(status("app", "web2", "server"), 0f64)));
+        assert!(readings.contains(&(status("app", "BACKEND", "backend"), 1f64)));
+
+        let sessions = row("http-in", "FRONTEND", "frontend")
+            .tag("what", "sessions")
+            .tag("unit", "sessions")
+            .build();
+        assert!(readings.contains(&(Arc::new(sessions), 3f64)));
+
+        let active = row("app", "BACKEND", "backend")
+            .tag("what", "active-servers")
+            .tag("unit", "servers")
+            .build();
+        assert!(readings.contains(&(Arc::new(active), 2f64)));
+
+        let requests = row("http-in", "FRONTEND", "frontend")
+            .tag("what", "requests")
+            .tag("unit", "requests/s")
+            .build();
+        assert!(counters.contains(&(Arc::new(requests), 116)));
+
+        let errors = row("app", "web2", "server")
+            .tag("what", "connection-errors")
+            .tag("unit", "connections/s")
+            .build();
+        assert!(counters.contains(&(Arc::new(errors), 5)));
+    }
+}
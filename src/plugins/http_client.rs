@@ -0,0 +1,488 @@
+//! Minimal blocking HTTP/1.1 client used by plugins which talk to HTTP endpoints.
+//!
+//! Requests are generic over the stream, so that the same client can be used over TCP and Unix
+//! sockets. Every request uses its own connection.
+//!
+//! `https://` urls are requested over TLS, verifying the certificate chain against the trusted
+//! certificates of the system (and an optional extra CA) and the certificate against the host.
+
+use errors::*;
+
+use native_tls::{Certificate, TlsConnector, TlsStream};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A parsed `http://` or `https://` url.
+#[derive(Clone)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    /// path including any query string.
+    pub path: String,
+    /// if the url is https.
+    pub tls: bool,
+    /// CA trusted in addition to the system's, for https.
+    ca: Option<Certificate>,
+}
+
+impl fmt::Debug for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = if self.tls { "https" } else { "http" };
+        write!(f, "Url({}://{}{})", scheme, self.authority(), self.path)
+    }
+}
+
+impl Url {
+    pub fn parse(input: &str) -> Result<Url> {
+        let (tls, rest) = if input.starts_with("http://") {
+            (false, &input["http://".len()..])
+        } else if input.starts_with("https://") {
+            (true, &input["https://".len()..])
+        } else {
+            return Err(ErrorKind::Message(format!("unsupported url: {}", input)).into());
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority.ends_with(']') => {
+                let port = authority[i + 1..].parse::<u16>().map_err(|e| {
+                    ErrorKind::Message(format!("bad port in url: {}: {}", input, e))
+                })?;
+
+                (&authority[..i], port)
+            }
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+
+        if host.is_empty() {
+            return Err(ErrorKind::Message(format!("missing host in url: {}", input)).into());
+        }
+
+        Ok(Url {
+            host: host.to_owned(),
+            port: port,
+            path: path.to_owned(),
+            tls: tls,
+            ca: None,
+        })
+    }
+
+    /// Trust the CA certificate in the given PEM file for https, in addition to the system's.
+    pub fn with_ca(mut self, path: &str) -> Result<Url> {
+        let mut pem = Vec::new();
+        File::open(path)?.read_to_end(&mut pem)?;
+
+        let ca = Certificate::from_pem(&pem).map_err(|e| {
+            ErrorKind::Message(format!("bad certificate: {}: {}", path, e))
+        })?;
+
+        self.ca = Some(ca);
+        Ok(self)
+    }
+
+    /// The `host:port` of the url.
+    pub fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// The host of the url, without the brackets of an IPv6 address.
+    fn bare_host(&self) -> &str {
+        self.host.trim_left_matches('[').trim_right_matches(']')
+    }
+
+    /// Connect to the host of the url.
+    pub fn connect(&self, timeout: Duration) -> Result<TcpStream> {
+        let host = self.bare_host();
+
+        let addr = match (host, self.port).to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => return Err(ErrorKind::Message(format!("no address for: {}", host)).into()),
+        };
+
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(stream)
+    }
+
+    /// Connect to the host of the url, and perform the TLS handshake for https.
+    pub fn open(&self, timeout: Duration) -> Result<Connection> {
+        let stream = self.connect(timeout)?;
+
+        if !self.tls {
+            return Ok(Connection::Plain(stream));
+        }
+
+        let mut builder = TlsConnector::builder();
+
+        if let Some(ref ca) = self.ca {
+            builder.add_root_certificate(ca.clone());
+        }
+
+        let connector = builder.build().map_err(|e| {
+            ErrorKind::Message(format!("failed to set up tls: {}", e))
+        })?;
+
+        let host = self.bare_host();
+
+        match connector.connect(host, stream) {
+            Ok(stream) => Ok(Connection::Tls(stream)),
+            Err(e) => Err(
+                ErrorKind::Message(format!("tls handshake with {} failed: {}", host, e)).into(),
+            ),
+        }
+    }
+}
+
+/// A connection opened for a url.
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.read(buf),
+            Connection::Tls(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.write(buf),
+            Connection::Tls(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Connection::Plain(ref mut stream) => stream.flush(),
+            Connection::Tls(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Get the first header with the given name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.0.to_lowercase() == name.to_lowercase())
+            .map(|h| h.1.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+}
+
+/// Perform a request over the given stream.
+pub fn request<S: Read + Write>(
+    mut stream: S,
+    host: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<Response> {
+    {
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method,
+            path,
+            host
+        );
+
+        for &(name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        if !body.is_empty() || method == "POST" || method == "PUT" {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+    }
+
+    read_response(BufReader::new(stream))
+}
+
+/// Perform a GET request against the given url.
+pub fn get(url: &Url, headers: &[(&str, &str)], timeout: Duration) -> Result<Response> {
+    let stream = url.open(timeout)?;
+    request(stream, &url.authority(), "GET", &url.path, headers, &[])
+}
+
+/// Perform a POST request against the given url.
+pub fn post(
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<Response> {
+    let stream = url.open(timeout)?;
+    request(stream, &url.authority(), "POST", &url.path, headers, body)
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line.trim_right_matches(|c| c == '\r' || c == '\n').to_owned())
+}
+
+fn read_response<R: BufRead>(mut reader: R) -> Result<Response> {
+    let status_line = read_line(&mut reader)?;
+
+    let status = match status_line.split_whitespace().nth(1).map(str::parse::<u16>) {
+        Some(Ok(status)) if status_line.starts_with("HTTP/") => status,
+        _ => {
+            return Err(
+                ErrorKind::Message(format!("bad status line: {:?}", status_line)).into(),
+            )
+        }
+    };
+
+    let mut headers = Vec::new();
+
+    loop {
+        let line = read_line(&mut reader)?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(i) = line.find(':') {
+            headers.push((line[..i].trim().to_owned(), line[i + 1..].trim().to_owned()));
+        }
+    }
+
+    let mut response = Response {
+        status: status,
+        headers: headers,
+        body: Vec::new(),
+    };
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .map(|t| t.to_lowercase() == "chunked")
+        .unwrap_or(false);
+
+    let content_length = match response.header("Content-Length") {
+        Some(length) => Some(length.parse::<usize>().map_err(|e| {
+            ErrorKind::Message(format!("bad content length: {}", e))
+        })?),
+        None => None,
+    };
+
+    let mut body = Vec::new();
+
+    if chunked {
+        loop {
+            let line = read_line(&mut reader)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+
+            let size = usize::from_str_radix(size, 16).map_err(|e| {
+                ErrorKind::Message(format!("bad chunk size: {:?}: {}", size, e))
+            })?;
+
+            if size == 0 {
+                break;
+            }
+
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            read_line(&mut reader)?;
+        }
+    } else if let Some(length) = content_length {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+
+    response.body = body;
+    Ok(response)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use plugins::test_helpers::{test_ca_file, tls_acceptor};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    /// Read a request from the stream and answer it with the canned response.
+    fn answer<S: Read + Write>(stream: S, response: &[u8]) -> Vec<u8> {
+        let mut reader = BufReader::new(stream);
+        let mut request = Vec::new();
+        let mut content_length = 0;
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            request.extend(line.as_bytes());
+
+            let lower = line.to_lowercase();
+
+            if lower.starts_with("content-length:") {
+                content_length = lower["content-length:".len()..].trim().parse().unwrap();
+            }
+
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+        }
+
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).unwrap();
+        request.extend(body);
+
+        reader.get_mut().write_all(response).unwrap();
+        request
+    }
+
+    /// Serve a single canned response on a local port, returning its address and a handle that
+    /// resolves to the raw request received.
+    pub fn serve_once(response: Vec<u8>) -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            answer(stream, &response)
+        });
+
+        (addr, handle)
+    }
+
+    /// Like `serve_once`, but over TLS with the test certificate.
+    ///
+    /// Resolves to an empty request if the handshake fails.
+    pub fn serve_tls_once(response: Vec<u8>) -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+
+            match tls_acceptor().accept(stream) {
+                Ok(stream) => answer(stream, &response),
+                Err(_) => Vec::new(),
+            }
+        });
+
+        (addr, handle)
+    }
+
+    /// Serve the canned responses in order, one connection each, returning the raw requests.
+    pub fn serve(responses: Vec<Vec<u8>>) -> (SocketAddr, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            responses
+                .iter()
+                .map(|response| answer(listener.accept().unwrap().0, response))
+                .collect()
+        });
+
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_url() {
+        let url = Url::parse("http://localhost:9100/metrics?x=1").unwrap();
+        assert_eq!("localhost", url.host);
+        assert_eq!(9100, url.port);
+        assert_eq!("/metrics?x=1", url.path);
+
+        let url = Url::parse("http://example.com").unwrap();
+        assert_eq!(80, url.port);
+        assert_eq!("/", url.path);
+
+        let url = Url::parse("https://example.com/_bulk").unwrap();
+        assert!(url.tls);
+        assert_eq!(443, url.port);
+
+        assert!(Url::parse("ftp://example.com").is_err());
+        assert!(Url::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn test_chunked() {
+        let (addr, server) = serve_once(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"
+                .to_vec(),
+        );
+
+        let url = Url::parse(&format!("http://{}/path", addr)).unwrap();
+        let response = get(&url, &[("Accept", "text/plain")], Duration::from_secs(5)).unwrap();
+
+        assert_eq!(200, response.status);
+        assert_eq!(b"hello world".to_vec(), response.body);
+
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("GET /path HTTP/1.1\r\n"));
+        assert!(request.contains("Accept: text/plain\r\n"));
+    }
+
+    #[test]
+    fn test_post() {
+        let (addr, server) = serve_once(
+            b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        );
+
+        let url = Url::parse(&format!("http://{}/api", addr)).unwrap();
+        let response = post(&url, &[], b"payload", Duration::from_secs(5)).unwrap();
+
+        assert!(response.is_success());
+
+        let request = server.join().unwrap();
+        assert!(request.ends_with(b"\r\n\r\npayload"));
+    }
+
+    #[test]
+    fn test_https() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec();
+        let ca = test_ca_file("http-client");
+
+        let (addr, server) = serve_tls_once(ok.clone());
+        let url = Url::parse(&format!("https://127.0.0.1:{}/secure", addr.port()))
+            .unwrap()
+            .with_ca(ca.to_str().unwrap())
+            .unwrap();
+        let response = post(&url, &[], b"payload", Duration::from_secs(5)).unwrap();
+
+        assert_eq!(b"ok".to_vec(), response.body);
+        assert!(server.join().unwrap().starts_with(b"POST /secure HTTP/1.1\r\n"));
+
+        // the certificate is not trusted without the CA.
+        let (addr, server) = serve_tls_once(ok);
+        let url = Url::parse(&format!("https://127.0.0.1:{}/secure", addr.port())).unwrap();
+
+        assert!(get(&url, &[], Duration::from_secs(5)).is_err());
+        assert!(server.join().unwrap().is_empty());
+    }
+}
@@ -1,9 +1,135 @@
-use errors::*;
+//! Input plugin that periodically polls status pages, like the ones served by web servers and
+//! load balancers.
+//!
+//! The fetching, tagging and rate calculation is shared by the inputs for specific status pages,
+//! which only provide a `StatusFormat`.
+
+use metric::*;
 use plugin::*;
+use errors::*;
+use super::background::{Background, Readings};
+use super::http_client::{self, Url};
+use super::socket::Endpoint;
+use super::nginx_status::NginxStatus;
+use super::apache_status::ApacheStatus;
+use super::haproxy::Haproxy;
+
+use futures::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize, Debug)]
-struct HttpInputConfig {
-    target: String,
+pub struct HttpPollerConfig {
+    /// Url of the status page.
+    target: Option<String>,
+    /// Path to a PEM file with a CA to trust for https, in addition to the system's.
+    ca: Option<String>,
+    /// Path to a Unix stats socket, for formats which support one.
+    unix: Option<String>,
+    /// Format of the status page, only used by http_poller.
+    format: Option<String>,
+    /// Timeout in seconds for fetching the page, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+/// Converts a fetched status page into readings.
+pub trait StatusFormat: Send {
+    /// Key of all samples.
+    fn key(&self) -> &'static str;
+
+    /// Url polled if no target is configured.
+    fn default_target(&self) -> &'static str;
+
+    /// Request sent over a Unix socket, or `None` if the format can only be fetched over HTTP.
+    fn socket_request(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// Convert the page into gauges, and counters which are reported as rates.
+    fn convert(
+        &self,
+        base: &MetricIdBuilder,
+        page: &str,
+        readings: &mut Vec<(Arc<MetricId>, f64)>,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) -> Result<()>;
+}
+
+/// Where the status page is fetched from.
+enum Source {
+    Http(Url),
+    Socket(Endpoint, &'static [u8]),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Http(ref url) => write!(f, "{}", url.authority()),
+            Source::Socket(ref endpoint, _) => write!(f, "{}", endpoint),
+        }
+    }
+}
+
+/// Set up an input instance polling a status page in the given format.
+pub fn setup(
+    id: &str,
+    config: HttpPollerConfig,
+    format: Box<StatusFormat>,
+) -> Result<Box<InputInstance>> {
+    let source = match (config.target, config.unix) {
+        (Some(_), Some(_)) => {
+            return Err(
+                ErrorKind::ConfigField(
+                    "unix".to_owned(),
+                    "can't be combined with target".to_owned(),
+                ).into(),
+            )
+        }
+        (None, Some(unix)) => {
+            match format.socket_request() {
+                Some(request) => Source::Socket(Endpoint::Unix(PathBuf::from(unix)), request),
+                None => {
+                    return Err(
+                        ErrorKind::ConfigField(
+                            "unix".to_owned(),
+                            "not supported by this format".to_owned(),
+                        ).into(),
+                    )
+                }
+            }
+        }
+        (target, None) => {
+            let target = target.unwrap_or_else(|| format.default_target().to_owned());
+
+            let mut url = Url::parse(&target).map_err(|e| {
+                ErrorKind::ConfigField("target".to_owned(), e.to_string())
+            })?;
+
+            if let Some(ca) = config.ca {
+                url = url.with_ca(&ca).map_err(|e| {
+                    ErrorKind::ConfigField("ca".to_owned(), e.to_string())
+                })?;
+            }
+
+            Source::Http(url)
+        }
+    };
+
+    let poller = Poller {
+        source: source,
+        timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+        tags: config.tags.unwrap_or_else(BTreeMap::new),
+        format: format,
+        deltas: Deltas::new(),
+    };
+
+    Ok(Box::new(HttpInputInstance::new(id, poller)?))
 }
 
 #[derive(Debug)]
@@ -11,22 +137,259 @@ struct HttpInput {}
 
 impl Input for HttpInput {
     fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
-        let _c: HttpInputConfig = ctx.decode_config()?;
-        Ok(Box::new(HttpInputInstance::new()))
+        let mut config: HttpPollerConfig = ctx.decode_config()?;
+
+        let format: Box<StatusFormat> = match config.format.take() {
+            Some(ref format) if format == "nginx" => Box::new(NginxStatus {}),
+            Some(ref format) if format == "apache" => Box::new(ApacheStatus {}),
+            Some(ref format) if format == "haproxy" => Box::new(Haproxy {}),
+            Some(other) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "format".to_owned(),
+                        format!("expected nginx, apache or haproxy, got: {}", other),
+                    ).into(),
+                )
+            }
+            None => return Err(ErrorKind::MissingField("format".to_owned()).into()),
+        };
+
+        setup(ctx.id, config, format)
     }
 }
 
-#[derive(Debug)]
-struct HttpInputInstance {}
+struct Poller {
+    source: Source,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+    format: Box<StatusFormat>,
+    deltas: Deltas<Arc<MetricId>>,
+}
+
+impl Poller {
+    fn fetch(&self) -> Result<String> {
+        match self.source {
+            Source::Http(ref url) => {
+                let response = http_client::get(url, &[], self.timeout)?;
+
+                if !response.is_success() {
+                    return Err(
+                        ErrorKind::Message(format!("unexpected status: {}", response.status))
+                            .into(),
+                    );
+                }
+
+                Ok(String::from_utf8_lossy(&response.body).into_owned())
+            }
+            Source::Socket(ref endpoint, request) => {
+                let mut stream = endpoint.connect(self.timeout)?;
+                stream.write_all(request)?;
+
+                let mut page = Vec::new();
+                stream.read_to_end(&mut page)?;
+                Ok(String::from_utf8_lossy(&page).into_owned())
+            }
+        }
+    }
+
+    fn base(&self) -> MetricIdBuilder {
+        let mut base = key(self.format.key());
+
+        for (k, v) in &self.tags {
+            base = base.tag(k, v);
+        }
+
+        base.tag("instance", &self.source.to_string())
+    }
+
+    /// Poll the status page, reporting whether it could be fetched and understood as `up`.
+    pub fn scan(&mut self, now: Instant) -> Vec<(Arc<MetricId>, f64)> {
+        let base = self.base();
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        let result = self.fetch().and_then(|page| {
+            self.format.convert(&base, &page, &mut readings, &mut counters)
+        });
+
+        let up = match result {
+            Ok(()) => {
+                readings.extend(self.deltas.rates(now, counters));
+                1f64
+            }
+            Err(e) => {
+                warn!("{}: polling status failed: {}", self.source, e);
+                readings.clear();
+                0f64
+            }
+        };
+
+        readings.push((Arc::new(base.tag("what", "up").build()), up));
+        readings
+    }
+}
+
+struct HttpInputInstance {
+    /// readings of the last poll of the page.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for HttpInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HttpInputInstance")
+    }
+}
 
 impl HttpInputInstance {
-    pub fn new() -> HttpInputInstance {
-        return HttpInputInstance {};
+    pub fn new(id: &str, mut poller: Poller) -> Result<HttpInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("http-{}", id), move || {
+            results.set(poller.scan(Instant::now()))
+        })?;
+
+        Ok(HttpInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_millis(1000),
+        })
     }
 }
 
-impl InputInstance for HttpInputInstance {}
+impl InputInstance for HttpInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// The page is fetched on a thread of its own, so a slow or unreachable server never holds up
+    /// the updates of other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
 
 pub fn input() -> Result<Box<Input>> {
     Ok(Box::new(HttpInput {}))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::http_client::test::{serve_once, serve_tls_once};
+    use plugins::test_helpers::test_ca_file;
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread;
+
+    fn poller(target: &str) -> Poller {
+        Poller {
+            source: Source::Http(Url::parse(target).unwrap()),
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            format: Box::new(NginxStatus {}),
+            deltas: Deltas::new(),
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let page = b"HTTP/1.1 200 OK\r\n\r\n\
+            Active connections: 2\n\
+            server accepts handled requests\n 10 10 20\n\
+            Reading: 0 Writing: 1 Waiting: 1\n";
+
+        let (addr, server) = serve_once(page.to_vec());
+        let readings = poller(&format!("http://{}/nginx_status", addr)).scan(Instant::now());
+
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("GET /nginx_status HTTP/1.1\r\n"));
+
+        let base = key("nginx").tag("instance", &addr.to_string());
+
+        let active = base.clone()
+            .tag("what", "active")
+            .tag("unit", "connections")
+            .build();
+
+        assert!(readings.contains(&(Arc::new(active), 2f64)));
+        assert!(readings.contains(&(Arc::new(base.tag("what", "up").build()), 1f64)));
+    }
+
+    #[test]
+    fn test_https() {
+        let page = b"HTTP/1.1 200 OK\r\n\r\n\
+            Active connections: 2\n\
+            server accepts handled requests\n 10 10 20\n\
+            Reading: 0 Writing: 1 Waiting: 1\n";
+
+        let (addr, server) = serve_tls_once(page.to_vec());
+        let ca = test_ca_file("http-poller");
+
+        let url = Url::parse(&format!("https://127.0.0.1:{}/nginx_status", addr.port()))
+            .unwrap()
+            .with_ca(ca.to_str().unwrap())
+            .unwrap();
+
+        let mut poller = poller("http://127.0.0.1/");
+        poller.source = Source::Http(url);
+        let readings = poller.scan(Instant::now());
+        server.join().unwrap();
+
+        let up = key("nginx").tag("instance", &addr.to_string()).tag("what", "up").build();
+        assert!(readings.contains(&(Arc::new(up), 1f64)));
+    }
+
+    #[test]
+    fn test_down() {
+        let (addr, server) = serve_once(b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec());
+        let readings = poller(&format!("http://{}/nginx_status", addr)).scan(Instant::now());
+        server.join().unwrap();
+
+        let up = key("nginx").tag("instance", &addr.to_string()).tag("what", "up").build();
+        assert_eq!(vec![(Arc::new(up), 0f64)], readings);
+    }
+
+    #[test]
+    fn test_socket() {
+        let path = env::temp_dir().join(format!("sysmon-http-poller-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 64];
+            let n = stream.read(&mut buffer).unwrap();
+            stream.write_all(b"# pxname,svname,scur,status,type,\nweb,FRONTEND,3,OPEN,0,\n").unwrap();
+            buffer[..n].to_vec()
+        });
+
+        let mut poller = Poller {
+            source: Source::Socket(Endpoint::Unix(path.clone()), b"show stat\n"),
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            format: Box::new(Haproxy {}),
+            deltas: Deltas::new(),
+        };
+
+        let readings = poller.scan(Instant::now());
+        assert_eq!(b"show stat\n".to_vec(), server.join().unwrap());
+
+        let up = key("haproxy")
+            .tag("instance", &path.display().to_string())
+            .tag("what", "up")
+            .build();
+        assert!(readings.contains(&(Arc::new(up), 1f64)));
+        assert_eq!(3, readings.len());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+//! Input plugin that accepts metrics pushed in the InfluxDB line protocol.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::influx::*;
+use super::listener::{bind_tcp, bind_udp, Received};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Deserialize, Debug)]
+struct InfluxListenerInputConfig {
+    /// TCP address to bind.
+    tcp: Option<SocketAddr>,
+    /// UDP address to bind.
+    udp: Option<SocketAddr>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct InfluxListenerInput {}
+
+impl Input for InfluxListenerInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: InfluxListenerInputConfig = ctx.decode_config()?;
+
+        let listener = Arc::new(Mutex::new(InfluxListener {
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+            received: Received::new("influx", ctx.id),
+        }));
+
+        let ref mut core = ctx.core.try_borrow_mut()?;
+        let handle = core.handle();
+
+        let udp = match (config.tcp, config.udp) {
+            (None, None) => {
+                Some("127.0.0.1:8089".parse::<SocketAddr>().map_err(|e| {
+                    ErrorKind::Message(e.to_string())
+                })?)
+            }
+            (_, udp) => udp,
+        };
+
+        if let Some(tcp) = config.tcp {
+            bind_tcp(&handle, &tcp, ingest(listener.clone()))?;
+        }
+
+        if let Some(udp) = udp {
+            bind_udp(&handle, &udp, ingest(listener.clone()))?;
+        }
+
+        Ok(Box::new(InfluxListenerInputInstance { listener: listener }))
+    }
+}
+
+/// Build a callback which feeds received data into the listener.
+fn ingest(listener: Arc<Mutex<InfluxListener>>) -> impl Fn(&[u8]) + Clone + 'static {
+    move |buffer: &[u8]| match listener.lock() {
+        Ok(mut listener) => listener.ingest(buffer),
+        Err(e) => warn!("failed to lock listener: {}", e),
+    }
+}
+
+struct InfluxListener {
+    tags: BTreeMap<String, String>,
+    received: Received,
+}
+
+impl InfluxListener {
+    fn record(&mut self, line: InfluxLine) {
+        let mut builder = key(&line.measurement);
+
+        for (k, v) in &self.tags {
+            builder = builder.tag(k, v);
+        }
+
+        for (k, v) in line.tags {
+            builder = builder.tag(&k, &v);
+        }
+
+        for (field, value) in line.fields {
+            // string fields have no numeric value to report.
+            if let Some(value) = value.as_f64() {
+                self.received.insert(builder.clone().tag("what", &field), value);
+            }
+        }
+    }
+
+    pub fn ingest(&mut self, buffer: &[u8]) {
+        // lines are split as bytes, so that a line which isn't valid UTF-8 is counted as malformed
+        // instead of being parsed with replacement characters.
+        for line in buffer.split(|b| *b == b'\n') {
+            let line = match str::from_utf8(line) {
+                Ok(line) => line.trim_right_matches('\r'),
+                Err(e) => {
+                    debug!("malformed line: {:?}: {}", String::from_utf8_lossy(line), e);
+                    self.received.malformed();
+                    continue;
+                }
+            };
+
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match parse_influx_line(line) {
+                Ok(line) => self.record(line),
+                Err(e) => {
+                    debug!("malformed line: {:?}: {}", line, e);
+                    self.received.malformed();
+                }
+            }
+        }
+    }
+}
+
+struct InfluxListenerInputInstance {
+    listener: Arc<Mutex<InfluxListener>>,
+}
+
+impl fmt::Debug for InfluxListenerInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InfluxListenerInputInstance")
+    }
+}
+
+impl InputInstance for InfluxListenerInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        Ok(self.listener.lock()?.received.flush())
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(InfluxListenerInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find_sample;
+
+    #[test]
+    fn test_ingest() {
+        let mut tags = BTreeMap::new();
+        tags.insert("dc".to_owned(), "eu1".to_owned());
+
+        let mut l = InfluxListener {
+            tags: tags,
+            received: Received::new("influx", "influx"),
+        };
+
+        l.ingest(b"cpu,host=a usage=0.5,msg=\"hi\",count=3i\ncpu usage=bad\n\n# comment\n");
+        l.ingest(b"cpu,host=\xff value=1\r\n");
+
+        let samples = l.received.flush();
+
+        let cpu = key("cpu").tag("dc", "eu1").tag("host", "a");
+        assert_eq!(Some(0.5), find_sample(&samples, &cpu.clone().tag("what", "usage").build()));
+        assert_eq!(Some(3f64), find_sample(&samples, &cpu.clone().tag("what", "count").build()));
+        assert_eq!(None, find_sample(&samples, &cpu.clone().tag("what", "msg").build()));
+
+        let malformed = key("influx")
+            .tag("instance", "influx")
+            .tag("what", "malformed-lines")
+            .build();
+        assert_eq!(Some(2f64), find_sample(&samples, &malformed));
+        assert!(!samples.iter().any(|s| s.metric_id.tag("what") == Some("value")));
+    }
+}
@@ -0,0 +1,285 @@
+//! Input plugin that reports the distribution of hardware and software interrupts.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::interrupts::*;
+use super::utils::read_optional_file;
+
+use futures::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct InterruptsInputConfig {
+    /// How to aggregate interrupt counts, one of `irq`, `cpu` or `matrix`. Defaults to `irq`.
+    mode: Option<String>,
+    /// Path to hardware interrupts, defaults to /proc/interrupts.
+    interrupts: Option<String>,
+    /// Path to software interrupts, defaults to /proc/softirqs.
+    softirqs: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Total per interrupt, summed over all cpus.
+    Irq,
+    /// Total per cpu, summed over all interrupts.
+    Cpu,
+    /// Every interrupt on every cpu.
+    Matrix,
+}
+
+#[derive(Debug)]
+struct InterruptsInput {}
+
+impl Input for InterruptsInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: InterruptsInputConfig = ctx.decode_config()?;
+
+        let mode = match config.mode.as_ref().map(|s| s.as_str()) {
+            None | Some("irq") => Mode::Irq,
+            Some("cpu") => Mode::Cpu,
+            Some("matrix") => Mode::Matrix,
+            Some(other) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "mode".to_owned(),
+                        format!("expected one of irq, cpu or matrix, but got: {}", other),
+                    ).into(),
+                );
+            }
+        };
+
+        let sources = vec![
+            (
+                "interrupts",
+                PathBuf::from(config.interrupts.unwrap_or("/proc/interrupts".to_owned())),
+            ),
+            (
+                "softirqs",
+                PathBuf::from(config.softirqs.unwrap_or("/proc/softirqs".to_owned())),
+            ),
+        ];
+
+        Ok(Box::new(InterruptsInputInstance::new(mode, sources)))
+    }
+}
+
+/// Whether the interrupt is counted for the whole system instead of per cpu, like the `ERR` and
+/// `MIS` rows which only have a single value.
+fn is_global(interrupt: &Interrupt, cpus: &[u64]) -> bool {
+    interrupt.irq == "ERR" || interrupt.irq == "MIS" || interrupt.counts.len() != cpus.len()
+}
+
+/// Build the counters for all interrupts, aggregated according to mode.
+///
+/// Interrupts which are counted for the whole system are reported without a cpu in all modes.
+///
+/// CPUs going offline simply disappear from the table, which causes their series to be forgotten
+/// and any totals they contributed to to decrease, which is treated as a counter reset.
+fn counters(mode: Mode, what: &str, interrupts: &Interrupts) -> Vec<(Arc<MetricId>, u64)> {
+    let base = key("system").tag("what", what).tag("unit", "interrupts/s");
+
+    let mut counters = Vec::new();
+
+    let global = |interrupt: &Interrupt| {
+        let id = base.clone().tag("irq", &interrupt.irq).tag("name", &interrupt.name()).build();
+        (Arc::new(id), interrupt.total())
+    };
+
+    match mode {
+        Mode::Irq => {
+            for interrupt in &interrupts.interrupts {
+                let id = base.clone()
+                    .tag("irq", &interrupt.irq)
+                    .tag("name", &interrupt.name())
+                    .build();
+
+                counters.push((Arc::new(id), interrupt.total()));
+            }
+        }
+        Mode::Cpu => {
+            let mut totals: HashMap<u64, u64> = HashMap::new();
+
+            for interrupt in &interrupts.interrupts {
+                if is_global(interrupt, &interrupts.cpus) {
+                    counters.push(global(interrupt));
+                    continue;
+                }
+
+                for (cpu, count) in interrupts.cpus.iter().zip(interrupt.counts.iter()) {
+                    *totals.entry(*cpu).or_insert(0) += *count;
+                }
+            }
+
+            for (cpu, total) in totals {
+                let id = base.clone().tag("cpu", &cpu.to_string()).build();
+                counters.push((Arc::new(id), total));
+            }
+        }
+        Mode::Matrix => {
+            for interrupt in &interrupts.interrupts {
+                if is_global(interrupt, &interrupts.cpus) {
+                    counters.push(global(interrupt));
+                    continue;
+                }
+
+                let name = interrupt.name();
+
+                for (cpu, count) in interrupts.cpus.iter().zip(interrupt.counts.iter()) {
+                    let id = base.clone()
+                        .tag("irq", &interrupt.irq)
+                        .tag("name", &name)
+                        .tag("cpu", &cpu.to_string())
+                        .build();
+
+                    counters.push((Arc::new(id), *count));
+                }
+            }
+        }
+    }
+
+    counters
+}
+
+struct Metrics {
+    mode: Mode,
+    /// what to report each source as, and where to read it from.
+    sources: Vec<(&'static str, PathBuf)>,
+    deltas: Deltas<Arc<MetricId>>,
+    rates: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        let now = Instant::now();
+
+        let mut all = Vec::new();
+
+        for &(what, ref path) in &self.sources {
+            if let Some(content) = read_optional_file(path)? {
+                let interrupts = parse_interrupts(&content).to_full_result()?;
+                all.extend(counters(self.mode, what, &interrupts));
+            }
+        }
+
+        self.rates = self.deltas.rates(now, all);
+        Ok(())
+    }
+}
+
+struct InterruptsInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for InterruptsInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InterruptsInputInstance")
+    }
+}
+
+impl InterruptsInputInstance {
+    pub fn new(mode: Mode, sources: Vec<(&'static str, PathBuf)>) -> InterruptsInputInstance {
+        InterruptsInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                mode: mode,
+                sources: sources,
+                deltas: Deltas::new(),
+                rates: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for InterruptsInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.rates
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(InterruptsInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn interrupts() -> Interrupts {
+        let text = b"           CPU0       CPU2       \n \
+                     24:        100          5  PCI-MSI 524288-edge      eth0-TxRx-0\n \
+                     25:         10        200  PCI-MSI 524289-edge      eth0-TxRx-1\n\
+                     ERR:          7\n";
+
+        parse_interrupts(text).to_full_result().unwrap()
+    }
+
+    #[test]
+    fn test_counters_irq() {
+        let counters = counters(Mode::Irq, "interrupts", &interrupts());
+        assert_eq!(3, counters.len());
+        assert_eq!(105, counters[0].1);
+        assert_eq!(210, counters[1].1);
+    }
+
+    #[test]
+    fn test_counters_cpu() {
+        let mut counters = counters(Mode::Cpu, "interrupts", &interrupts());
+        counters.sort();
+
+        let expected = key("system")
+            .tag("what", "interrupts")
+            .tag("unit", "interrupts/s")
+            .tag("cpu", "2")
+            .build();
+
+        let err = key("system")
+            .tag("what", "interrupts")
+            .tag("unit", "interrupts/s")
+            .tag("irq", "ERR")
+            .tag("name", "ERR")
+            .build();
+
+        assert_eq!(3, counters.len());
+        assert_eq!((Arc::new(expected), 205), counters[1]);
+        assert_eq!((Arc::new(err), 7), counters[2]);
+    }
+
+    #[test]
+    fn test_counters_matrix() {
+        let counters = counters(Mode::Matrix, "interrupts", &interrupts());
+        assert_eq!(5, counters.len());
+        assert_eq!(None, counters[4].0.tag("cpu"));
+        assert_eq!(Some("ERR"), counters[4].0.tag("irq"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Input plugin that reports how close the system is to various kernel limits.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::file_nr::*;
+use parsers::inode_nr::*;
+use parsers::loadavg::*;
+use parsers::pid_max::*;
+use parsers::swaps::*;
+use super::utils::{read_file, read_optional_file};
+
+use futures::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug)]
+struct LimitsInput {}
+
+impl Input for LimitsInput {
+    fn setup(&self, _ctx: PluginContext) -> Result<Box<InputInstance>> {
+        Ok(Box::new(LimitsInputInstance::new()))
+    }
+}
+
+/// Calculate the saturation of a limit in percent.
+fn saturation(used: u64, max: u64) -> f64 {
+    if max == 0 {
+        return 0f64;
+    }
+
+    (used as f64 / max as f64) * 100f64
+}
+
+/// A current value, the limit it is bounded by, and how saturated it is.
+struct Limit {
+    current: (Arc<MetricId>, Gauge),
+    max: (Arc<MetricId>, Gauge),
+    saturation: (Arc<MetricId>, Gauge),
+}
+
+impl Limit {
+    pub fn new(base: MetricIdBuilder, what: &str, unit: &str) -> Limit {
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        Limit {
+            current: (id(what, unit), Gauge::new()),
+            max: (id(&format!("{}-max", what), unit), Gauge::new()),
+            saturation: (id(&format!("{}-saturation", what), "%"), Gauge::new()),
+        }
+    }
+
+    pub fn set(&mut self, current: u64, max: u64) {
+        self.current.1.set(current as f64);
+        self.max.1.set(max as f64);
+        self.saturation.1.set(saturation(current, max));
+    }
+
+    pub fn samples(&self, samples: &mut Samples) {
+        for &(ref id, ref gauge) in &[&self.current, &self.max, &self.saturation] {
+            samples.push(Sample::new(id.clone(), gauge.snapshot()));
+        }
+    }
+}
+
+struct Metrics {
+    file_handles: Limit,
+    pids: Limit,
+    /// inodes in use, and allocated. Inode caches grow on demand, so there is no limit.
+    inodes: (Arc<MetricId>, Gauge),
+    inodes_allocated: (Arc<MetricId>, Gauge),
+    swaps: HashMap<String, Limit>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        let file_nr = parse_file_nr(&read_file("/proc/sys/fs/file-nr")?)
+            .to_full_result()?;
+        self.file_handles.set(file_nr.used(), file_nr.max);
+
+        let pid_max = parse_pid_max(&read_file("/proc/sys/kernel/pid_max")?)
+            .to_full_result()?;
+        let loadavg = parse_loadavg(&read_file("/proc/loadavg")?).to_full_result()?;
+        self.pids.set(loadavg.total, pid_max);
+
+        let inode_nr = parse_inode_nr(&read_file("/proc/sys/fs/inode-nr")?)
+            .to_full_result()?;
+        self.inodes.1.set(inode_nr.used() as f64);
+        self.inodes_allocated.1.set(inode_nr.nr_inodes as f64);
+
+        // the file is missing on kernels built without swap support.
+        let swaps = match read_optional_file("/proc/swaps")? {
+            Some(content) => parse_swaps(&content).to_full_result()?,
+            None => Vec::new(),
+        };
+
+        // forget about swap areas which have been removed.
+        self.swaps.retain(|filename, _| {
+            swaps.iter().any(|s| &s.filename == filename)
+        });
+
+        for swap in swaps {
+            let limit = self.swaps.entry(swap.filename.clone()).or_insert_with(|| {
+                Limit::new(key("system").tag("device", &swap.filename), "swap", "bytes")
+            });
+
+            limit.set(swap.used * 1024, swap.size * 1024);
+        }
+
+        Ok(())
+    }
+}
+
+struct LimitsInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for LimitsInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LimitsInputInstance")
+    }
+}
+
+impl LimitsInputInstance {
+    pub fn new() -> LimitsInputInstance {
+        let system = key("system");
+
+        let inodes = |what: &str| {
+            let id = system.clone().tag("what", what).tag("unit", "inodes").build();
+            (Arc::new(id), Gauge::new())
+        };
+
+        LimitsInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                file_handles: Limit::new(system.clone(), "file-handles", "handles"),
+                pids: Limit::new(system.clone(), "pids", "pids"),
+                inodes: inodes("inodes"),
+                inodes_allocated: inodes("inodes-allocated"),
+                swaps: HashMap::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for LimitsInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        let mut results = Vec::new();
+
+        m.file_handles.samples(&mut results);
+        m.pids.samples(&mut results);
+
+        for &(ref id, ref gauge) in &[&m.inodes, &m.inodes_allocated] {
+            results.push(Sample::new(id.clone(), gauge.snapshot()));
+        }
+
+        for swap in m.swaps.values() {
+            swap.samples(&mut results);
+        }
+
+        Ok(results)
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(LimitsInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_saturation() {
+        assert_eq!(50f64, saturation(512, 1024));
+        assert_eq!(0f64, saturation(10, 0));
+    }
+}
@@ -0,0 +1,237 @@
+//! Utilities for input plugins which listen for pushed metrics.
+
+use errors::*;
+use metric::*;
+use plugin::*;
+
+use futures::{future, Async, Future};
+use futures::stream::Stream;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self as std_io, BufReader, ErrorKind as IoErrorKind};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_core::net;
+use tokio_core::reactor::Handle;
+use tokio_io::io;
+use tokio_uds::UnixDatagram;
+
+/// Largest datagram accepted.
+const MAX_DATAGRAM: usize = 65536;
+
+fn report_and_discard<E: fmt::Display>(e: E) -> () {
+    info!("An error occured: {}", e);
+}
+
+/// Receive datagrams with `recv`, calling `on_datagram` for every datagram received.
+///
+/// Errors are logged and receiving carries on, so that a single failed receive doesn't stop the
+/// listener for the rest of the process.
+fn receive<R, F>(name: String, recv: R, on_datagram: F) -> Box<Future<Item = (), Error = ()>>
+where
+    R: Fn(&mut [u8]) -> std_io::Result<usize> + 'static,
+    F: Fn(&[u8]) + 'static,
+{
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+
+    Box::new(future::poll_fn(move || loop {
+        match recv(&mut buffer) {
+            Ok(n) => on_datagram(&buffer[..n]),
+            // the socket is registered to wake up the task once there is more to receive.
+            Err(ref e) if e.kind() == IoErrorKind::WouldBlock => return Ok(Async::NotReady),
+            Err(e) => warn!("{}: failed to receive: {}", name, e),
+        }
+    }))
+}
+
+/// Bind a UDP socket, calling `on_datagram` for every datagram received.
+///
+/// Returns the local address of the socket.
+pub fn bind_udp<F>(handle: &Handle, addr: &SocketAddr, on_datagram: F) -> Result<SocketAddr>
+where
+    F: Fn(&[u8]) + 'static,
+{
+    let socket = net::UdpSocket::bind(addr, handle)?;
+    let local_addr = socket.local_addr()?;
+
+    handle.spawn(receive(
+        local_addr.to_string(),
+        move |buffer| socket.recv(buffer),
+        on_datagram,
+    ));
+
+    Ok(local_addr)
+}
+
+/// Bind a TCP listener, calling `on_line` for every line received on any connection.
+///
+/// Returns the local address of the listener.
+pub fn bind_tcp<F>(handle: &Handle, addr: &SocketAddr, on_line: F) -> Result<SocketAddr>
+where
+    F: Fn(&[u8]) + Clone + 'static,
+{
+    let listener = net::TcpListener::bind(addr, handle)?;
+    let local_addr = listener.local_addr()?;
+
+    let connection_handle = handle.clone();
+
+    let accept = listener.incoming().map_err(report_and_discard).for_each(
+        move |(socket, addr)| {
+            debug!("connect: {}", addr);
+
+            let on_line = on_line.clone();
+
+            // lines are split as raw bytes, so that a line which isn't valid UTF-8 is only counted
+            // as malformed instead of dropping the connection.
+            let reader = (BufReader::new(socket), Vec::new());
+
+            let connection = future::loop_fn(reader, move |(reader, buffer)| {
+                let on_line = on_line.clone();
+
+                io::read_until(reader, b'\n', buffer).map(move |(reader, mut buffer)| {
+                    if buffer.is_empty() {
+                        return future::Loop::Break(());
+                    }
+
+                    on_line(&buffer);
+                    buffer.clear();
+                    future::Loop::Continue((reader, buffer))
+                })
+            });
+
+            connection_handle.spawn(connection.then(move |result| {
+                if let Err(e) = result {
+                    report_and_discard(e);
+                }
+
+                debug!("disconnect: {}", addr);
+                Ok(())
+            }));
+
+            Ok(())
+        },
+    );
+
+    handle.spawn(accept);
+    Ok(local_addr)
+}
+
+/// Bind a Unix datagram socket, calling `on_datagram` for every datagram received.
+///
+/// Any stale socket at the path is removed first.
+pub fn bind_unix_datagram<F>(handle: &Handle, path: &Path, on_datagram: F) -> Result<()>
+where
+    F: Fn(&[u8]) + 'static,
+{
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != IoErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+
+    let socket = UnixDatagram::bind(path, handle)?;
+
+    handle.spawn(receive(
+        path.display().to_string(),
+        move |buffer| socket.recv(buffer),
+        on_datagram,
+    ));
+
+    Ok(())
+}
+
+/// Values received by a listener since the last poll.
+///
+/// Only the last value received for each metric is kept, and everything is cleared once polled so
+/// that metrics which are no longer pushed go away.
+pub struct Received {
+    key: String,
+    id: String,
+    values: HashMap<MetricIdBuilder, f64>,
+    malformed: u64,
+}
+
+impl Received {
+    /// Create a new collection, where `key` is the key used for the listener's own metrics.
+    pub fn new(key: &str, id: &str) -> Received {
+        Received {
+            key: key.to_owned(),
+            id: id.to_owned(),
+            values: HashMap::new(),
+            malformed: 0,
+        }
+    }
+
+    pub fn insert(&mut self, metric_id: MetricIdBuilder, value: f64) {
+        self.values.insert(metric_id, value);
+    }
+
+    /// Mark that a malformed line was received.
+    pub fn malformed(&mut self) {
+        self.malformed += 1;
+    }
+
+    /// Build samples for everything received since the last flush, and reset.
+    pub fn flush(&mut self) -> Samples {
+        let mut samples: Samples = self.values
+            .drain()
+            .map(|(id, value)| Sample::new(Arc::new(id.build()), value))
+            .collect();
+
+        let malformed = key(&self.key)
+            .tag("instance", &self.id)
+            .tag("what", "malformed-lines")
+            .build();
+
+        samples.push(Sample::new(Arc::new(malformed), self.malformed as f64));
+        self.malformed = 0;
+
+        samples
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::{self, Notify, NotifyHandle};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Noop;
+
+    impl Notify for Noop {
+        fn notify(&self, _id: usize) {}
+    }
+
+    #[test]
+    fn test_receive_after_error() {
+        let results: Vec<std_io::Result<&'static [u8]>> = vec![
+            Ok(b"first"),
+            Err(std_io::Error::new(IoErrorKind::ConnectionRefused, "refused")),
+            Ok(b"second"),
+        ];
+
+        let results = RefCell::new(results.into_iter());
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let seen = received.clone();
+
+        let recv = move |buffer: &mut [u8]| match results.borrow_mut().next() {
+            Some(Ok(data)) => {
+                buffer[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+            Some(Err(e)) => Err(e),
+            None => Err(IoErrorKind::WouldBlock.into()),
+        };
+
+        let mut task = executor::spawn(receive("test".to_owned(), recv, move |datagram| {
+            seen.borrow_mut().push(datagram.to_vec())
+        }));
+
+        let notify = NotifyHandle::from(Arc::new(Noop));
+        assert_eq!(Ok(Async::NotReady), task.poll_future_notify(&notify, 0));
+        assert_eq!(vec![b"first".to_vec(), b"second".to_vec()], *received.borrow());
+    }
+}
@@ -0,0 +1,624 @@
+//! Input plugin that follows a log file, and derives metrics from lines matching patterns.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use super::utils::read_optional_file;
+
+use futures::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Lines longer than this are discarded.
+const MAX_LINE: usize = 65536;
+
+#[derive(Deserialize, Debug)]
+struct PatternConfig {
+    /// Regular expression to match against each line.
+    regex: String,
+    /// Key of the metric.
+    key: String,
+    /// One of counter, gauge or histogram. Defaults to counter.
+    kind: Option<String>,
+    /// Named group holding the value for gauges and histograms.
+    value: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LogtailInputConfig {
+    /// Path of the file to follow.
+    path: Option<String>,
+    /// Path to a file where the read offset is persisted.
+    state: Option<String>,
+    patterns: Option<Vec<PatternConfig>>,
+    /// Percentiles to report for histograms, defaults to 50, 90 and 99.
+    percentiles: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+struct Pattern {
+    regex: Regex,
+    key: String,
+    kind: Kind,
+    value: Option<String>,
+}
+
+impl Pattern {
+    fn from_config(config: PatternConfig) -> Result<Pattern> {
+        let regex = Regex::new(&config.regex).map_err(|e| {
+            ErrorKind::ConfigField("regex".to_owned(), e.to_string())
+        })?;
+
+        let kind = match config.kind.as_ref().map(String::as_str) {
+            None | Some("counter") => Kind::Counter,
+            Some("gauge") => Kind::Gauge,
+            Some("histogram") => Kind::Histogram,
+            Some(other) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "kind".to_owned(),
+                        format!("expected counter, gauge or histogram, got: {}", other),
+                    ).into(),
+                )
+            }
+        };
+
+        if kind != Kind::Counter {
+            match config.value {
+                Some(ref value) if regex.capture_names().any(|n| n == Some(value)) => {}
+                Some(ref value) => {
+                    return Err(
+                        ErrorKind::ConfigField(
+                            "value".to_owned(),
+                            format!("no such group in regex: {}", value),
+                        ).into(),
+                    )
+                }
+                None => return Err(ErrorKind::MissingField("value".to_owned()).into()),
+            }
+        }
+
+        Ok(Pattern {
+            regex: regex,
+            key: config.key,
+            kind: kind,
+            value: config.value,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct LogtailInput {}
+
+impl Input for LogtailInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: LogtailInputConfig = ctx.decode_config()?;
+
+        let path = match config.path {
+            Some(path) => PathBuf::from(path),
+            None => return Err(ErrorKind::MissingField("path".to_owned()).into()),
+        };
+
+        let mut patterns = Vec::new();
+
+        for pattern in config.patterns.unwrap_or_else(Vec::new) {
+            patterns.push(Pattern::from_config(pattern)?);
+        }
+
+        let mut tail = Tail::new(path, config.state.map(PathBuf::from));
+        tail.restore()?;
+
+        Ok(Box::new(LogtailInputInstance::new(Logtail {
+            id: ctx.id.clone(),
+            tail: tail,
+            patterns: patterns,
+            percentiles: config.percentiles.unwrap_or(vec![50f64, 90f64, 99f64]),
+            counters: HashMap::new(),
+            gauges: HashMap::new(),
+            histograms: HashMap::new(),
+            failed: false,
+        })))
+    }
+}
+
+/// Follows a file across rotation and truncation.
+struct Tail {
+    path: PathBuf,
+    state: Option<PathBuf>,
+    file: Option<File>,
+    inode: u64,
+    /// offset in the current file which has been read.
+    offset: u64,
+    /// trailing data which is not yet terminated by a newline.
+    partial: Vec<u8>,
+    /// offset to start reading from the first time the file is opened.
+    initial: Option<(u64, u64)>,
+    /// inode and offset last written to the state file.
+    persisted: Option<(u64, u64)>,
+}
+
+impl Tail {
+    pub fn new(path: PathBuf, state: Option<PathBuf>) -> Tail {
+        Tail {
+            path: path,
+            state: state,
+            file: None,
+            inode: 0,
+            offset: 0,
+            partial: Vec::new(),
+            initial: None,
+            persisted: None,
+        }
+    }
+
+    /// Restore the offset from the state file, if one is configured and exists.
+    pub fn restore(&mut self) -> Result<()> {
+        let content = match self.state {
+            Some(ref state) => read_optional_file(state)?,
+            None => None,
+        };
+
+        if let Some(content) = content {
+            let content = String::from_utf8_lossy(&content);
+            let mut parts = content.split_whitespace().map(str::parse::<u64>);
+
+            if let (Some(Ok(inode)), Some(Ok(offset))) = (parts.next(), parts.next()) {
+                self.initial = Some((inode, offset));
+                self.persisted = Some((inode, offset));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Persist the offset of the last complete line read, if it changed since last persisted.
+    pub fn persist(&mut self) -> Result<()> {
+        let state = match (&self.state, &self.file) {
+            (&Some(ref state), &Some(_)) => state,
+            _ => return Ok(()),
+        };
+
+        let offset = self.offset - self.partial.len() as u64;
+
+        if self.persisted == Some((self.inode, offset)) {
+            return Ok(());
+        }
+
+        // write and rename, so that a crash never leaves a partially written state behind.
+        let tmp = state.with_extension("tmp");
+
+        {
+            let mut f = File::create(&tmp)?;
+            f.write_all(format!("{} {}\n", self.inode, offset).as_bytes())?;
+        }
+
+        fs::rename(&tmp, state)?;
+        self.persisted = Some((self.inode, offset));
+        Ok(())
+    }
+
+    /// Open the file at the path, returning `None` if it does not exist.
+    fn open(&self) -> Result<Option<(File, u64)>> {
+        match File::open(&self.path) {
+            Ok(file) => {
+                let inode = file.metadata()?.ino();
+                Ok(Some((file, inode)))
+            }
+            Err(e) => {
+                if e.kind() == IoErrorKind::NotFound {
+                    return Ok(None);
+                }
+
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Read everything available in the current file into lines.
+    fn drain(&mut self, lines: &mut Vec<String>) -> Result<()> {
+        let mut buffer = Vec::new();
+
+        {
+            let file = match self.file {
+                Some(ref mut file) => file,
+                None => return Ok(()),
+            };
+
+            // truncated, start over from the beginning.
+            if file.metadata()?.len() < self.offset {
+                self.offset = 0;
+                self.partial.clear();
+            }
+
+            file.seek(SeekFrom::Start(self.offset))?;
+            file.read_to_end(&mut buffer)?;
+        }
+
+        self.offset += buffer.len() as u64;
+        self.partial.extend(buffer);
+
+        while let Some(i) = self.partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..i + 1).collect();
+            lines.push(String::from_utf8_lossy(&line[..i]).into_owned());
+        }
+
+        if self.partial.len() > MAX_LINE {
+            warn!("{}: discarding overlong line", self.path.display());
+            self.partial.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Read all new complete lines.
+    ///
+    /// Lines read before a failure are kept in lines.
+    pub fn read_lines(&mut self, lines: &mut Vec<String>) -> Result<()> {
+        // finish reading the current file, in case it was rotated since the last read.
+        self.drain(lines)?;
+
+        let (file, inode) = match self.open()? {
+            Some(opened) => opened,
+            None => return Ok(()),
+        };
+
+        if self.file.is_none() || inode != self.inode {
+            let size = file.metadata()?.len();
+
+            self.offset = match self.initial.take() {
+                // resume from the persisted state.
+                Some((i, offset)) if i == inode && offset <= size => offset,
+                // first time the file is opened, skip everything already in it.
+                _ if self.file.is_none() => size,
+                // rotated.
+                _ => 0,
+            };
+
+            self.file = Some(file);
+            self.inode = inode;
+            self.partial.clear();
+            self.drain(lines)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Logtail {
+    id: String,
+    tail: Tail,
+    patterns: Vec<Pattern>,
+    percentiles: Vec<f64>,
+    counters: HashMap<MetricIdBuilder, f64>,
+    gauges: HashMap<MetricIdBuilder, f64>,
+    histograms: HashMap<MetricIdBuilder, Distribution>,
+    /// if the last update failed to read the file or to persist the offset.
+    failed: bool,
+}
+
+impl Logtail {
+    fn process(&mut self, line: &str) {
+        for pattern in &self.patterns {
+            let captures = match pattern.regex.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+
+            let mut id = key(&pattern.key);
+            let mut value = None;
+
+            for name in pattern.regex.capture_names() {
+                let name = match name {
+                    Some(name) => name,
+                    None => continue,
+                };
+
+                let captured = match captures.name(name) {
+                    Some(captured) => captured.as_str(),
+                    None => continue,
+                };
+
+                if pattern.value.as_ref().map(String::as_str) == Some(name) {
+                    value = captured.parse::<f64>().ok();
+                    continue;
+                }
+
+                id = id.tag(name, captured);
+            }
+
+            match (pattern.kind, value) {
+                (Kind::Counter, _) => *self.counters.entry(id).or_insert(0f64) += 1f64,
+                (Kind::Gauge, Some(value)) => {
+                    self.gauges.insert(id, value);
+                }
+                (Kind::Histogram, Some(value)) => {
+                    self.histograms
+                        .entry(id)
+                        .or_insert_with(Distribution::new)
+                        .record(value, 1f64);
+                }
+                // value was not numeric.
+                _ => {}
+            }
+        }
+    }
+
+    /// Process new lines and persist the offset.
+    ///
+    /// Failures are logged and reported through the logtail-failed series, so that a file which
+    /// can't be read doesn't stop the other inputs.
+    pub fn update(&mut self) {
+        let mut lines = Vec::new();
+        let result = self.tail.read_lines(&mut lines);
+
+        for line in lines {
+            self.process(&line);
+        }
+
+        self.failed = match result.and_then(|_| self.tail.persist()) {
+            Ok(()) => false,
+            Err(e) => {
+                warn!("{}: {}", self.tail.path.display(), e);
+                true
+            }
+        };
+    }
+
+    /// Build samples, histograms are reset on every poll.
+    pub fn samples(&mut self) -> Samples {
+        let mut samples = Vec::new();
+
+        for (id, value) in self.counters.iter().chain(self.gauges.iter()) {
+            samples.push(Sample::new(Arc::new(id.build()), *value));
+        }
+
+        for (id, histogram) in self.histograms.drain() {
+            for (stat, value) in histogram.stats(&self.percentiles) {
+                let id = id.clone().tag("stat", &stat).build();
+                samples.push(Sample::new(Arc::new(id), value));
+            }
+        }
+
+        let failed = key("logtail")
+            .tag("instance", &self.id)
+            .tag("what", "logtail-failed")
+            .build();
+        samples.push(Sample::new(Arc::new(failed), if self.failed { 1f64 } else { 0f64 }));
+
+        samples
+    }
+}
+
+struct LogtailInputInstance {
+    logtail: Arc<Mutex<Logtail>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for LogtailInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LogtailInputInstance")
+    }
+}
+
+impl LogtailInputInstance {
+    pub fn new(logtail: Logtail) -> LogtailInputInstance {
+        LogtailInputInstance {
+            next_update: Duration::from_millis(1000),
+            logtail: Arc::new(Mutex::new(logtail)),
+        }
+    }
+}
+
+impl InputInstance for LogtailInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        Ok(self.logtail.lock()?.samples())
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let l = self.logtail.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = l.lock().map_err(Into::into).map(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(LogtailInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find_sample;
+    use std::env;
+    use std::fs::OpenOptions;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sysmon-logtail-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn append(path: &PathBuf, content: &str) {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+
+        f.write_all(content.as_bytes()).unwrap();
+    }
+
+    fn logtail(path: &PathBuf, state: &PathBuf) -> Logtail {
+        let mut tail = Tail::new(path.clone(), Some(state.clone()));
+        tail.restore().unwrap();
+
+        let patterns = vec![
+            PatternConfig {
+                regex: r#""GET [^"]*" (?P<status>\d+) (?P<bytes>\d+)"#.to_owned(),
+                key: "requests".to_owned(),
+                kind: None,
+                value: None,
+            },
+            PatternConfig {
+                regex: r#"took (?P<ms>[\d.]+)ms"#.to_owned(),
+                key: "latency".to_owned(),
+                kind: Some("histogram".to_owned()),
+                value: Some("ms".to_owned()),
+            },
+        ];
+
+        Logtail {
+            id: "access".to_owned(),
+            tail: tail,
+            patterns: patterns
+                .into_iter()
+                .map(|p| Pattern::from_config(p).unwrap())
+                .collect(),
+            percentiles: vec![50f64],
+            counters: HashMap::new(),
+            gauges: HashMap::new(),
+            histograms: HashMap::new(),
+            failed: false,
+        }
+    }
+
+    fn failed() -> MetricId {
+        key("logtail")
+            .tag("instance", "access")
+            .tag("what", "logtail-failed")
+            .build()
+    }
+
+    #[test]
+    fn test_logtail() {
+        let dir = temp_dir("tail");
+        let path = dir.join("access.log");
+        let state = dir.join("state");
+
+        append(&path, "\"GET /old\" 200 10\n");
+
+        let mut l = logtail(&path, &state);
+
+        // existing content is skipped the first time.
+        l.update();
+        append(&path, "\"GET /\" 200 10\n\"GET /\" 404 3\n\"GET /\" 200");
+        l.update();
+
+        let ok = key("requests").tag("status", "200").tag("bytes", "10").build();
+        assert_eq!(Some(1f64), find_sample(&l.samples(), &ok));
+
+        // partial line is completed.
+        append(&path, " 10\ntook 12.5ms\n");
+        l.update();
+
+        let samples = l.samples();
+        assert_eq!(Some(2f64), find_sample(&samples, &ok));
+
+        let latency = key("latency").tag("stat", "max").build();
+        assert_eq!(Some(12.5), find_sample(&samples, &latency));
+
+        // rotation, the remainder of the old file is read before the new one.
+        append(&path, "\"GET /\" 200 10\n");
+        fs::rename(&path, dir.join("access.log.1")).unwrap();
+        append(&path, "\"GET /\" 200 10\n");
+        l.update();
+        assert_eq!(Some(4f64), find_sample(&l.samples(), &ok));
+
+        // truncation.
+        File::create(&path).unwrap();
+        l.update();
+        append(&path, "\"GET /\" 200 10\n");
+        l.update();
+        assert_eq!(Some(5f64), find_sample(&l.samples(), &ok));
+
+        // restart, resumes from the persisted offset.
+        append(&path, "\"GET /\" 200 10\n\"GET /\" 200");
+        l.update();
+        let mut l = logtail(&path, &state);
+        append(&path, " 10\n");
+        l.update();
+        assert_eq!(Some(1f64), find_sample(&l.samples(), &ok));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_persist_on_change() {
+        let dir = temp_dir("persist");
+        let path = dir.join("access.log");
+        let state = dir.join("state");
+
+        append(&path, "\"GET /\" 200 10\n");
+
+        let mut l = logtail(&path, &state);
+        l.update();
+        assert!(state.exists());
+
+        // nothing new was read, so the state is left alone.
+        fs::remove_file(&state).unwrap();
+        l.update();
+        assert!(!state.exists());
+
+        append(&path, "\"GET /\" 200 10\n");
+        l.update();
+        assert!(state.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failures() {
+        let dir = temp_dir("failures");
+        let path = dir.join("access.log");
+
+        // the state can't be written, lines are still counted.
+        let mut l = logtail(&path, &dir.join("missing").join("state"));
+        append(&path, "");
+        l.update();
+        append(&path, "\"GET /\" 200 10\n");
+        l.update();
+
+        let samples = l.samples();
+        let ok = key("requests").tag("status", "200").tag("bytes", "10").build();
+        assert_eq!(Some(1f64), find_sample(&samples, &ok));
+        assert_eq!(Some(1f64), find_sample(&samples, &failed()));
+
+        // a directory can be opened, but not read.
+        let mut l = logtail(&dir, &dir.join("state"));
+        l.update();
+        assert_eq!(Some(1f64), find_sample(&l.samples(), &failed()));
+
+        // a file which doesn't exist yet is not a failure.
+        let mut l = logtail(&dir.join("later.log"), &dir.join("state"));
+        l.update();
+        assert_eq!(Some(0f64), find_sample(&l.samples(), &failed()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+//! Input plugin that reports the health of software RAID (md) arrays.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::common::single_u64;
+use parsers::mdstat::*;
+use super::utils::{read_file, read_optional_file, read_text};
+
+use futures::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct MdRaidInputConfig {
+    /// Path to mdstat, defaults to /proc/mdstat.
+    mdstat: Option<String>,
+    /// Root of the sysfs filesystem, defaults to /sys.
+    sysfs: Option<String>,
+}
+
+#[derive(Debug)]
+struct MdRaidInput {}
+
+impl Input for MdRaidInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: MdRaidInputConfig = ctx.decode_config()?;
+
+        let mdstat = PathBuf::from(config.mdstat.unwrap_or("/proc/mdstat".to_owned()));
+        let sysfs = PathBuf::from(config.sysfs.unwrap_or("/sys".to_owned()));
+
+        Ok(Box::new(MdRaidInputInstance::new(MdStat::new(mdstat, sysfs))))
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    read_file(path).ok().and_then(|b| single_u64(&b).to_full_result().ok())
+}
+
+fn flag(value: bool) -> f64 {
+    match value {
+        true => 1f64,
+        false => 0f64,
+    }
+}
+
+struct MdStat {
+    mdstat: PathBuf,
+    sysfs: PathBuf,
+}
+
+impl MdStat {
+    pub fn new(mdstat: PathBuf, sysfs: PathBuf) -> MdStat {
+        MdStat {
+            mdstat: mdstat,
+            sysfs: sysfs,
+        }
+    }
+
+    fn array_readings(&self, array: &MdArray, readings: &mut Vec<(Arc<MetricId>, f64)>) {
+        let md = self.sysfs.join("block").join(&array.name).join("md");
+
+        let mut base = key("system").tag("array", &array.name);
+
+        if let Some(ref personality) = array.personality {
+            base = base.tag("level", personality);
+        }
+
+        // sysfs knows how many devices are missing, even when mdstat is ambiguous.
+        let degraded_disks = read_u64(&md.join("degraded"));
+        let degraded = array.degraded() || degraded_disks.map(|d| d > 0).unwrap_or(false);
+
+        let failed = array.devices.iter().filter(|d| d.faulty()).count();
+        let spare = array.devices.iter().filter(|d| d.spare()).count();
+
+        {
+            let mut push = |what: &str, unit: Option<&str>, value: f64| {
+                let mut id = base.clone().tag("what", what);
+
+                if let Some(unit) = unit {
+                    id = id.tag("unit", unit);
+                }
+
+                readings.push((Arc::new(id.build()), value));
+            };
+
+            push("md-active", None, flag(array.state == "active"));
+            push("md-degraded", None, flag(degraded));
+            push("md-disks-failed", Some("disks"), failed as f64);
+            push("md-disks-spare", Some("disks"), spare as f64);
+
+            if let Some(raid_disks) = array.raid_disks {
+                push("md-disks", Some("disks"), raid_disks as f64);
+            }
+
+            if let Some(in_sync_disks) = array.in_sync_disks {
+                push("md-disks-active", Some("disks"), in_sync_disks as f64);
+            }
+
+            if let Some(degraded_disks) = degraded_disks {
+                push("md-disks-degraded", Some("disks"), degraded_disks as f64);
+            }
+
+            if let Some(mismatches) = read_u64(&md.join("mismatch_cnt")) {
+                push("md-mismatches", Some("sectors"), mismatches as f64);
+            }
+        }
+
+        if let Some(ref progress) = array.progress {
+            let base = base.clone().tag("action", &progress.action);
+
+            let progress_id = base.clone().tag("what", "md-sync-progress").tag("unit", "%");
+            let speed_id = base.clone().tag("what", "md-sync-speed").tag("unit", "bytes/s");
+
+            readings.push((Arc::new(progress_id.build()), progress.percent));
+            readings.push((Arc::new(speed_id.build()), (progress.speed * 1024) as f64));
+        } else if let Some(action) = read_text(md.join("sync_action")) {
+            // an operation which is pending, or has been frozen.
+            if action != "idle" {
+                let id = base.clone()
+                    .tag("action", &action)
+                    .tag("what", "md-sync-progress")
+                    .tag("unit", "%")
+                    .build();
+
+                readings.push((Arc::new(id), 0f64));
+            }
+        }
+    }
+
+    /// Read all arrays.
+    ///
+    /// Hosts without md support have no mdstat, and produce no readings.
+    pub fn scan(&self) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let content = match read_optional_file(&self.mdstat)? {
+            Some(content) => content,
+            None => return Ok(Vec::new()),
+        };
+
+        let arrays = parse_mdstat(&content).to_full_result()?;
+
+        let mut readings = Vec::new();
+
+        for array in &arrays {
+            self.array_readings(array, &mut readings);
+        }
+
+        Ok(readings)
+    }
+}
+
+struct Metrics {
+    mdstat: MdStat,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        self.readings = self.mdstat.scan()?;
+        Ok(())
+    }
+}
+
+struct MdRaidInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for MdRaidInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MdRaidInputInstance")
+    }
+}
+
+impl MdRaidInputInstance {
+    pub fn new(mdstat: MdStat) -> MdRaidInputInstance {
+        MdRaidInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                mdstat: mdstat,
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for MdRaidInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(MdRaidInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{self, write_fixture};
+
+    use std::env;
+    use std::fs;
+
+    fn find(readings: &[(Arc<MetricId>, f64)], expected: MetricIdBuilder) -> Option<f64> {
+        test_helpers::find(readings, &expected.build())
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = env::temp_dir().join("sysmon-mdraid-scan");
+        let _ = fs::remove_dir_all(&root);
+
+        write_fixture(
+            &root,
+            "mdstat",
+            "Personalities : [raid1] \n\
+             md1 : active raid1 sdb2[1] sda2[0]\n      \
+             136448 blocks [2/2] [UU]\n      \n\
+             md2 : active raid1 sdc3[2](S) sdb3[1](F) sda3[0]\n      \
+             129596288 blocks [2/1] [U_]\n      \
+             [===>.................]  recovery = 19.0% (24700032/129596288) \
+             finish=22.5min speed=77526K/sec\n      \n\
+             unused devices: <none>\n",
+        );
+
+        write_fixture(&root, "sys/block/md1/md/degraded", "0\n");
+        write_fixture(&root, "sys/block/md1/md/mismatch_cnt", "128\n");
+        write_fixture(&root, "sys/block/md1/md/sync_action", "frozen\n");
+        write_fixture(&root, "sys/block/md2/md/degraded", "1\n");
+
+        let readings = MdStat::new(root.join("mdstat"), root.join("sys")).scan().unwrap();
+
+        let md1 = key("system").tag("array", "md1").tag("level", "raid1");
+        let md2 = key("system").tag("array", "md2").tag("level", "raid1");
+
+        assert_eq!(Some(1f64), find(&readings, md1.clone().tag("what", "md-active")));
+        assert_eq!(Some(0f64), find(&readings, md1.clone().tag("what", "md-degraded")));
+        assert_eq!(
+            Some(128f64),
+            find(&readings, md1.clone().tag("what", "md-mismatches").tag("unit", "sectors"))
+        );
+
+        // a frozen operation is reported without any progress.
+        assert_eq!(
+            Some(0f64),
+            find(
+                &readings,
+                md1.clone()
+                    .tag("action", "frozen")
+                    .tag("what", "md-sync-progress")
+                    .tag("unit", "%"),
+            )
+        );
+
+        assert_eq!(Some(1f64), find(&readings, md2.clone().tag("what", "md-degraded")));
+
+        for &(what, value) in &[
+            ("md-disks", 2f64),
+            ("md-disks-active", 1f64),
+            ("md-disks-degraded", 1f64),
+            ("md-disks-failed", 1f64),
+            ("md-disks-spare", 1f64),
+        ]
+        {
+            let id = md2.clone().tag("what", what).tag("unit", "disks");
+            assert_eq!(Some(value), find(&readings, id), "{}", what);
+        }
+
+        let recovery = md2.clone().tag("action", "recovery");
+
+        assert_eq!(
+            Some(19f64),
+            find(&readings, recovery.clone().tag("what", "md-sync-progress").tag("unit", "%"))
+        );
+        assert_eq!(
+            Some(77526f64 * 1024f64),
+            find(&readings, recovery.tag("what", "md-sync-speed").tag("unit", "bytes/s"))
+        );
+
+        // hosts without md support have no mdstat.
+        let missing = MdStat::new(root.join("missing"), root.join("sys"));
+        assert_eq!(0, missing.scan().unwrap().len());
+    }
+}
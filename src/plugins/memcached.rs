@@ -0,0 +1,347 @@
+//! Input plugin that reports statistics of a memcached server, as reported by `stats`.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::memcached::*;
+use super::background::{Background, Readings};
+use super::socket::Endpoint;
+
+use futures::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Largest response accepted for `stats`.
+const MAX_RESPONSE: usize = 1 << 20;
+
+#[derive(Deserialize, Debug)]
+struct MemcachedInputConfig {
+    /// TCP address of the server, defaults to 127.0.0.1:11211.
+    address: Option<String>,
+    /// Path to the Unix socket of the server, instead of a TCP address.
+    unix: Option<String>,
+    /// Timeout in seconds for talking to the server, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct MemcachedInput {}
+
+impl Input for MemcachedInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: MemcachedInputConfig = ctx.decode_config()?;
+
+        let memcached = Memcached {
+            endpoint: Endpoint::from_config(config.address, config.unix, "127.0.0.1:11211")?,
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+            deltas: Deltas::new(),
+        };
+
+        Ok(Box::new(MemcachedInputInstance::new(ctx.id, memcached)?))
+    }
+}
+
+/// Statistics which are monotonically increasing counters, reported as rates.
+const COUNTERS: &'static [(&'static str, &'static str)] = &[
+    ("total_connections", "connections/s"),
+    ("rejected_connections", "connections/s"),
+    ("listen_disabled_num", "events/s"),
+    ("conn_yields", "events/s"),
+    ("cmd_get", "commands/s"),
+    ("cmd_set", "commands/s"),
+    ("cmd_flush", "commands/s"),
+    ("cmd_touch", "commands/s"),
+    ("get_hits", "lookups/s"),
+    ("get_misses", "lookups/s"),
+    ("get_expired", "lookups/s"),
+    ("delete_hits", "lookups/s"),
+    ("delete_misses", "lookups/s"),
+    ("incr_hits", "lookups/s"),
+    ("incr_misses", "lookups/s"),
+    ("decr_hits", "lookups/s"),
+    ("decr_misses", "lookups/s"),
+    ("cas_hits", "lookups/s"),
+    ("cas_misses", "lookups/s"),
+    ("cas_badval", "lookups/s"),
+    ("touch_hits", "lookups/s"),
+    ("touch_misses", "lookups/s"),
+    ("bytes_read", "bytes/s"),
+    ("bytes_written", "bytes/s"),
+    ("total_items", "items/s"),
+    ("evictions", "items/s"),
+    ("reclaimed", "items/s"),
+    ("expired_unfetched", "items/s"),
+    ("evicted_unfetched", "items/s"),
+];
+
+/// Statistics which are not measurements, like the pid or current time of the server.
+const SKIPPED: &'static [&'static str] = &["pid", "time", "pointer_size", "version", "libevent"];
+
+/// Unit of a gauge, given the name of its statistic.
+fn gauge_unit(name: &str) -> Option<&'static str> {
+    match name {
+        "bytes" | "limit_maxbytes" => Some("bytes"),
+        "curr_items" => Some("items"),
+        "curr_connections" | "connection_structures" => Some("connections"),
+        "uptime" | "rusage_user" | "rusage_system" => Some("s"),
+        _ => None,
+    }
+}
+
+struct Memcached {
+    endpoint: Endpoint,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+    deltas: Deltas<Arc<MetricId>>,
+}
+
+impl Memcached {
+    fn stats(&self) -> Result<Vec<(String, String)>> {
+        let mut stream = self.endpoint.connect(self.timeout)?;
+        stream.write_all(b"stats\r\n")?;
+
+        let mut response = Vec::new();
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            let n = stream.read(&mut buffer)?;
+
+            if n == 0 {
+                return Err(ErrorKind::Message("connection closed".to_owned()).into());
+            }
+
+            response.extend(&buffer[..n]);
+
+            let text = String::from_utf8_lossy(&response);
+
+            if text.starts_with("ERROR") || text.starts_with("SERVER_ERROR") ||
+                text.starts_with("CLIENT_ERROR")
+            {
+                return Err(ErrorKind::Message(text.trim().to_owned()).into());
+            }
+
+            if let Some(stats) = parse_stats(&text) {
+                return Ok(stats);
+            }
+
+            if response.len() > MAX_RESPONSE {
+                return Err(ErrorKind::Message("response too large".to_owned()).into());
+            }
+        }
+    }
+
+    fn base(&self) -> MetricIdBuilder {
+        let mut base = key("memcached");
+
+        for (k, v) in &self.tags {
+            base = base.tag(k, v);
+        }
+
+        base.tag("instance", &self.endpoint.to_string())
+    }
+
+    /// Convert statistics into gauges, and the rates of counters.
+    fn convert(&mut self, now: Instant, stats: &[(String, String)]) -> Vec<(Arc<MetricId>, f64)> {
+        let base = self.base();
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        for &(ref name, ref value) in stats {
+            if SKIPPED.contains(&name.as_str()) {
+                continue;
+            }
+
+            let what = name.replace("_", "-");
+
+            if let Some(&(_, unit)) = COUNTERS.iter().find(|c| c.0 == name) {
+                if let Ok(value) = value.parse::<u64>() {
+                    let id = base.clone().tag("what", &what).tag("unit", unit).build();
+                    counters.push((Arc::new(id), value));
+                }
+
+                continue;
+            }
+
+            if let Ok(value) = value.parse::<f64>() {
+                let mut id = base.clone().tag("what", &what);
+
+                if let Some(unit) = gauge_unit(name) {
+                    id = id.tag("unit", unit);
+                }
+
+                readings.push((Arc::new(id.build()), value));
+            }
+        }
+
+        readings.extend(self.deltas.rates(now, counters));
+        readings
+    }
+
+    /// Query the server, reporting whether it could be reached as `up`.
+    pub fn scan(&mut self, now: Instant) -> Vec<(Arc<MetricId>, f64)> {
+        let (mut readings, up) = match self.stats() {
+            Ok(stats) => (self.convert(now, &stats), 1f64),
+            Err(e) => {
+                warn!("{}: memcached stats failed: {}", self.endpoint, e);
+                (Vec::new(), 0f64)
+            }
+        };
+
+        readings.push((Arc::new(self.base().tag("what", "up").build()), up));
+        readings
+    }
+}
+
+struct MemcachedInputInstance {
+    /// readings of the last query.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for MemcachedInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MemcachedInputInstance")
+    }
+}
+
+impl MemcachedInputInstance {
+    pub fn new(id: &str, mut memcached: Memcached) -> Result<MemcachedInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("memcached-{}", id), move || {
+            results.set(memcached.scan(Instant::now()))
+        })?;
+
+        Ok(MemcachedInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_millis(1000),
+        })
+    }
+}
+
+impl InputInstance for MemcachedInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// The server is queried on a thread of its own, so a slow or unreachable server never holds
+    /// up the updates of other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(MemcachedInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find;
+    use super::super::socket::test::serve_lines;
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+
+    const STATS: &'static [u8] = b"STAT pid 1234\r\n\
+        STAT uptime 600\r\n\
+        STAT version 1.5.2\r\n\
+        STAT rusage_user 0.123456\r\n\
+        STAT curr_connections 10\r\n\
+        STAT cmd_get 100\r\n\
+        STAT bytes 2048\r\n\
+        END\r\n";
+
+    fn memcached(endpoint: Endpoint) -> Memcached {
+        Memcached {
+            endpoint: endpoint,
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            deltas: Deltas::new(),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let (address, server) = serve_lines(|line| match line {
+            "stats" => Some(STATS.to_vec()),
+            _ => None,
+        });
+
+        let mut m = memcached(Endpoint::Tcp(address.clone()));
+        let now = Instant::now();
+        let readings = m.scan(now);
+        assert_eq!(vec!["stats"], server.join().unwrap());
+
+        let base = key("memcached").tag("instance", &address);
+        let id = |what: &str, unit: &str| base.clone().tag("what", what).tag("unit", unit).build();
+
+        assert_eq!(Some(600f64), find(&readings, &id("uptime", "s")));
+        assert_eq!(Some(0.123456f64), find(&readings, &id("rusage-user", "s")));
+        assert_eq!(Some(10f64), find(&readings, &id("curr-connections", "connections")));
+        assert_eq!(Some(2048f64), find(&readings, &id("bytes", "bytes")));
+        assert_eq!(Some(1f64), find(&readings, &base.clone().tag("what", "up").build()));
+
+        // pid and version are skipped, and counters need two updates.
+        assert_eq!(5, readings.len());
+
+        let stats = vec![("cmd_get".to_owned(), "300".to_owned())];
+        let readings = m.convert(now + Duration::from_secs(2), &stats);
+        assert_eq!(Some(100f64), find(&readings, &id("cmd-get", "commands/s")));
+    }
+
+    #[test]
+    fn test_unix() {
+        let path = env::temp_dir().join(format!("sysmon-memcached-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 64];
+            let n = stream.read(&mut buffer).unwrap();
+            stream.write_all(STATS).unwrap();
+            buffer[..n].to_vec()
+        });
+
+        let mut m = memcached(Endpoint::Unix(PathBuf::from(&path)));
+        let readings = m.scan(Instant::now());
+        assert_eq!(b"stats\r\n".to_vec(), server.join().unwrap());
+
+        let up = key("memcached")
+            .tag("instance", &path.display().to_string())
+            .tag("what", "up")
+            .build();
+        assert_eq!(Some(1f64), find(&readings, &up));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_error() {
+        let (address, server) = serve_lines(|_| Some(b"ERROR\r\n".to_vec()));
+
+        let readings = memcached(Endpoint::Tcp(address.clone())).scan(Instant::now());
+        server.join().unwrap();
+
+        let up = key("memcached").tag("instance", &address).tag("what", "up").build();
+        assert_eq!(vec![(Arc::new(up), 0f64)], readings);
+    }
+}
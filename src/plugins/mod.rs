@@ -3,8 +3,51 @@ mod disk;
 mod load;
 #[cfg(feature = "http")]
 mod http_poller;
+#[cfg(feature = "http")]
+mod nginx_status;
+#[cfg(feature = "http")]
+mod apache_status;
+#[cfg(feature = "http")]
+mod haproxy;
 mod debug;
 mod snoop;
+mod limits;
+mod sensors;
+mod mdraid;
+mod nfs;
+mod interrupts;
+mod numa;
+mod conntrack;
+mod system;
+mod exec;
+mod statsd;
+mod listener;
+mod graphite_listener;
+mod influx_listener;
+mod http_client;
+mod prometheus_scrape;
+mod logtail;
+mod tls;
+mod net_check;
+mod socket;
+mod redis;
+mod memcached;
+mod docker;
+mod delivery;
+mod background;
+mod opentsdb;
+mod statsd_output;
+mod protobuf;
+mod riemann;
+mod otlp;
+mod snappy;
+mod remote_write;
+mod elasticsearch;
+mod file;
+mod syslog;
+mod utils;
+#[cfg(test)]
+mod test_helpers;
 
 use plugin::*;
 use std::collections::HashMap;
@@ -15,13 +58,42 @@ pub fn load_plugins() -> PluginRegistry {
     input.insert("disk".to_owned(), disk::input);
     input.insert("cpu".to_owned(), cpu::input);
     input.insert("load".to_owned(), load::input);
+    input.insert("limits".to_owned(), limits::input);
+    input.insert("sensors".to_owned(), sensors::input);
+    input.insert("mdraid".to_owned(), mdraid::input);
+    input.insert("nfs".to_owned(), nfs::input);
+    input.insert("interrupts".to_owned(), interrupts::input);
+    input.insert("numa".to_owned(), numa::input);
+    input.insert("conntrack".to_owned(), conntrack::input);
+    input.insert("system".to_owned(), system::input);
+    input.insert("exec".to_owned(), exec::input);
+    input.insert("statsd".to_owned(), statsd::input);
+    input.insert("graphite_listener".to_owned(), graphite_listener::input);
+    input.insert("influx_listener".to_owned(), influx_listener::input);
+    input.insert("prometheus_scrape".to_owned(), prometheus_scrape::input);
+    input.insert("logtail".to_owned(), logtail::input);
+    input.insert("net_check".to_owned(), net_check::input);
+    input.insert("redis".to_owned(), redis::input);
+    input.insert("memcached".to_owned(), memcached::input);
+    input.insert("docker".to_owned(), docker::input);
 
     #[cfg(feature = "http")] input.insert("http_poller".to_owned(), http_poller::input);
+    #[cfg(feature = "http")] input.insert("nginx_status".to_owned(), nginx_status::input);
+    #[cfg(feature = "http")] input.insert("apache_status".to_owned(), apache_status::input);
+    #[cfg(feature = "http")] input.insert("haproxy".to_owned(), haproxy::input);
 
     let mut output: HashMap<String, OutputEntry> = HashMap::new();
 
     output.insert("debug".to_owned(), debug::output);
     output.insert("snoop".to_owned(), snoop::output);
+    output.insert("opentsdb".to_owned(), opentsdb::output);
+    output.insert("statsd".to_owned(), statsd_output::output);
+    output.insert("riemann".to_owned(), riemann::output);
+    output.insert("otlp".to_owned(), otlp::output);
+    output.insert("remote_write".to_owned(), remote_write::output);
+    output.insert("elasticsearch".to_owned(), elasticsearch::output);
+    output.insert("file".to_owned(), file::output);
+    output.insert("syslog".to_owned(), syslog::output);
 
     PluginRegistry::new(input, output)
 }
@@ -0,0 +1,667 @@
+//! Input plugin that checks whether network services are reachable.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use super::background::{Background, Readings};
+use super::tls;
+
+use futures::*;
+use native_tls::{self, Certificate};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Core, Handle, Timeout};
+use tokio_io::{io, AsyncRead, AsyncWrite};
+use tokio_tls::TlsConnector;
+use tokio_uds::UnixStream;
+
+/// Largest response read while waiting for an expected response.
+const MAX_RESPONSE: usize = 65536;
+
+#[derive(Deserialize, Debug)]
+struct CheckConfig {
+    /// TCP address to connect to, like localhost:6379.
+    address: Option<String>,
+    /// Path of a Unix socket to connect to.
+    unix: Option<String>,
+    /// Perform a TLS handshake, and report the days until the certificate expires.
+    tls: Option<bool>,
+    /// Server name the certificate is verified against, defaults to the host of the address.
+    server_name: Option<String>,
+    /// Path of a PEM file with the CA which signed the certificate, trusted in addition to the
+    /// ones of the system.
+    ca: Option<String>,
+    /// Payload to send once connected.
+    send: Option<String>,
+    /// Data expected in the response.
+    expect: Option<String>,
+    /// Timeout in seconds for the whole check, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every sample of the check.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NetCheckInputConfig {
+    checks: Option<Vec<CheckConfig>>,
+    /// Interval in seconds between checks, defaults to 10.
+    interval: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+/// Where a check connects to, once resolved.
+enum Target {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+/// How a check performs its TLS handshake.
+#[derive(Debug)]
+struct Tls {
+    connector: native_tls::TlsConnector,
+    server_name: String,
+}
+
+impl Tls {
+    fn from_config(
+        address: &Address,
+        server_name: Option<String>,
+        ca: Option<String>,
+    ) -> Result<Tls> {
+        // default to the host of the address, without the brackets of an IPv6 address.
+        let server_name = match (server_name, address) {
+            (Some(server_name), _) => server_name,
+            (None, &Address::Tcp(ref address)) => {
+                let host = address.rsplitn(2, ':').last().unwrap_or("");
+                host.trim_left_matches('[').trim_right_matches(']').to_owned()
+            }
+            (None, &Address::Unix(_)) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "checks".to_owned(),
+                        "server_name is required for tls over unix".to_owned(),
+                    ).into(),
+                )
+            }
+        };
+
+        let mut builder = native_tls::TlsConnector::builder();
+
+        if let Some(path) = ca {
+            let bad = |e: String| {
+                ErrorKind::ConfigField("checks".to_owned(), format!("{}: {}", path, e))
+            };
+
+            let mut pem = Vec::new();
+            File::open(&path).and_then(|mut f| f.read_to_end(&mut pem)).map_err(
+                |e| bad(e.to_string()),
+            )?;
+
+            let ca = Certificate::from_pem(&pem).map_err(|e| bad(e.to_string()))?;
+            builder.add_root_certificate(ca);
+        }
+
+        let connector = builder.build().map_err(|e| {
+            ErrorKind::Message(format!("failed to set up tls: {}", e))
+        })?;
+
+        Ok(Tls {
+            connector: connector,
+            server_name: server_name,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Check {
+    address: Address,
+    tls: Option<Tls>,
+    send: Option<Vec<u8>>,
+    expect: Option<Vec<u8>>,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+}
+
+impl Check {
+    fn from_config(config: CheckConfig) -> Result<Check> {
+        let address = match (config.address, config.unix) {
+            (Some(address), None) => Address::Tcp(address),
+            (None, Some(unix)) => Address::Unix(PathBuf::from(unix)),
+            _ => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "checks".to_owned(),
+                        "expected exactly one of address or unix".to_owned(),
+                    ).into(),
+                )
+            }
+        };
+
+        let tls = match config.tls.unwrap_or(false) {
+            true => Some(Tls::from_config(&address, config.server_name, config.ca)?),
+            false => None,
+        };
+
+        if tls.is_some() && (config.send.is_some() || config.expect.is_some()) {
+            return Err(
+                ErrorKind::ConfigField(
+                    "checks".to_owned(),
+                    "send and expect can't be used with tls".to_owned(),
+                ).into(),
+            );
+        }
+
+        Ok(Check {
+            address: address,
+            tls: tls,
+            send: config.send.map(String::into_bytes),
+            expect: config.expect.map(String::into_bytes),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+        })
+    }
+
+    /// The target tag of the check.
+    fn target(&self) -> String {
+        match self.address {
+            Address::Tcp(ref address) => address.clone(),
+            Address::Unix(ref path) => path.display().to_string(),
+        }
+    }
+
+    /// Resolve where to connect, which might block on name resolution.
+    fn resolve(&self) -> Result<Target> {
+        match self.address {
+            Address::Tcp(ref address) => {
+                match address.to_socket_addrs()?.next() {
+                    Some(addr) => Ok(Target::Tcp(addr)),
+                    None => Err(ErrorKind::Message(format!("no address for: {}", address)).into()),
+                }
+            }
+            Address::Unix(ref path) => Ok(Target::Unix(path.clone())),
+        }
+    }
+}
+
+/// The outcome of a successful check.
+struct Outcome {
+    connect: Duration,
+    response: Option<Duration>,
+    expiry: Option<f64>,
+}
+
+/// Days until the certificate presented by the server expires.
+fn expiry<S: Read + Write>(stream: &native_tls::TlsStream<S>) -> Result<f64> {
+    let certificate = match stream.peer_certificate() {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => return Err(ErrorKind::Message("no certificate presented".to_owned()).into()),
+        Err(e) => return Err(ErrorKind::Message(e.to_string()).into()),
+    };
+
+    let der = certificate.to_der().map_err(
+        |e| ErrorKind::Message(e.to_string()),
+    )?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| {
+        ErrorKind::Message(e.to_string())
+    })?;
+
+    Ok((tls::not_after(&der)? - now.as_secs() as i64) as f64 / 86400f64)
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+type BoxFuture<T> = Box<Future<Item = T, Error = Error>>;
+
+/// Read from the stream until `f` produces a value from everything read so far.
+fn read_until<S, F, T>(stream: S, f: F) -> BoxFuture<T>
+where
+    S: AsyncRead + 'static,
+    F: Fn(&[u8]) -> Result<Option<T>> + 'static,
+    T: 'static,
+{
+    let f = Rc::new(f);
+
+    Box::new(future::loop_fn((stream, Vec::new()), move |(stream, mut seen)| {
+        let f = f.clone();
+
+        io::read(stream, vec![0u8; 4096]).map_err(Into::into).and_then(
+            move |(stream, buffer, n)| {
+                seen.extend(&buffer[..n]);
+
+                if let Some(value) = f(&seen)? {
+                    return Ok(future::Loop::Break(value));
+                }
+
+                if n == 0 {
+                    return Err(ErrorKind::Message("connection closed".to_owned()).into());
+                }
+
+                if seen.len() > MAX_RESPONSE {
+                    return Err(ErrorKind::Message("response too large".to_owned()).into());
+                }
+
+                Ok(future::Loop::Continue((stream, seen)))
+            },
+        )
+    }))
+}
+
+/// Perform the check over a connected stream.
+fn exchange<S>(stream: S, check: Arc<Check>, started: Instant) -> BoxFuture<Outcome>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let connect = started.elapsed();
+
+    if let Some(ref tls) = check.tls {
+        let handshake = TlsConnector::from(tls.connector.clone())
+            .connect(&tls.server_name, stream)
+            .map_err(|e| ErrorKind::Message(format!("tls handshake failed: {}", e)).into());
+
+        return Box::new(handshake.and_then(move |stream| {
+            Ok(Outcome {
+                connect: connect,
+                response: None,
+                expiry: Some(expiry(stream.get_ref())?),
+            })
+        }));
+    }
+
+    let sent: BoxFuture<S> = match check.send {
+        Some(ref send) => Box::new(io::write_all(stream, send.clone()).map(|(s, _)| s).map_err(
+            Into::into,
+        )),
+        None => Box::new(future::ok(stream)),
+    };
+
+    let expect = match check.expect {
+        Some(ref expect) => expect.clone(),
+        None => {
+            return Box::new(sent.map(move |_| {
+                Outcome {
+                    connect: connect,
+                    response: None,
+                    expiry: None,
+                }
+            }))
+        }
+    };
+
+    Box::new(
+        sent.and_then(move |stream| {
+            read_until(stream, move |seen| {
+                Ok(if seen.windows(expect.len()).any(|w| w == &expect[..]) {
+                    Some(())
+                } else {
+                    None
+                })
+            })
+        }).map(move |_| {
+                Outcome {
+                    connect: connect,
+                    response: Some(started.elapsed()),
+                    expiry: None,
+                }
+            }),
+    )
+}
+
+/// Run a single check, bounded by its timeout.
+fn run_check(handle: &Handle, check: Arc<Check>, target: Target) -> BoxFuture<Outcome> {
+    let started = Instant::now();
+
+    let timeout = match Timeout::new(check.timeout, handle) {
+        Ok(timeout) => timeout,
+        Err(e) => return Box::new(future::err(e.into())),
+    };
+
+    let work: BoxFuture<Outcome> = match target {
+        Target::Tcp(addr) => {
+            Box::new(TcpStream::connect(&addr, handle).map_err(Into::into).and_then(
+                move |stream| exchange(stream, check, started),
+            ))
+        }
+        Target::Unix(path) => {
+            match UnixStream::connect(&path, handle) {
+                Ok(stream) => exchange(stream, check, started),
+                Err(e) => Box::new(future::err(e.into())),
+            }
+        }
+    };
+
+    let timeout = timeout.then(|_| -> Result<Outcome> {
+        Err(ErrorKind::Message("timed out".to_owned()).into())
+    });
+
+    Box::new(work.select(timeout).map(|(outcome, _)| outcome).map_err(
+        |(e, _)| e,
+    ))
+}
+
+/// Convert the result of a check into readings.
+fn readings(check: &Check, result: Result<Outcome>) -> Vec<(Arc<MetricId>, f64)> {
+    let mut base = key("net-check");
+
+    for (k, v) in &check.tags {
+        base = base.tag(k, v);
+    }
+
+    let base = base.tag("target", &check.target());
+    let id = |what: &str, unit: &str| {
+        Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+    };
+
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            debug!("{}: check failed: {}", check.target(), e);
+            return vec![(id("success", "bool"), 0f64)];
+        }
+    };
+
+    let mut readings = vec![
+        (id("success", "bool"), 1f64),
+        (id("connect-time", "s"), seconds(outcome.connect)),
+    ];
+
+    if let Some(response) = outcome.response {
+        readings.push((id("response-time", "s"), seconds(response)));
+    }
+
+    if let Some(expiry) = outcome.expiry {
+        readings.push((id("tls-expiry", "days"), expiry));
+    }
+
+    readings
+}
+
+/// Run all checks concurrently on the given reactor.
+fn run_checks(
+    handle: &Handle,
+    targets: Vec<(Arc<Check>, Result<Target>)>,
+) -> Box<Future<Item = Vec<(Arc<MetricId>, f64)>, Error = ()>> {
+    let futures: Vec<_> = targets
+        .into_iter()
+        .map(|(check, target)| {
+            let outcome = match target {
+                Ok(target) => run_check(handle, check.clone(), target),
+                Err(e) => Box::new(future::err(e)),
+            };
+
+            outcome.then(move |result| Ok(readings(&check, result)))
+        })
+        .collect();
+
+    Box::new(future::join_all(futures).map(|all| {
+        all.into_iter().flat_map(|r| r).collect()
+    }))
+}
+
+#[derive(Debug)]
+struct NetCheckInput {}
+
+impl Input for NetCheckInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: NetCheckInputConfig = ctx.decode_config()?;
+
+        let mut checks = Vec::new();
+
+        for check in config.checks.unwrap_or_else(Vec::new) {
+            checks.push(Arc::new(Check::from_config(check)?));
+        }
+
+        if checks.is_empty() {
+            return Err(ErrorKind::MissingField("checks".to_owned()).into());
+        }
+
+        let checks = Arc::new(checks);
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("net-check-{}", ctx.id), move || {
+            let targets: Vec<_> = checks.iter().map(|c| (c.clone(), c.resolve())).collect();
+            let mut core = Core::new()?;
+
+            match core.run(run_checks(&core.handle(), targets)) {
+                Ok(r) => results.set(r),
+                Err(()) => Err(ErrorKind::Message("checks cancelled".to_owned()).into()),
+            }
+        })?;
+
+        Ok(Box::new(NetCheckInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_secs(config.interval.unwrap_or(10)),
+        }))
+    }
+}
+
+struct NetCheckInputInstance {
+    /// readings of the last checks.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for NetCheckInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NetCheckInputInstance")
+    }
+}
+
+impl InputInstance for NetCheckInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// Names are resolved and the checks run on a thread of its own, so slow checks never hold
+    /// up the updates of other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(NetCheckInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test_helpers::{test_ca_file, tls_acceptor};
+    use std::env;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread;
+    use tokio_core::reactor::Core;
+
+    fn check(address: Address) -> Check {
+        Check {
+            address: address,
+            tls: None,
+            send: None,
+            expect: None,
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+        }
+    }
+
+    fn run(check: Check) -> Vec<(Arc<MetricId>, f64)> {
+        let mut core = Core::new().unwrap();
+        let check = Arc::new(check);
+        let target = check.resolve();
+        core.run(run_checks(&core.handle(), vec![(check, target)])).unwrap()
+    }
+
+    fn find(readings: &[(Arc<MetricId>, f64)], what: &str) -> Option<f64> {
+        readings
+            .iter()
+            .find(|&&(ref id, _)| id.to_string().contains(&format!("\"{}\"", what)))
+            .map(|r| r.1)
+    }
+
+    /// Serve a single connection, responding with the given data once something is received.
+    fn serve(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 1024];
+            stream.read(&mut buffer).unwrap();
+            stream.write_all(response).unwrap();
+        });
+
+        addr.to_string()
+    }
+
+    #[test]
+    fn test_send_expect() {
+        let mut c = check(Address::Tcp(serve(b"+PONG\r\n")));
+        c.send = Some(b"PING\r\n".to_vec());
+        c.expect = Some(b"PONG".to_vec());
+
+        let readings = run(c);
+        assert_eq!(Some(1f64), find(&readings, "success"));
+        assert!(find(&readings, "connect-time").is_some());
+        assert!(find(&readings, "response-time").is_some());
+    }
+
+    #[test]
+    fn test_unexpected_response() {
+        let mut c = check(Address::Tcp(serve(b"-ERR\r\n")));
+        c.send = Some(b"PING\r\n".to_vec());
+        c.expect = Some(b"PONG".to_vec());
+
+        assert_eq!(Some(0f64), find(&run(c), "success"));
+    }
+
+    #[test]
+    fn test_refused() {
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        assert_eq!(Some(0f64), find(&run(check(Address::Tcp(addr.to_string()))), "success"));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut c = check(Address::Tcp(listener.local_addr().unwrap().to_string()));
+        c.expect = Some(b"never".to_vec());
+        c.timeout = Duration::from_millis(200);
+
+        let started = Instant::now();
+        assert_eq!(Some(0f64), find(&run(c), "success"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_unix() {
+        let path = env::temp_dir().join(format!("sysmon-net-check-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH\r\n").unwrap();
+        });
+
+        let mut c = check(Address::Unix(path.clone()));
+        c.expect = Some(b"SSH-".to_vec());
+
+        assert_eq!(Some(1f64), find(&run(c), "success"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Serve a single TLS connection with the test certificate.
+    fn serve_tls() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+
+            // wait for the client to hang up once the handshake completed.
+            if let Ok(mut stream) = tls_acceptor().accept(stream) {
+                let _ = stream.read(&mut [0u8; 1]);
+            }
+        });
+
+        addr.to_string()
+    }
+
+    fn tls_check(address: String, server_name: Option<&str>, ca: bool) -> Check {
+        let address = Address::Tcp(address);
+        let server_name = server_name.map(str::to_owned);
+        let ca = match ca {
+            true => Some(test_ca_file("net-check").display().to_string()),
+            false => None,
+        };
+
+        let mut c = check(address.clone());
+        c.tls = Some(Tls::from_config(&address, server_name, ca).unwrap());
+        c
+    }
+
+    #[test]
+    fn test_tls_expiry() {
+        let readings = run(tls_check(serve_tls(), None, true));
+        assert_eq!(Some(1f64), find(&readings, "success"));
+
+        // the test certificate expires at the start of 2117.
+        let expiry = find(&readings, "tls-expiry").unwrap();
+        assert!(expiry > 365f64 * 90f64 && expiry < 365f64 * 100f64, "expiry = {}", expiry);
+    }
+
+    #[test]
+    fn test_tls_untrusted() {
+        let readings = run(tls_check(serve_tls(), None, false));
+        assert_eq!(Some(0f64), find(&readings, "success"));
+        assert_eq!(None, find(&readings, "tls-expiry"));
+    }
+
+    #[test]
+    fn test_tls_wrong_name() {
+        let readings = run(tls_check(serve_tls(), Some("example.com"), true));
+        assert_eq!(Some(0f64), find(&readings, "success"));
+    }
+
+    #[test]
+    fn test_server_name() {
+        let name = |address: &str| {
+            Tls::from_config(&Address::Tcp(address.to_owned()), None, None)
+                .unwrap()
+                .server_name
+        };
+
+        assert_eq!("example.com", name("example.com:443"));
+        assert_eq!("::1", name("[::1]:443"));
+        assert!(Tls::from_config(&Address::Unix(PathBuf::from("/a")), None, None).is_err());
+    }
+}
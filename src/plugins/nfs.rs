@@ -0,0 +1,456 @@
+//! Input plugin that reports NFS client and server statistics.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::mountstats::*;
+use parsers::rpc_stats::*;
+use super::utils::read_optional_file;
+
+use futures::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct NfsInputConfig {
+    /// Path to client statistics, defaults to /proc/net/rpc/nfs.
+    nfs: Option<String>,
+    /// Path to server statistics, defaults to /proc/net/rpc/nfsd.
+    nfsd: Option<String>,
+    /// Path to per-mount statistics, defaults to /proc/self/mountstats.
+    mountstats: Option<String>,
+}
+
+#[derive(Debug)]
+struct NfsInput {}
+
+impl Input for NfsInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: NfsInputConfig = ctx.decode_config()?;
+
+        let nfs = Nfs {
+            nfs: PathBuf::from(config.nfs.unwrap_or("/proc/net/rpc/nfs".to_owned())),
+            nfsd: PathBuf::from(config.nfsd.unwrap_or("/proc/net/rpc/nfsd".to_owned())),
+            mountstats: PathBuf::from(config.mountstats.unwrap_or(
+                "/proc/self/mountstats".to_owned(),
+            )),
+            deltas: Deltas::new(),
+        };
+
+        Ok(Box::new(NfsInputInstance::new(nfs)))
+    }
+}
+
+const NFS2_PROCEDURES: &'static [&'static str] = &[
+    "null", "getattr", "setattr", "root", "lookup", "readlink", "read", "wrcache", "write",
+    "create", "remove", "rename", "link", "symlink", "mkdir", "rmdir", "readdir", "fsstat",
+];
+
+const NFS3_PROCEDURES: &'static [&'static str] = &[
+    "null", "getattr", "setattr", "lookup", "access", "readlink", "read", "write", "create",
+    "mkdir", "symlink", "mknod", "remove", "rmdir", "rename", "link", "readdir", "readdirplus",
+    "fsstat", "fsinfo", "pathconf", "commit",
+];
+
+/// NFSv4 client procedures, in the order of the kernel (`NFSPROC4_CLNT_*` in linux/nfs4.h).
+const NFS4_CLIENT_PROCEDURES: &'static [&'static str] = &[
+    "null", "read", "write", "commit", "open", "open_confirm", "open_noattr", "open_downgrade",
+    "close", "setattr", "fsinfo", "renew", "setclientid", "setclientid_confirm", "lock", "lockt",
+    "locku", "access", "getattr", "lookup", "lookup_root", "remove", "rename", "link", "symlink",
+    "create", "pathconf", "statfs", "readlink", "readdir", "server_caps", "delegreturn", "getacl",
+    "setacl", "fs_locations", "release_lockowner", "secinfo", "fsid_present", "exchange_id",
+    "create_session", "destroy_session", "sequence", "get_lease_time", "reclaim_complete",
+    "layoutget", "getdeviceinfo", "layoutcommit", "layoutreturn", "secinfo_no_name",
+    "test_stateid", "free_stateid", "getdevicelist", "bind_conn_to_session", "destroy_clientid",
+    "seek", "allocate", "deallocate", "layoutstats", "clone",
+];
+
+const NFS4_SERVER_PROCEDURES: &'static [&'static str] = &["null", "compound"];
+
+/// NFSv4 operations, indexed by their operation number (RFC 5661).
+const NFS4_OPERATIONS: &'static [&'static str] = &[
+    "op0", "op1", "op2", "access", "close", "commit", "create", "delegpurge", "delegreturn",
+    "getattr", "getfh", "link", "lock", "lockt", "locku", "lookup", "lookupp", "nverify", "open",
+    "openattr", "open_confirm", "open_downgrade", "putfh", "putpubfh", "putrootfh", "read",
+    "readdir", "readlink", "remove", "rename", "renew", "restorefh", "savefh", "secinfo",
+    "setattr", "setclientid", "setclientid_confirm", "verify", "write", "release_lockowner",
+    "backchannel_ctl", "bind_conn_to_session", "exchange_id", "create_session",
+    "destroy_session", "free_stateid", "get_dir_delegation", "getdeviceinfo", "getdevicelist",
+    "layoutcommit", "layoutget", "layoutreturn", "secinfo_no_name", "sequence", "set_ssv",
+    "test_stateid", "want_delegation", "destroy_clientid", "reclaim_complete",
+];
+
+/// Name of a procedure, falling back to its index for tables which are not known.
+fn procedure_name(table: &[&str], index: usize) -> String {
+    table.get(index).map(|s| s.to_string()).unwrap_or_else(
+        || format!("op{}", index),
+    )
+}
+
+fn procedure_table(role: &str, version: u64) -> &'static [&'static str] {
+    match (role, version) {
+        (_, 2) => NFS2_PROCEDURES,
+        (_, 3) => NFS3_PROCEDURES,
+        ("client", 4) => NFS4_CLIENT_PROCEDURES,
+        ("server", 4) => NFS4_SERVER_PROCEDURES,
+        _ => &[],
+    }
+}
+
+/// Identifiers of the counters collected for a single operation on a mount.
+struct MountOpIds {
+    ops: Arc<MetricId>,
+    transmissions: Arc<MetricId>,
+    retransmissions: Arc<MetricId>,
+    rtt: Arc<MetricId>,
+    execute: Arc<MetricId>,
+}
+
+struct Nfs {
+    nfs: PathBuf,
+    nfsd: PathBuf,
+    mountstats: PathBuf,
+    deltas: Deltas<Arc<MetricId>>,
+}
+
+impl Nfs {
+    fn rpc_counters(
+        &self,
+        role: &str,
+        stats: &RpcStats,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) {
+        let base = key("system").tag("role", role);
+
+        let id = |what: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", "calls/s").build())
+        };
+
+        if let Some(rpc) = stats.get("rpc") {
+            if let Some(calls) = rpc.get(0) {
+                counters.push((id("nfs-rpc-calls"), *calls as u64));
+            }
+
+            // the client reports retransmissions, the server bad calls.
+            if let Some(second) = rpc.get(1) {
+                let what = match role {
+                    "client" => "nfs-rpc-retransmissions",
+                    _ => "nfs-rpc-bad-calls",
+                };
+
+                counters.push((id(what), *second as u64));
+            }
+        }
+
+        for (version, values) in stats.procedures() {
+            let table = procedure_table(role, version);
+
+            for (index, value) in values.iter().enumerate() {
+                let id = base.clone()
+                    .tag("what", "nfs-ops")
+                    .tag("unit", "ops/s")
+                    .tag("version", &version.to_string())
+                    .tag("op", &procedure_name(table, index))
+                    .build();
+
+                counters.push((Arc::new(id), *value as u64));
+            }
+        }
+
+        if let Some(operations) = stats.operations() {
+            for (index, value) in operations.iter().enumerate() {
+                let id = base.clone()
+                    .tag("what", "nfs-ops")
+                    .tag("unit", "ops/s")
+                    .tag("version", "4")
+                    .tag("op", &procedure_name(NFS4_OPERATIONS, index))
+                    .build();
+
+                counters.push((Arc::new(id), *value as u64));
+            }
+        }
+    }
+
+    fn mount_counters(
+        &self,
+        mounts: &[MountStats],
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) -> Vec<MountOpIds> {
+        let mut ids = Vec::new();
+
+        for mount in mounts.iter().filter(|m| m.fstype.starts_with("nfs")) {
+            let base = key("system")
+                .tag("role", "client")
+                .tag("mount", &mount.mountpoint)
+                .tag("export", &mount.device);
+
+            for op in &mount.ops {
+                let op_name = op.name.to_lowercase();
+
+                let id = |what: &str, unit: &str| {
+                    Arc::new(
+                        base.clone()
+                            .tag("what", what)
+                            .tag("unit", unit)
+                            .tag("op", &op_name)
+                            .build(),
+                    )
+                };
+
+                let op_ids = MountOpIds {
+                    ops: id("nfs-mount-ops", "ops/s"),
+                    transmissions: id("nfs-mount-transmissions", "ops/s"),
+                    retransmissions: id("nfs-mount-retransmissions", "ops/s"),
+                    rtt: id("nfs-mount-rtt", "ms"),
+                    execute: id("nfs-mount-execute", "ms"),
+                };
+
+                counters.push((op_ids.ops.clone(), op.ops));
+                counters.push((op_ids.transmissions.clone(), op.transmissions));
+                counters.push((op_ids.rtt.clone(), op.rtt));
+                counters.push((op_ids.execute.clone(), op.execute));
+
+                ids.push(op_ids);
+            }
+        }
+
+        ids
+    }
+
+    pub fn scan(&mut self, now: Instant) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let mut counters = Vec::new();
+
+        if let Some(content) = read_optional_file(&self.nfs)? {
+            let stats = parse_rpc_stats(&content).to_full_result()?;
+            self.rpc_counters("client", &stats, &mut counters);
+        }
+
+        if let Some(content) = read_optional_file(&self.nfsd)? {
+            let stats = parse_rpc_stats(&content).to_full_result()?;
+            self.rpc_counters("server", &stats, &mut counters);
+        }
+
+        // everything up until here is reported as a plain rate.
+        let rates: Vec<Arc<MetricId>> = counters.iter().map(|c| c.0.clone()).collect();
+
+        let mount_ids = match read_optional_file(&self.mountstats)? {
+            Some(content) => {
+                let mounts = parse_mountstats(&content).to_full_result()?;
+                self.mount_counters(&mounts, &mut counters)
+            }
+            None => Vec::new(),
+        };
+
+        let (elapsed, deltas) = match self.deltas.update(now, counters) {
+            Some((elapsed, deltas)) if elapsed > 0f64 => (elapsed, deltas),
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut readings = Vec::new();
+
+        for id in rates {
+            if let Some(delta) = deltas.get(&id) {
+                readings.push((id, *delta as f64 / elapsed));
+            }
+        }
+
+        for op in mount_ids {
+            let get = |id: &Arc<MetricId>| deltas.get(id).map(|d| *d);
+
+            let (ops, transmissions, rtt, execute) =
+                match (get(&op.ops), get(&op.transmissions), get(&op.rtt), get(&op.execute)) {
+                    (Some(ops), Some(transmissions), Some(rtt), Some(execute)) => {
+                        (ops, transmissions, rtt, execute)
+                    }
+                    _ => continue,
+                };
+
+            let retransmissions = transmissions.saturating_sub(ops);
+
+            readings.push((op.ops, ops as f64 / elapsed));
+            readings.push((op.retransmissions, retransmissions as f64 / elapsed));
+
+            // averages are only meaningful if any operations completed.
+            if ops > 0 {
+                readings.push((op.rtt, rtt as f64 / ops as f64));
+                readings.push((op.execute, execute as f64 / ops as f64));
+            }
+        }
+
+        Ok(readings)
+    }
+}
+
+struct Metrics {
+    nfs: Nfs,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        self.readings = self.nfs.scan(Instant::now())?;
+        Ok(())
+    }
+}
+
+struct NfsInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for NfsInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NfsInputInstance")
+    }
+}
+
+impl NfsInputInstance {
+    pub fn new(nfs: Nfs) -> NfsInputInstance {
+        NfsInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                nfs: nfs,
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for NfsInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(NfsInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{find, write_fixture};
+
+    use std::env;
+    use std::fs;
+
+    fn rpc_stats(calls: u64, retransmissions: u64, getattr: u64) -> String {
+        format!(
+            "net 0 0 0 0\n\
+             rpc {} {} 0\n\
+             proc3 22 0 {} 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n",
+            calls,
+            retransmissions,
+            getattr
+        )
+    }
+
+    fn mountstats(ops: u64, transmissions: u64, rtt: u64, execute: u64) -> String {
+        format!(
+            "device proc mounted on /proc with fstype proc\n\
+             device nas:/export mounted on /mnt/nas with fstype nfs4 statvers=1.1\n\
+             \tage:\t4242\n\
+             \tper-op statistics\n\
+             \t        NULL: 0 0 0 0 0 0 0 0\n\
+             \t        READ: {} {} 0 160000 1048000000 40 {} {}\n\
+             \n",
+            ops,
+            transmissions,
+            rtt,
+            execute
+        )
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = env::temp_dir().join("sysmon-nfs-scan");
+        let _ = fs::remove_dir_all(&root);
+
+        write_fixture(&root, "nfs", rpc_stats(1000, 5, 100));
+        write_fixture(&root, "mountstats", mountstats(1000, 1002, 3000, 3200));
+
+        let mut nfs = Nfs {
+            nfs: root.join("nfs"),
+            nfsd: root.join("nfsd"),
+            mountstats: root.join("mountstats"),
+            deltas: Deltas::new(),
+        };
+
+        let now = Instant::now();
+        assert!(nfs.scan(now).unwrap().is_empty());
+
+        write_fixture(&root, "nfs", rpc_stats(1200, 7, 300));
+        write_fixture(&root, "mountstats", mountstats(1100, 1122, 3500, 3800));
+
+        let readings = nfs.scan(now + Duration::from_secs(2)).unwrap();
+
+        let client = key("system").tag("role", "client");
+        let rpc = |what: &str| client.clone().tag("what", what).tag("unit", "calls/s").build();
+        assert_eq!(Some(100f64), find(&readings, &rpc("nfs-rpc-calls")));
+        assert_eq!(Some(1f64), find(&readings, &rpc("nfs-rpc-retransmissions")));
+
+        let getattr = client
+            .clone()
+            .tag("what", "nfs-ops")
+            .tag("unit", "ops/s")
+            .tag("version", "3")
+            .tag("op", "getattr")
+            .build();
+        assert_eq!(Some(100f64), find(&readings, &getattr));
+
+        // no server statistics are reported without the file.
+        assert!(readings.iter().all(|r| r.0.tag("role") == Some("client")));
+
+        let mount = client.tag("mount", "/mnt/nas").tag("export", "nas:/export");
+        let op = |what: &str, unit: &str, op: &str| {
+            mount.clone().tag("what", what).tag("unit", unit).tag("op", op).build()
+        };
+        assert_eq!(Some(50f64), find(&readings, &op("nfs-mount-ops", "ops/s", "read")));
+        assert_eq!(
+            Some(10f64),
+            find(&readings, &op("nfs-mount-retransmissions", "ops/s", "read"))
+        );
+        assert_eq!(Some(5f64), find(&readings, &op("nfs-mount-rtt", "ms", "read")));
+        assert_eq!(Some(6f64), find(&readings, &op("nfs-mount-execute", "ms", "read")));
+
+        // averages are left out for operations which didn't complete.
+        assert_eq!(Some(0f64), find(&readings, &op("nfs-mount-ops", "ops/s", "null")));
+        assert_eq!(None, find(&readings, &op("nfs-mount-rtt", "ms", "null")));
+    }
+
+    #[test]
+    fn test_procedure_name() {
+        assert_eq!("readdirplus", procedure_name(procedure_table("client", 3), 17));
+        assert_eq!("compound", procedure_name(procedure_table("server", 4), 1));
+        assert_eq!("setclientid", procedure_name(procedure_table("client", 4), 12));
+        assert_eq!("sequence", procedure_name(procedure_table("client", 4), 41));
+        assert_eq!("op70", procedure_name(procedure_table("client", 4), 70));
+        assert_eq!("sequence", procedure_name(NFS4_OPERATIONS, 53));
+    }
+}
@@ -0,0 +1,89 @@
+//! Input plugin that polls the nginx `stub_status` page.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::nginx::*;
+use super::http_poller::{self, StatusFormat};
+
+use std::sync::Arc;
+
+pub struct NginxStatus {}
+
+impl StatusFormat for NginxStatus {
+    fn key(&self) -> &'static str {
+        "nginx"
+    }
+
+    fn default_target(&self) -> &'static str {
+        "http://127.0.0.1/nginx_status"
+    }
+
+    fn convert(
+        &self,
+        base: &MetricIdBuilder,
+        page: &str,
+        readings: &mut Vec<(Arc<MetricId>, f64)>,
+        counters: &mut Vec<(Arc<MetricId>, u64)>,
+    ) -> Result<()> {
+        let status = parse_stub_status(page).map_err(ErrorKind::Message)?;
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        readings.push((id("active", "connections"), status.active as f64));
+        readings.push((id("reading", "connections"), status.reading as f64));
+        readings.push((id("writing", "connections"), status.writing as f64));
+        readings.push((id("waiting", "connections"), status.waiting as f64));
+
+        counters.push((id("accepts", "connections/s"), status.accepts));
+        counters.push((id("handled", "connections/s"), status.handled));
+        counters.push((id("requests", "requests/s"), status.requests));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct NginxStatusInput {}
+
+impl Input for NginxStatusInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        http_poller::setup(ctx.id, ctx.decode_config()?, Box::new(NginxStatus {}))
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(NginxStatusInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STUB_STATUS: &'static str = "Active connections: 291 \n\
+        server accepts handled requests\n \
+        16630948 16630948 31070465 \n\
+        Reading: 6 Writing: 179 Waiting: 106 \n";
+
+    #[test]
+    fn test_convert() {
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        let base = key("nginx");
+        NginxStatus {}
+            .convert(&base, STUB_STATUS, &mut readings, &mut counters)
+            .unwrap();
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(base.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        assert_eq!(4, readings.len());
+        assert!(readings.contains(&(id("writing", "connections"), 179f64)));
+
+        assert_eq!(3, counters.len());
+        assert!(counters.contains(&(id("requests", "requests/s"), 31070465)));
+    }
+}
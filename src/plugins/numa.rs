@@ -0,0 +1,208 @@
+//! Input plugin that reports per-node memory and allocation statistics on NUMA systems.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::numa::*;
+use super::utils::{list_dir, read_optional_file};
+
+use futures::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct NumaInputConfig {
+    /// Root of the sysfs filesystem, defaults to /sys.
+    root: Option<String>,
+}
+
+#[derive(Debug)]
+struct NumaInput {}
+
+impl Input for NumaInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: NumaInputConfig = ctx.decode_config()?;
+        let root = PathBuf::from(config.root.unwrap_or("/sys".to_owned()));
+        Ok(Box::new(NumaInputInstance::new(Numa::new(root))))
+    }
+}
+
+struct Numa {
+    root: PathBuf,
+    deltas: Deltas<Arc<MetricId>>,
+}
+
+impl Numa {
+    pub fn new(root: PathBuf) -> Numa {
+        Numa {
+            root: root,
+            deltas: Deltas::new(),
+        }
+    }
+
+    /// Read all nodes, returning memory gauges and the rates of numastat counters.
+    pub fn scan(&mut self, now: Instant) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        for dir in list_dir(&self.root.join("devices/system/node"), "node")? {
+            let node = dir.file_name().unwrap().to_string_lossy()["node".len()..].to_owned();
+
+            // skip other entries with the same prefix, like `node_online`.
+            if node.is_empty() || !node.chars().all(|c| c.is_digit(10)) {
+                continue;
+            }
+
+            let base = key("system").tag("node", &node);
+
+            if let Some(content) = read_optional_file(dir.join("meminfo"))? {
+                for field in parse_node_meminfo(&content).to_full_result()? {
+                    let unit = match field.bytes {
+                        true => "bytes",
+                        false => "pages",
+                    };
+
+                    let id = base.clone()
+                        .tag("what", "numa-memory")
+                        .tag("unit", unit)
+                        .tag("field", &field.field)
+                        .build();
+
+                    readings.push((Arc::new(id), field.value as f64));
+                }
+            }
+
+            if let Some(content) = read_optional_file(dir.join("numastat"))? {
+                for (field, value) in parse_numastat(&content).to_full_result()? {
+                    let what = match field.starts_with("numa_") {
+                        true => field.replace("_", "-"),
+                        false => format!("numa-{}", field.replace("_", "-")),
+                    };
+
+                    let id = base.clone().tag("what", &what).tag("unit", "pages/s").build();
+                    counters.push((Arc::new(id), value));
+                }
+            }
+        }
+
+        readings.extend(self.deltas.rates(now, counters));
+        Ok(readings)
+    }
+}
+
+struct Metrics {
+    numa: Numa,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        self.readings = self.numa.scan(Instant::now())?;
+        Ok(())
+    }
+}
+
+struct NumaInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for NumaInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NumaInputInstance")
+    }
+}
+
+impl NumaInputInstance {
+    pub fn new(numa: Numa) -> NumaInputInstance {
+        NumaInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                numa: numa,
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for NumaInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(NumaInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{find, write_fixture};
+
+    use std::env;
+    use std::fs;
+
+    fn numastat(hit: u64, miss: u64) -> String {
+        format!("numa_hit {}\nnuma_miss {}\nlocal_node {}\n", hit, miss, hit)
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = env::temp_dir().join("sysmon-numa-scan");
+        let _ = fs::remove_dir_all(&root);
+
+        write_fixture(&root, "devices/system/node/node_online", "");
+        write_fixture(&root, "devices/system/node/node0/meminfo", "Node 0 MemTotal: 1024 kB\n");
+        write_fixture(&root, "devices/system/node/node1/meminfo", "Node 1 MemTotal: 2048 kB\n");
+        write_fixture(&root, "devices/system/node/node0/numastat", &numastat(100, 0));
+        write_fixture(&root, "devices/system/node/node1/numastat", &numastat(100, 10));
+
+        let mut numa = Numa::new(root.clone());
+        let now = Instant::now();
+
+        // first scan only has memory, since rates need two samples.
+        let first = numa.scan(now).unwrap();
+        assert_eq!(2, first.len());
+        assert_eq!(2048f64 * 1024f64, first[1].1);
+
+        write_fixture(&root, "devices/system/node/node1/numastat", &numastat(300, 30));
+
+        let second = numa.scan(now + Duration::from_secs(2)).unwrap();
+        assert_eq!(2 + 6, second.len());
+
+        let miss = key("system")
+            .tag("node", "1")
+            .tag("what", "numa-miss")
+            .tag("unit", "pages/s")
+            .build();
+
+        assert_eq!(Some(10f64), find(&second, &miss));
+    }
+}
@@ -0,0 +1,581 @@
+//! Output plugin that sends samples to OpenTSDB, over its telnet interface or HTTP API.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+use super::delivery::{Backoff, Worker};
+use super::http_client::{self, Url};
+use super::utils::read_text;
+
+use serde_json;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Attempts to deliver a batch before it is dropped.
+const ATTEMPTS: usize = 3;
+
+#[derive(Deserialize, Debug)]
+struct OpenTsdbOutputConfig {
+    /// Address of the telnet interface, defaults to 127.0.0.1:4242.
+    address: Option<String>,
+    /// Url of the HTTP API, like http://127.0.0.1:4242/api/put, instead of the telnet interface.
+    url: Option<String>,
+    /// Maximum number of tags per datapoint, defaults to 8.
+    max_tags: Option<usize>,
+    /// Maximum number of datapoints per HTTP request, defaults to 50.
+    batch_size: Option<usize>,
+    /// Timeout in seconds for connecting and sending, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every datapoint.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct OpenTsdbOutput {}
+
+impl Output for OpenTsdbOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: OpenTsdbOutputConfig = ctx.decode_config()?;
+
+        let timeout = Duration::from_secs(config.timeout.unwrap_or(5));
+        let backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+
+        let mut transport = match (config.address, config.url) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "url".to_owned(),
+                        "can't be combined with address".to_owned(),
+                    ).into(),
+                )
+            }
+            (None, Some(url)) => {
+                let url = Url::parse(&url).map_err(|e| {
+                    ErrorKind::ConfigField("url".to_owned(), e.to_string())
+                })?;
+
+                Transport::Http(Http {
+                    url: url,
+                    batch_size: config.batch_size.unwrap_or(50),
+                    timeout: timeout,
+                    backoff: backoff,
+                })
+            }
+            (address, None) => {
+                Transport::Telnet(Telnet {
+                    address: address.unwrap_or("127.0.0.1:4242".to_owned()),
+                    timeout: timeout,
+                    stream: None,
+                    backoff: backoff,
+                })
+            }
+        };
+
+        let max_tags = config.max_tags.unwrap_or(8);
+
+        if max_tags == 0 {
+            return Err(
+                ErrorKind::ConfigField("max_tags".to_owned(), "must be at least 1".to_owned())
+                    .into(),
+            );
+        }
+
+        let converter = Converter {
+            tags: config
+                .tags
+                .unwrap_or_else(BTreeMap::new)
+                .iter()
+                .map(|(k, v)| (sanitize(k), sanitize(v)))
+                .collect(),
+            max_tags: max_tags,
+            host: read_text("/proc/sys/kernel/hostname").unwrap_or("localhost".to_owned()),
+        };
+
+        let worker = Worker::spawn(&format!("opentsdb-{}", ctx.id), 16, move |points| {
+            transport.deliver(points)
+        })?;
+
+        Ok(Box::new(OpenTsdbOutputInstance {
+            converter: converter,
+            batch: Mutex::new(Vec::new()),
+            worker: worker,
+        }))
+    }
+}
+
+/// Replace characters which are not allowed in metric names and tags.
+fn sanitize(input: &str) -> String {
+    let out: String = input
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() => c,
+            '-' | '_' | '.' | '/' => c,
+            _ => '_',
+        })
+        .collect();
+
+    if out.is_empty() { "_".to_owned() } else { out }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Point {
+    metric: String,
+    timestamp: u64,
+    value: f64,
+    tags: Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+struct JsonPoint<'a> {
+    metric: &'a str,
+    timestamp: u64,
+    value: f64,
+    tags: BTreeMap<&'a str, &'a str>,
+}
+
+impl Point {
+    fn to_put(&self) -> String {
+        let mut line = format!("put {} {} {}", self.metric, self.timestamp, self.value);
+
+        for &(ref k, ref v) in &self.tags {
+            line.push_str(&format!(" {}={}", k, v));
+        }
+
+        line.push('\n');
+        line
+    }
+
+    fn to_json(&self) -> JsonPoint {
+        JsonPoint {
+            metric: &self.metric,
+            timestamp: self.timestamp,
+            value: self.value,
+            tags: self.tags
+                .iter()
+                .map(|&(ref k, ref v)| (k.as_str(), v.as_str()))
+                .collect(),
+        }
+    }
+}
+
+/// Converts samples into datapoints.
+struct Converter {
+    /// configured tags, which take precedence over the tags of samples.
+    tags: Vec<(String, String)>,
+    max_tags: usize,
+    /// fallback tag for datapoints without any tags, which OpenTSDB rejects.
+    host: String,
+}
+
+impl Converter {
+    /// Build a datapoint, where the `what` tag becomes part of the metric name.
+    ///
+    /// Tags beyond `max_tags` are folded into the metric name as `.<key>_<value>`, so that
+    /// datapoints of different series are never merged.
+    fn point(&self, id: &MetricId, value: f64, timestamp: u64) -> Option<Point> {
+        if !value.is_finite() {
+            return None;
+        }
+
+        let mut metric = sanitize(id.key().unwrap_or("sysmon"));
+
+        if let Some(what) = id.tag("what") {
+            metric.push('.');
+            metric.push_str(&sanitize(what));
+        }
+
+        let mut tags = self.tags.clone();
+
+        for &(ref k, ref v) in id.tags().iter().chain(id.resource()) {
+            if k == "what" || v.is_empty() {
+                continue;
+            }
+
+            let k = sanitize(k);
+
+            if tags.iter().any(|t| t.0 == k) {
+                continue;
+            }
+
+            tags.push((k, sanitize(v)));
+        }
+
+        if tags.len() > self.max_tags {
+            for (k, v) in tags.split_off(self.max_tags) {
+                metric.push_str(&format!(".{}_{}", k, v));
+            }
+        }
+
+        if tags.is_empty() {
+            tags.push(("host".to_owned(), self.host.clone()));
+        }
+
+        Some(Point {
+            metric: metric,
+            timestamp: timestamp,
+            value: value,
+            tags: tags,
+        })
+    }
+}
+
+enum Transport {
+    Telnet(Telnet),
+    Http(Http),
+}
+
+impl Transport {
+    fn deliver(&mut self, points: Vec<Point>) {
+        match *self {
+            Transport::Telnet(ref mut telnet) => telnet.deliver(&points),
+            Transport::Http(ref mut http) => http.deliver(&points),
+        }
+    }
+}
+
+/// Whether the peer closed the connection, discarding anything it sent, like error messages.
+fn is_closed(stream: &mut TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+
+    let mut buffer = [0u8; 4096];
+
+    let closed = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break true,
+            Ok(n) => debug!("opentsdb: {}", String::from_utf8_lossy(&buffer[..n]).trim()),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break false,
+            Err(_) => break true,
+        }
+    };
+
+    stream.set_nonblocking(false).is_err() || closed
+}
+
+struct Telnet {
+    address: String,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    backoff: Backoff,
+}
+
+impl Telnet {
+    fn connect(&self) -> Result<TcpStream> {
+        let addr = match self.address.to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => {
+                return Err(
+                    ErrorKind::Message(format!("no address for: {}", self.address)).into(),
+                )
+            }
+        };
+
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+
+    fn write(&mut self, payload: &[u8]) -> Result<()> {
+        let reuse = match self.stream {
+            Some(ref mut stream) => !is_closed(stream),
+            None => false,
+        };
+
+        if !reuse {
+            self.stream = Some(self.connect()?);
+        }
+
+        match self.stream {
+            Some(ref mut stream) => stream.write_all(payload).map_err(Into::into),
+            None => Err(ErrorKind::Message("not connected".to_owned()).into()),
+        }
+    }
+
+    fn deliver(&mut self, points: &[Point]) {
+        let payload: String = points.iter().map(Point::to_put).collect();
+
+        for _ in 0..ATTEMPTS {
+            match self.write(payload.as_bytes()) {
+                Ok(()) => {
+                    self.backoff.reset();
+                    return;
+                }
+                Err(e) => {
+                    warn!("{}: failed to send: {}", self.address, e);
+                    self.stream = None;
+                    thread::sleep(self.backoff.fail());
+                }
+            }
+        }
+
+        warn!("{}: dropping {} datapoints", self.address, points.len());
+    }
+}
+
+struct Http {
+    url: Url,
+    batch_size: usize,
+    timeout: Duration,
+    backoff: Backoff,
+}
+
+impl Http {
+    /// Post a single batch, returning `false` if it should be retried.
+    fn post(&self, body: &[u8]) -> bool {
+        match http_client::post(
+            &self.url,
+            &[("Content-Type", "application/json")],
+            body,
+            self.timeout,
+        ) {
+            Ok(ref response) if response.is_success() => true,
+            // the request was understood, but some datapoints were rejected.
+            Ok(ref response) if response.status < 500 => {
+                warn!(
+                    "{}: datapoints rejected: {}: {}",
+                    self.url.authority(),
+                    response.status,
+                    String::from_utf8_lossy(&response.body)
+                );
+
+                true
+            }
+            Ok(response) => {
+                warn!("{}: failed to send: {}", self.url.authority(), response.status);
+                false
+            }
+            Err(e) => {
+                warn!("{}: failed to send: {}", self.url.authority(), e);
+                false
+            }
+        }
+    }
+
+    fn deliver(&mut self, points: &[Point]) {
+        for chunk in points.chunks(self.batch_size.max(1)) {
+            let json: Vec<JsonPoint> = chunk.iter().map(Point::to_json).collect();
+
+            let body = match serde_json::to_vec(&json) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("{}: failed to serialize: {}", self.url.authority(), e);
+                    continue;
+                }
+            };
+
+            let mut delivered = false;
+
+            for _ in 0..ATTEMPTS {
+                if self.post(&body) {
+                    self.backoff.reset();
+                    delivered = true;
+                    break;
+                }
+
+                thread::sleep(self.backoff.fail());
+            }
+
+            if !delivered {
+                warn!("{}: dropping {} datapoints", self.url.authority(), chunk.len());
+            }
+        }
+    }
+}
+
+struct OpenTsdbOutputInstance {
+    converter: Converter,
+    /// datapoints of the current poll cycle.
+    batch: Mutex<Vec<Point>>,
+    worker: Worker<Vec<Point>>,
+}
+
+impl OutputInstance for OpenTsdbOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ErrorKind::Message(e.to_string()))?
+            .as_secs();
+
+        if let Some(point) = self.converter.point(&sample.metric_id, sample.value, timestamp) {
+            self.batch.lock()?.push(point);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let points = mem::replace(&mut *self.batch.lock()?, Vec::new());
+
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        self.worker.send(points)
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(OpenTsdbOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::http_client::test::serve_once;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    fn converter(max_tags: usize) -> Converter {
+        Converter {
+            tags: vec![("dc".to_owned(), "east".to_owned())],
+            max_tags: max_tags,
+            host: "example".to_owned(),
+        }
+    }
+
+    fn point(metric: &str, value: f64) -> Point {
+        Point {
+            metric: metric.to_owned(),
+            timestamp: 1500000000,
+            value: value,
+            tags: vec![("host".to_owned(), "a".to_owned())],
+        }
+    }
+
+    fn telnet(address: String) -> Telnet {
+        Telnet {
+            address: address,
+            timeout: Duration::from_secs(5),
+            stream: None,
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(100)),
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!("cpu-usage_1.a/b", sanitize("cpu-usage 1.a/b"));
+        assert_eq!("résumé", sanitize("résumé"));
+        assert_eq!("a_b_c", sanitize("a=b,c"));
+        assert_eq!("_", sanitize(""));
+    }
+
+    #[test]
+    fn test_point() {
+        let id = key("system")
+            .tag("what", "cpu usage")
+            .tag("unit", "%")
+            .tag("dc", "west")
+            .tag("empty", "")
+            .build();
+
+        assert_eq!(
+            Some(Point {
+                metric: "system.cpu_usage".to_owned(),
+                timestamp: 10,
+                value: 1.5,
+                tags: vec![
+                    ("dc".to_owned(), "east".to_owned()),
+                    ("unit".to_owned(), "_".to_owned()),
+                ],
+            }),
+            converter(8).point(&id, 1.5, 10)
+        );
+
+        assert_eq!(None, converter(8).point(&id, ::std::f64::NAN, 10));
+    }
+
+    #[test]
+    fn test_max_tags() {
+        let mut id = key("many");
+
+        for i in 0..10 {
+            id = id.tag(&format!("t{}", i), "v");
+        }
+
+        let point = converter(8).point(&id.build(), 1.0, 10).unwrap();
+        assert_eq!("many.t7_v.t8_v.t9_v", point.metric);
+        assert_eq!(8, point.tags.len());
+        assert_eq!(("dc".to_owned(), "east".to_owned()), point.tags[0]);
+        assert_eq!(("t6".to_owned(), "v".to_owned()), point.tags[7]);
+
+        let bare = Converter {
+            tags: Vec::new(),
+            max_tags: 8,
+            host: "example".to_owned(),
+        };
+
+        let point = bare.point(&key("bare").build(), 1.0, 10).unwrap();
+        assert_eq!(vec![("host".to_owned(), "example".to_owned())], point.tags);
+    }
+
+    #[test]
+    fn test_telnet_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (closed_tx, closed_rx) = mpsc::channel();
+
+        // the server closes every connection after the first line.
+        let server = thread::spawn(move || {
+            let mut lines = Vec::new();
+
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                lines.push(line);
+                drop(reader);
+                closed_tx.send(()).unwrap();
+            }
+
+            lines
+        });
+
+        let mut telnet = telnet(address);
+        telnet.deliver(&[point("a", 1.0)]);
+        closed_rx.recv().unwrap();
+        telnet.deliver(&[point("b", 2.5)]);
+
+        assert_eq!(
+            vec![
+                "put a 1500000000 1 host=a\n".to_owned(),
+                "put b 1500000000 2.5 host=a\n".to_owned(),
+            ],
+            server.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_http() {
+        let (addr, server) = serve_once(
+            b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        );
+
+        let mut http = Http {
+            url: Url::parse(&format!("http://{}/api/put", addr)).unwrap(),
+            batch_size: 50,
+            timeout: Duration::from_secs(5),
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(100)),
+        };
+
+        http.deliver(&[point("a", 1.0), point("b", 2.0)]);
+
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("POST /api/put HTTP/1.1\r\n"));
+
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        let points = json.as_array().unwrap();
+
+        assert_eq!(2, points.len());
+        assert_eq!(Some("b"), points[1].find("metric").and_then(|m| m.as_str()));
+        assert_eq!(Some("a"), points[0].pointer("/tags/host").and_then(|m| m.as_str()));
+    }
+}
@@ -0,0 +1,734 @@
+//! Output plugin that exports samples to an OpenTelemetry collector, over OTLP/HTTP.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+use super::delivery::{Backoff, Worker};
+use super::http_client::{self, Url};
+use super::protobuf::Encoder;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::{self, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::io::Write;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Attempts to deliver a request before it is dropped.
+const ATTEMPTS: usize = 3;
+
+/// Aggregation temporality of sums, which are reported as running totals.
+const CUMULATIVE: u64 = 2;
+
+#[derive(Deserialize, Debug)]
+struct OtlpOutputConfig {
+    /// Url of the metrics endpoint, defaults to http://127.0.0.1:4318/v1/metrics.
+    url: Option<String>,
+    /// Path to a PEM file with a CA to trust for https, in addition to the system's.
+    ca: Option<String>,
+    /// Encoding of requests, protobuf or json, defaults to protobuf.
+    encoding: Option<String>,
+    /// Compress requests with gzip, defaults to false.
+    gzip: Option<bool>,
+    /// Headers added to every request, like for authentication.
+    headers: Option<BTreeMap<String, String>>,
+    /// Attributes added to the resource of every sample.
+    resource: Option<BTreeMap<String, String>>,
+    /// Timeout in seconds for sending a request, defaults to 5.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Protobuf,
+    Json,
+}
+
+#[derive(Debug)]
+struct OtlpOutput {}
+
+impl Output for OtlpOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: OtlpOutputConfig = ctx.decode_config()?;
+
+        let url = config.url.unwrap_or(
+            "http://127.0.0.1:4318/v1/metrics".to_owned(),
+        );
+
+        let mut url = Url::parse(&url).map_err(|e| {
+            ErrorKind::ConfigField("url".to_owned(), e.to_string())
+        })?;
+
+        if let Some(ca) = config.ca {
+            url = url.with_ca(&ca).map_err(|e| {
+                ErrorKind::ConfigField("ca".to_owned(), e.to_string())
+            })?;
+        }
+
+        let encoding = match config.encoding {
+            Some(ref encoding) if encoding == "json" => Encoding::Json,
+            Some(ref encoding) if encoding == "protobuf" => Encoding::Protobuf,
+            Some(other) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "encoding".to_owned(),
+                        format!("expected protobuf or json, got: {}", other),
+                    ).into(),
+                )
+            }
+            None => Encoding::Protobuf,
+        };
+
+        let mut resource = config.resource.unwrap_or_else(BTreeMap::new);
+
+        if !resource.contains_key("service.name") {
+            resource.insert("service.name".to_owned(), "sysmon".to_owned());
+        }
+
+        let mut client = Client {
+            url: url,
+            encoding: encoding,
+            gzip: config.gzip.unwrap_or(false),
+            headers: config.headers.unwrap_or_else(BTreeMap::new).into_iter().collect(),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
+        };
+
+        let worker = Worker::spawn(&format!("otlp-{}", ctx.id), 16, move |request| {
+            client.deliver(request)
+        })?;
+
+        Ok(Box::new(OtlpOutputInstance {
+            resource: resource.into_iter().collect(),
+            samples: Mutex::new(Vec::new()),
+            sums: Mutex::new(Sums {
+                last_flush: None,
+                totals: HashMap::new(),
+            }),
+            worker: worker,
+        }))
+    }
+}
+
+type KeyValue = (String, String);
+
+#[derive(Debug, Clone, PartialEq)]
+struct DataPoint {
+    attributes: Vec<KeyValue>,
+    start_time_unix_nano: Option<u64>,
+    time_unix_nano: u64,
+    value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Metric {
+    name: String,
+    unit: String,
+    /// whether the metric is a monotonic cumulative sum, instead of a gauge.
+    sum: bool,
+    data_points: Vec<DataPoint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ResourceMetrics {
+    resource: Vec<KeyValue>,
+    metrics: Vec<Metric>,
+}
+
+fn encode_attributes(e: &mut Encoder, field: u32, attributes: &[KeyValue]) {
+    for &(ref k, ref v) in attributes {
+        e.message(field, |kv| {
+            kv.string(1, k).message(2, |any| {
+                any.string(1, v);
+            });
+        });
+    }
+}
+
+/// Encode an `ExportMetricsServiceRequest` as protobuf.
+fn encode_protobuf(request: &[ResourceMetrics]) -> Vec<u8> {
+    let mut e = Encoder::new();
+
+    for rm in request {
+        e.message(1, |rm_e| {
+            rm_e.message(1, |r| encode_attributes(r, 1, &rm.resource));
+
+            rm_e.message(2, |sm| {
+                sm.message(1, |scope| {
+                    scope.string(1, "sysmon");
+                });
+
+                for metric in &rm.metrics {
+                    sm.message(2, |m| {
+                        m.string(1, &metric.name).string(3, &metric.unit);
+
+                        let points = |d: &mut Encoder| for point in &metric.data_points {
+                            d.message(1, |p| {
+                                if let Some(start) = point.start_time_unix_nano {
+                                    p.fixed64(2, start);
+                                }
+
+                                p.fixed64(3, point.time_unix_nano).double(4, point.value);
+                                encode_attributes(p, 7, &point.attributes);
+                            });
+                        };
+
+                        if metric.sum {
+                            m.message(7, |s| {
+                                points(s);
+                                s.uint64(2, CUMULATIVE).bool(3, true);
+                            });
+                        } else {
+                            m.message(5, points);
+                        }
+                    });
+                }
+            });
+        });
+    }
+
+    e.into_bytes()
+}
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn json_attributes(attributes: &[KeyValue]) -> Value {
+    Value::Array(
+        attributes
+            .iter()
+            .map(|&(ref k, ref v)| {
+                object(vec![
+                    ("key", Value::String(k.clone())),
+                    ("value", object(vec![("stringValue", Value::String(v.clone()))])),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Encode an `ExportMetricsServiceRequest` as JSON, where 64 bit integers are strings.
+fn encode_json(request: &[ResourceMetrics]) -> Value {
+    let resource_metrics = request.iter().map(|rm| {
+        let metrics = rm.metrics.iter().map(|metric| {
+            let points = metric.data_points.iter().map(|point| {
+                let mut fields = vec![
+                    ("attributes", json_attributes(&point.attributes)),
+                    ("timeUnixNano", Value::String(point.time_unix_nano.to_string())),
+                    ("asDouble", Value::F64(point.value)),
+                ];
+
+                if let Some(start) = point.start_time_unix_nano {
+                    fields.push(("startTimeUnixNano", Value::String(start.to_string())));
+                }
+
+                object(fields)
+            });
+
+            let points = Value::Array(points.collect());
+
+            let data = if metric.sum {
+                (
+                    "sum",
+                    object(vec![
+                        ("dataPoints", points),
+                        ("aggregationTemporality", Value::U64(CUMULATIVE)),
+                        ("isMonotonic", Value::Bool(true)),
+                    ]),
+                )
+            } else {
+                ("gauge", object(vec![("dataPoints", points)]))
+            };
+
+            object(vec![
+                ("name", Value::String(metric.name.clone())),
+                ("unit", Value::String(metric.unit.clone())),
+                data,
+            ])
+        });
+
+        object(vec![
+            ("resource", object(vec![("attributes", json_attributes(&rm.resource))])),
+            (
+                "scopeMetrics",
+                Value::Array(vec![
+                    object(vec![
+                        ("scope", object(vec![("name", Value::String("sysmon".to_owned()))])),
+                        ("metrics", Value::Array(metrics.collect())),
+                    ]),
+                ]),
+            ),
+        ])
+    });
+
+    object(vec![("resourceMetrics", Value::Array(resource_metrics.collect()))])
+}
+
+/// Running totals of counters, which are fed as rates.
+///
+/// Totals of counters which are missing from a flush are dropped, so that they restart at zero
+/// with a new start time if they come back.
+struct Sums {
+    last_flush: Option<Instant>,
+    /// start time and total of every counter.
+    totals: HashMap<Arc<MetricId>, (u64, f64)>,
+}
+
+impl Sums {
+    /// Add the increase since the last flush to the total of a counter.
+    ///
+    /// Counters first seen start at zero, since their rate covers a period before their start.
+    fn add(
+        &mut self,
+        id: &Arc<MetricId>,
+        rate: f64,
+        seconds: Option<f64>,
+        time: u64,
+    ) -> (u64, f64) {
+        match self.totals.entry(id.clone()) {
+            Entry::Occupied(mut total) => {
+                if let Some(seconds) = seconds {
+                    total.get_mut().1 += rate * seconds;
+                }
+
+                *total.get()
+            }
+            Entry::Vacant(total) => *total.insert((time, 0f64)),
+        }
+    }
+}
+
+/// Build the request for the samples of a poll cycle.
+///
+/// Samples are grouped by resource, and by metric within a resource.
+fn build(
+    resource: &[KeyValue],
+    samples: Vec<(Arc<MetricId>, f64)>,
+    sums: &mut Sums,
+    now: Instant,
+    time: u64,
+) -> Vec<ResourceMetrics> {
+    let seconds = sums.last_flush.map(|last| {
+        let elapsed = now.duration_since(last);
+        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+    });
+
+    sums.last_flush = Some(now);
+
+    let mut request: Vec<ResourceMetrics> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (id, value) in samples {
+        if !value.is_finite() {
+            continue;
+        }
+
+        let mut own_resource = resource.to_vec();
+
+        for &(ref k, ref v) in id.resource() {
+            if !own_resource.iter().any(|r| r.0 == *k) {
+                own_resource.push((k.clone(), v.clone()));
+            }
+        }
+
+        let mut name = id.key().unwrap_or("sysmon").to_owned();
+
+        if let Some(what) = id.tag("what") {
+            name.push('.');
+            name.push_str(what);
+        }
+
+        let sum = is_rate(&id);
+        let mut unit = id.tag("unit").unwrap_or("").to_owned();
+
+        let (start, value) = if sum {
+            let len = unit.len() - 2;
+            unit.truncate(len);
+            let (start, total) = sums.add(&id, value, seconds, time);
+            seen.insert(id.clone());
+            (Some(start), total)
+        } else {
+            (None, value)
+        };
+
+        let attributes = id.tags()
+            .iter()
+            .filter(|t| t.0 != "what" && t.0 != "unit")
+            .map(|t| t.clone())
+            .collect();
+
+        let point = DataPoint {
+            attributes: attributes,
+            start_time_unix_nano: start,
+            time_unix_nano: time,
+            value: value,
+        };
+
+        let index = match request.iter().position(|rm| rm.resource == own_resource) {
+            Some(index) => index,
+            None => {
+                request.push(ResourceMetrics {
+                    resource: own_resource,
+                    metrics: Vec::new(),
+                });
+
+                request.len() - 1
+            }
+        };
+
+        let metrics = &mut request[index].metrics;
+
+        match metrics.iter_mut().find(
+            |m| m.name == name && m.unit == unit && m.sum == sum,
+        ) {
+            Some(metric) => metric.data_points.push(point),
+            None => {
+                metrics.push(Metric {
+                    name: name,
+                    unit: unit,
+                    sum: sum,
+                    data_points: vec![point],
+                })
+            }
+        }
+    }
+
+    sums.totals.retain(|id, _| seen.contains(id));
+    request
+}
+
+struct Client {
+    url: Url,
+    encoding: Encoding,
+    gzip: bool,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    backoff: Backoff,
+}
+
+impl Client {
+    fn body(&self, request: &[ResourceMetrics]) -> Result<Vec<u8>> {
+        let body = match self.encoding {
+            Encoding::Protobuf => encode_protobuf(request),
+            Encoding::Json => serde_json::to_vec(&encode_json(request))?,
+        };
+
+        if !self.gzip {
+            return Ok(body);
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::Default);
+        encoder.write_all(&body)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Post a single request, returning `false` if it should be retried.
+    fn post(&self, body: &[u8]) -> bool {
+        let content_type = match self.encoding {
+            Encoding::Protobuf => "application/x-protobuf",
+            Encoding::Json => "application/json",
+        };
+
+        let mut headers: Vec<(&str, &str)> = vec![("Content-Type", content_type)];
+
+        if self.gzip {
+            headers.push(("Content-Encoding", "gzip"));
+        }
+
+        headers.extend(self.headers.iter().map(|h| (h.0.as_str(), h.1.as_str())));
+
+        match http_client::post(&self.url, &headers, body, self.timeout) {
+            Ok(ref response) if response.is_success() => true,
+            // only these are retryable according to the OTLP specification.
+            Ok(ref response) if [429, 502, 503, 504].contains(&response.status) => {
+                warn!("{}: failed to send: {}", self.url.authority(), response.status);
+                false
+            }
+            Ok(ref response) => {
+                warn!(
+                    "{}: request rejected: {}: {}",
+                    self.url.authority(),
+                    response.status,
+                    String::from_utf8_lossy(&response.body)
+                );
+
+                true
+            }
+            Err(e) => {
+                warn!("{}: failed to send: {}", self.url.authority(), e);
+                false
+            }
+        }
+    }
+
+    fn deliver(&mut self, request: Vec<ResourceMetrics>) {
+        let body = match self.body(&request) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("{}: failed to encode: {}", self.url.authority(), e);
+                return;
+            }
+        };
+
+        for _ in 0..ATTEMPTS {
+            if self.post(&body) {
+                self.backoff.reset();
+                return;
+            }
+
+            thread::sleep(self.backoff.fail());
+        }
+
+        warn!("{}: dropping request", self.url.authority());
+    }
+}
+
+struct OtlpOutputInstance {
+    resource: Vec<KeyValue>,
+    /// samples of the current poll cycle.
+    samples: Mutex<Vec<(Arc<MetricId>, f64)>>,
+    sums: Mutex<Sums>,
+    worker: Worker<Vec<ResourceMetrics>>,
+}
+
+impl OutputInstance for OtlpOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        self.samples.lock()?.push((sample.metric_id.clone(), sample.value));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let samples = mem::replace(&mut *self.samples.lock()?, Vec::new());
+
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| {
+            ErrorKind::Message(e.to_string())
+        })?;
+
+        let time = now.as_secs() * 1_000_000_000 + now.subsec_nanos() as u64;
+        let request = build(&self.resource, samples, &mut *self.sums.lock()?, Instant::now(), time);
+        self.worker.send(request)
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(OtlpOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::http_client::test::{serve, serve_tls_once};
+    use super::super::protobuf::{self, Field};
+    use plugins::test_helpers::test_ca_file;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn key_value(key: &str, value: &str) -> KeyValue {
+        (key.to_owned(), value.to_owned())
+    }
+
+    fn resource() -> Vec<KeyValue> {
+        vec![key_value("service.name", "sysmon")]
+    }
+
+    fn sums() -> Sums {
+        Sums {
+            last_flush: None,
+            totals: HashMap::new(),
+        }
+    }
+
+    fn client(url: String, encoding: Encoding, gzip: bool) -> Client {
+        Client {
+            url: Url::parse(&url).unwrap(),
+            encoding: encoding,
+            gzip: gzip,
+            headers: vec![key_value("Authorization", "Bearer secret")],
+            timeout: Duration::from_secs(5),
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(100)),
+        }
+    }
+
+    fn samples() -> Vec<(Arc<MetricId>, f64)> {
+        let cpu = key("system").tag("what", "cpu-used").tag("unit", "%");
+        let rx = key("net").tag("what", "rx").tag("unit", "bytes/s").tag("interface", "eth0");
+        let info = key("system").tag("what", "info").resource("hostname", "db1");
+
+        vec![
+            (Arc::new(cpu.build()), 12.5),
+            (Arc::new(rx.build()), 100f64),
+            (Arc::new(info.build()), 1f64),
+        ]
+    }
+
+    /// Body of the raw request received by the stand-in receiver.
+    fn body(request: &[u8]) -> Vec<u8> {
+        let end = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        request[end + 4..].to_vec()
+    }
+
+    fn field<'a>(fields: &[(u32, Field<'a>)], number: u32) -> Vec<Field<'a>> {
+        fields.iter().filter(|f| f.0 == number).map(|f| f.1).collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let mut sums = sums();
+        let now = Instant::now();
+
+        let request = build(&resource(), samples(), &mut sums, now, 1000);
+        assert_eq!(2, request.len());
+        assert_eq!(resource(), request[0].resource);
+        assert_eq!(
+            vec![key_value("service.name", "sysmon"), key_value("hostname", "db1")],
+            request[1].resource
+        );
+
+        let metrics = &request[0].metrics;
+        assert_eq!(2, metrics.len());
+        assert_eq!("system.cpu-used", metrics[0].name);
+        assert_eq!("%", metrics[0].unit);
+        assert!(!metrics[0].sum);
+
+        assert_eq!(
+            Metric {
+                name: "net.rx".to_owned(),
+                unit: "bytes".to_owned(),
+                sum: true,
+                data_points: vec![
+                    DataPoint {
+                        attributes: vec![key_value("interface", "eth0")],
+                        start_time_unix_nano: Some(1000),
+                        time_unix_nano: 1000,
+                        value: 0f64,
+                    },
+                ],
+            },
+            metrics[1]
+        );
+
+        let request = build(&resource(), samples(), &mut sums, now + Duration::from_secs(10), 2000);
+        let point = &request[0].metrics[1].data_points[0];
+        assert_eq!(Some(1000), point.start_time_unix_nano);
+        assert_eq!(1000f64, point.value);
+
+        // the counter goes missing, and restarts once it is back.
+        let gauges = samples().into_iter().filter(|s| !is_rate(&s.0)).collect();
+        build(&resource(), gauges, &mut sums, now + Duration::from_secs(20), 3000);
+        assert!(sums.totals.is_empty());
+
+        let request = build(&resource(), samples(), &mut sums, now + Duration::from_secs(30), 4000);
+        let point = &request[0].metrics[1].data_points[0];
+        assert_eq!(Some(4000), point.start_time_unix_nano);
+        assert_eq!(0f64, point.value);
+    }
+
+    #[test]
+    fn test_protobuf() {
+        let request = build(&resource(), samples(), &mut sums(), Instant::now(), 1000);
+        let (addr, server) = serve(vec![b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()]);
+
+        client(format!("http://{}/v1/metrics", addr), Encoding::Protobuf, true).deliver(request);
+
+        let requests = server.join().unwrap();
+        let request = String::from_utf8_lossy(&requests[0]).into_owned();
+        assert!(request.starts_with("POST /v1/metrics HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/x-protobuf\r\n"));
+        assert!(request.contains("Content-Encoding: gzip\r\n"));
+        assert!(request.contains("Authorization: Bearer secret\r\n"));
+
+        let mut payload = Vec::new();
+        GzDecoder::new(&body(&requests[0])[..]).unwrap().read_to_end(&mut payload).unwrap();
+
+        let request = protobuf::decode(&payload).unwrap();
+        let resource_metrics = field(&request, 1);
+        assert_eq!(2, resource_metrics.len());
+
+        let rm = protobuf::decode(resource_metrics[0].as_bytes().unwrap()).unwrap();
+        let sm = protobuf::decode(field(&rm, 2)[0].as_bytes().unwrap()).unwrap();
+        let metrics = field(&sm, 2);
+        assert_eq!(2, metrics.len());
+
+        let gauge = protobuf::decode(metrics[0].as_bytes().unwrap()).unwrap();
+        assert_eq!(Some("system.cpu-used"), field(&gauge, 1)[0].as_str());
+        let data = protobuf::decode(field(&gauge, 5)[0].as_bytes().unwrap()).unwrap();
+        let point = protobuf::decode(field(&data, 1)[0].as_bytes().unwrap()).unwrap();
+        assert_eq!(vec![Field::Fixed64(1000)], field(&point, 3));
+        assert_eq!(vec![Field::Fixed64(12.5f64.to_bits())], field(&point, 4));
+
+        let sum = protobuf::decode(metrics[1].as_bytes().unwrap()).unwrap();
+        assert_eq!(Some("bytes"), field(&sum, 3)[0].as_str());
+        let data = protobuf::decode(field(&sum, 7)[0].as_bytes().unwrap()).unwrap();
+        assert_eq!(vec![Field::Varint(CUMULATIVE)], field(&data, 2));
+        assert_eq!(vec![Field::Varint(1)], field(&data, 3));
+    }
+
+    #[test]
+    fn test_json_retry() {
+        let request = build(&resource(), samples(), &mut sums(), Instant::now(), 1000);
+
+        let (addr, server) = serve(vec![
+            b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+
+        client(format!("http://{}/v1/metrics", addr), Encoding::Json, false).deliver(request);
+
+        let requests = server.join().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(body(&requests[0]), body(&requests[1]));
+
+        let json: Value = serde_json::from_slice(&body(&requests[1])).unwrap();
+
+        let metric = json.pointer("/resourceMetrics/0/scopeMetrics/0/metrics/1").unwrap();
+        assert_eq!(Some("net.rx"), metric.find("name").and_then(|n| n.as_str()));
+
+        let sum = metric.find("sum").unwrap();
+        assert_eq!(Some(CUMULATIVE), sum.find("aggregationTemporality").and_then(|a| a.as_u64()));
+
+        let point = sum.pointer("/dataPoints/0").unwrap();
+        assert_eq!(Some("1000"), point.find("timeUnixNano").and_then(|t| t.as_str()));
+        assert_eq!(
+            Some("eth0"),
+            point.pointer("/attributes/0/value/stringValue").and_then(|v| v.as_str())
+        );
+    }
+
+    #[test]
+    fn test_https() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec();
+        let (addr, server) = serve_tls_once(ok);
+        let ca = test_ca_file("otlp");
+
+        let url = format!("https://127.0.0.1:{}/v1/metrics", addr.port());
+        let mut client = client(url, Encoding::Json, false);
+        client.url = client.url.with_ca(ca.to_str().unwrap()).unwrap();
+
+        client.deliver(build(&resource(), samples(), &mut sums(), Instant::now(), 1000));
+
+        let request = String::from_utf8_lossy(&server.join().unwrap()).into_owned();
+        assert!(request.starts_with("POST /v1/metrics HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Bearer secret\r\n"));
+    }
+
+    #[test]
+    fn test_rejected() {
+        let (addr, server) = serve(vec![
+            b"HTTP/1.1 400 Bad Request\r\nContent-Length: 3\r\n\r\nbad".to_vec(),
+        ]);
+
+        let request = build(&resource(), samples(), &mut sums(), Instant::now(), 1000);
+        client(format!("http://{}/v1/metrics", addr), Encoding::Json, false).deliver(request);
+
+        assert_eq!(1, server.join().unwrap().len());
+    }
+}
@@ -0,0 +1,351 @@
+//! Input plugin that scrapes Prometheus endpoints.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::prometheus::*;
+use super::background::{Background, Readings};
+use super::http_client::{self, Url};
+
+use futures::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct TargetConfig {
+    /// Url to scrape, like http://localhost:9100/metrics.
+    url: String,
+    /// Tags added to every series scraped from this target.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PrometheusScrapeInputConfig {
+    targets: Option<Vec<TargetConfig>>,
+    /// Keep scraped labels which conflict with target tags, instead of renaming them.
+    honor_labels: Option<bool>,
+    /// Timeout in seconds for each scrape, defaults to 5.
+    timeout: Option<u64>,
+    /// Interval in seconds between scrapes, defaults to 10.
+    interval: Option<u64>,
+}
+
+#[derive(Debug)]
+struct PrometheusScrapeInput {}
+
+impl Input for PrometheusScrapeInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: PrometheusScrapeInputConfig = ctx.decode_config()?;
+
+        let mut targets = Vec::new();
+
+        for target in config.targets.unwrap_or_else(Vec::new) {
+            let url = Url::parse(&target.url).map_err(|e| {
+                ErrorKind::ConfigField("targets".to_owned(), e.to_string())
+            })?;
+
+            let mut tags = vec![("instance".to_owned(), url.authority())];
+
+            for (k, v) in target.tags.unwrap_or_else(BTreeMap::new) {
+                tags.retain(|t| t.0 != k);
+                tags.push((k, v));
+            }
+
+            targets.push(Target {
+                url: url,
+                tags: tags,
+            });
+        }
+
+        if targets.is_empty() {
+            return Err(ErrorKind::MissingField("targets".to_owned()).into());
+        }
+
+        let scraper = Scraper {
+            targets: targets,
+            honor_labels: config.honor_labels.unwrap_or(false),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+        };
+
+        Ok(Box::new(PrometheusScrapeInputInstance::new(
+            &ctx.id,
+            scraper,
+            Duration::from_secs(config.interval.unwrap_or(10)),
+        )?))
+    }
+}
+
+struct Target {
+    url: Url,
+    /// target tags, which always include the `instance` scraped.
+    tags: Vec<(String, String)>,
+}
+
+struct Scraper {
+    targets: Vec<Target>,
+    honor_labels: bool,
+    timeout: Duration,
+}
+
+impl Scraper {
+    /// Build the metric id for a scraped series.
+    ///
+    /// Scraped labels which conflict with target tags are either kept in favor of the target
+    /// tag (honor_labels), or renamed to `exported_<label>`.
+    fn metric_id(&self, target: &Target, sample: &PromSample) -> MetricId {
+        let mut builder = key(&sample.name);
+
+        let conflicts = |name: &str| sample.labels.iter().any(|l| l.0 == name && !l.1.is_empty());
+
+        for &(ref k, ref v) in &target.tags {
+            if self.honor_labels && conflicts(k) {
+                continue;
+            }
+
+            builder = builder.tag(k, v);
+        }
+
+        for &(ref k, ref v) in &sample.labels {
+            // empty labels are equivalent to missing ones.
+            if v.is_empty() {
+                continue;
+            }
+
+            if !self.honor_labels && target.tags.iter().any(|t| t.0 == *k) {
+                builder = builder.tag(&format!("exported_{}", k), v);
+                continue;
+            }
+
+            builder = builder.tag(k, v);
+        }
+
+        builder.build()
+    }
+
+    fn scrape(&self, target: &Target) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let response = http_client::get(
+            &target.url,
+            &[("Accept", "text/plain;version=0.0.4")],
+            self.timeout,
+        )?;
+
+        if !response.is_success() {
+            return Err(
+                ErrorKind::Message(format!("unexpected status: {}", response.status)).into(),
+            );
+        }
+
+        let body = String::from_utf8_lossy(&response.body);
+        let exposition = parse_exposition(&body).map_err(ErrorKind::Message)?;
+
+        Ok(
+            exposition
+                .samples
+                .iter()
+                .map(|s| (Arc::new(self.metric_id(target, s)), s.value))
+                .collect(),
+        )
+    }
+
+    /// Scrape all targets.
+    ///
+    /// Failing targets are logged and reported through the `up` series of the target, instead
+    /// of failing the update.
+    pub fn scan(&self) -> Vec<(Arc<MetricId>, f64)> {
+        let mut readings = Vec::new();
+
+        for target in &self.targets {
+            let up = match self.scrape(target) {
+                Ok(scraped) => {
+                    readings.extend(scraped);
+                    1f64
+                }
+                Err(e) => {
+                    warn!("{}: scrape failed: {}", target.url.authority(), e);
+                    0f64
+                }
+            };
+
+            let mut id = key("up");
+
+            for &(ref k, ref v) in &target.tags {
+                id = id.tag(k, v);
+            }
+
+            readings.push((Arc::new(id.build()), up));
+        }
+
+        readings
+    }
+}
+
+struct PrometheusScrapeInputInstance {
+    /// readings of the last scrape.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for PrometheusScrapeInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrometheusScrapeInputInstance")
+    }
+}
+
+impl PrometheusScrapeInputInstance {
+    pub fn new(
+        id: &str,
+        scraper: Scraper,
+        interval: Duration,
+    ) -> Result<PrometheusScrapeInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("prometheus-{}", id), move || {
+            results.set(scraper.scan())
+        })?;
+
+        Ok(PrometheusScrapeInputInstance {
+            readings: readings,
+            background: background,
+            next_update: interval,
+        })
+    }
+}
+
+impl InputInstance for PrometheusScrapeInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// Targets are scraped on a thread of its own, so slow targets never hold up the updates of
+    /// other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(PrometheusScrapeInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find;
+    use super::super::http_client::test::serve_once;
+    use std::thread;
+
+    const RESPONSE: &'static [u8] = b"HTTP/1.1 200 OK\r\n\
+        Content-Type: text/plain; version=0.0.4\r\n\
+        \r\n\
+        # TYPE requests counter\n\
+        requests{code=\"200\",job=\"api\"} 10\n\
+        # TYPE latency histogram\n\
+        latency_bucket{le=\"0.1\"} 3\n\
+        latency_bucket{le=\"+Inf\"} 5\n";
+
+    fn scraper(url: &str, honor_labels: bool) -> Scraper {
+        let url = Url::parse(url).unwrap();
+
+        Scraper {
+            targets: vec![
+                Target {
+                    tags: vec![
+                        ("instance".to_owned(), url.authority()),
+                        ("job".to_owned(), "node".to_owned()),
+                    ],
+                    url: url,
+                },
+            ],
+            honor_labels: honor_labels,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_scrape() {
+        let (addr, server) = serve_once(RESPONSE.to_vec());
+        let instance = addr.to_string();
+
+        let readings = scraper(&format!("http://{}/metrics", addr), false).scan();
+        server.join().unwrap();
+
+        let requests = key("requests")
+            .tag("instance", &instance)
+            .tag("job", "node")
+            .tag("code", "200")
+            .tag("exported_job", "api")
+            .build();
+        assert_eq!(Some(10f64), find(&readings, &requests));
+
+        let bucket = key("latency_bucket")
+            .tag("instance", &instance)
+            .tag("job", "node")
+            .tag("le", "+Inf")
+            .build();
+        assert_eq!(Some(5f64), find(&readings, &bucket));
+
+        let up = key("up").tag("instance", &instance).tag("job", "node").build();
+        assert_eq!(Some(1f64), find(&readings, &up));
+    }
+
+    #[test]
+    fn test_honor_labels() {
+        let (addr, server) = serve_once(RESPONSE.to_vec());
+        let instance = addr.to_string();
+
+        let readings = scraper(&format!("http://{}/metrics", addr), true).scan();
+        server.join().unwrap();
+
+        let requests = key("requests")
+            .tag("instance", &instance)
+            .tag("code", "200")
+            .tag("job", "api")
+            .build();
+        assert_eq!(Some(10f64), find(&readings, &requests));
+    }
+
+    #[test]
+    fn test_update() {
+        let instance = PrometheusScrapeInputInstance::new(
+            "prometheus",
+            scraper("http://127.0.0.1:1/metrics", false),
+            Duration::from_secs(10),
+        ).unwrap();
+
+        instance.update().wait().unwrap();
+
+        let up = key("up")
+            .tag("instance", "127.0.0.1:1")
+            .tag("job", "node")
+            .build();
+
+        for _ in 0..500 {
+            if instance.poll().unwrap().iter().any(|s| *s.metric_id == up) {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("no readings after the scrape finished");
+    }
+
+    #[test]
+    fn test_down() {
+        let readings = scraper("http://127.0.0.1:1/metrics", false).scan();
+
+        let up = key("up")
+            .tag("instance", "127.0.0.1:1")
+            .tag("job", "node")
+            .build();
+        assert_eq!(Some(0f64), find(&readings, &up));
+    }
+}
@@ -0,0 +1,225 @@
+//! Minimal protocol buffers encoding, for outputs which speak protobuf based protocols.
+//!
+//! Messages are written field by field, so no generated code is needed.
+
+use errors::*;
+
+/// Builds an encoded message.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder { buffer: Vec::new() }
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+
+        self.buffer.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    pub fn uint64(&mut self, field: u32, value: u64) -> &mut Encoder {
+        self.key(field, 0);
+        self.raw_varint(value);
+        self
+    }
+
+    pub fn int64(&mut self, field: u32, value: i64) -> &mut Encoder {
+        self.uint64(field, value as u64)
+    }
+
+    pub fn bool(&mut self, field: u32, value: bool) -> &mut Encoder {
+        self.uint64(field, value as u64)
+    }
+
+    pub fn fixed64(&mut self, field: u32, value: u64) -> &mut Encoder {
+        self.key(field, 1);
+
+        for i in 0..8 {
+            self.buffer.push((value >> (i * 8)) as u8);
+        }
+
+        self
+    }
+
+    pub fn double(&mut self, field: u32, value: f64) -> &mut Encoder {
+        self.fixed64(field, value.to_bits())
+    }
+
+    pub fn float(&mut self, field: u32, value: f32) -> &mut Encoder {
+        let value = value.to_bits();
+        self.key(field, 5);
+
+        for i in 0..4 {
+            self.buffer.push((value >> (i * 8)) as u8);
+        }
+
+        self
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Encoder {
+        self.key(field, 2);
+        self.raw_varint(value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Encoder {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Write an embedded message.
+    pub fn message<F>(&mut self, field: u32, build: F) -> &mut Encoder
+    where
+        F: FnOnce(&mut Encoder),
+    {
+        let mut inner = Encoder::new();
+        build(&mut inner);
+        self.bytes(field, &inner.buffer)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// The value of a decoded field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Field<'a> {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Field::Varint(value) | Field::Fixed64(value) => Some(value),
+            Field::Fixed32(value) => Some(value as u64),
+            Field::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Field::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|b| ::std::str::from_utf8(b).ok())
+    }
+}
+
+fn read_varint(input: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+
+    for shift in 0..10 {
+        let byte = *input.get(*pos).ok_or_else(|| {
+            ErrorKind::Message("truncated varint".to_owned())
+        })?;
+
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << (shift * 7);
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ErrorKind::Message("varint too long".to_owned()).into())
+}
+
+fn read_slice<'a>(input: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    if input.len() - *pos < len {
+        return Err(ErrorKind::Message("truncated field".to_owned()).into());
+    }
+
+    let slice = &input[*pos..*pos + len];
+    *pos += len;
+    Ok(slice)
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// Decode the fields of a message, in the order they were written.
+pub fn decode(input: &[u8]) -> Result<Vec<(u32, Field)>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let key = read_varint(input, &mut pos)?;
+        let field = (key >> 3) as u32;
+
+        let value = match key & 0x7 {
+            0 => Field::Varint(read_varint(input, &mut pos)?),
+            1 => Field::Fixed64(little_endian(read_slice(input, &mut pos, 8)?)),
+            2 => {
+                let len = read_varint(input, &mut pos)? as usize;
+                Field::Bytes(read_slice(input, &mut pos, len)?)
+            }
+            5 => Field::Fixed32(little_endian(read_slice(input, &mut pos, 4)?) as u32),
+            other => {
+                return Err(ErrorKind::Message(format!("unsupported wire type: {}", other)).into())
+            }
+        };
+
+        fields.push((field, value));
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut e = Encoder::new();
+        e.uint64(1, 150).int64(2, 3).string(3, "hi").uint64(4, 1);
+        e.message(5, |m| {
+            m.int64(1, 1);
+        });
+
+        assert_eq!(
+            vec![
+                0x08, 0x96, 0x01,
+                0x10, 0x03,
+                0x1a, 0x02, b'h', b'i',
+                0x20, 0x01,
+                0x2a, 0x02, 0x08, 0x01,
+            ],
+            e.into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut e = Encoder::new();
+        e.double(1, 1.5).float(2, 2.5).string(3, "hi").int64(4, -1);
+        let bytes = e.into_bytes();
+        let fields = decode(&bytes).unwrap();
+
+        assert_eq!((1, Field::Fixed64(1.5f64.to_bits())), fields[0]);
+        assert_eq!((2, Field::Fixed32(2.5f32.to_bits())), fields[1]);
+        assert_eq!(Some("hi"), fields[2].1.as_str());
+        assert_eq!(Some(-1i64 as u64), fields[3].1.as_u64());
+
+        assert!(decode(&[0x1a, 0x05, b'h']).is_err());
+    }
+}
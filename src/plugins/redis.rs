@@ -0,0 +1,398 @@
+//! Input plugin that reports statistics of a Redis server, as reported by the INFO command.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::redis::*;
+use super::background::{Background, Readings};
+use super::socket::Endpoint;
+
+use futures::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug)]
+struct RedisInputConfig {
+    /// TCP address of the server, defaults to 127.0.0.1:6379.
+    address: Option<String>,
+    /// Path to the Unix socket of the server, instead of a TCP address.
+    unix: Option<String>,
+    /// Password to authenticate with.
+    password: Option<String>,
+    /// Timeout in seconds for talking to the server, defaults to 5.
+    timeout: Option<u64>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct RedisInput {}
+
+impl Input for RedisInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: RedisInputConfig = ctx.decode_config()?;
+
+        let redis = Redis {
+            endpoint: Endpoint::from_config(config.address, config.unix, "127.0.0.1:6379")?,
+            password: config.password,
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            tags: config.tags.unwrap_or_else(BTreeMap::new),
+            deltas: Deltas::new(),
+        };
+
+        Ok(Box::new(RedisInputInstance::new(ctx.id, redis)?))
+    }
+}
+
+/// Fields which are monotonically increasing counters, reported as rates.
+const COUNTERS: &'static [(&'static str, &'static str)] = &[
+    ("total_connections_received", "connections/s"),
+    ("rejected_connections", "connections/s"),
+    ("total_commands_processed", "commands/s"),
+    ("total_net_input_bytes", "bytes/s"),
+    ("total_net_output_bytes", "bytes/s"),
+    ("expired_keys", "keys/s"),
+    ("evicted_keys", "keys/s"),
+    ("keyspace_hits", "lookups/s"),
+    ("keyspace_misses", "lookups/s"),
+    ("sync_full", "syncs/s"),
+    ("sync_partial_ok", "syncs/s"),
+    ("sync_partial_err", "syncs/s"),
+];
+
+/// Unit of a gauge, guessed from the name of its field.
+fn gauge_unit(name: &str) -> Option<&'static str> {
+    if name.starts_with("used_memory") || name.starts_with("maxmemory") ||
+        name.starts_with("total_system_memory") || name.ends_with("_bytes")
+    {
+        return Some("bytes");
+    }
+
+    if name.starts_with("used_cpu_") || name.ends_with("_seconds") || name.ends_with("_sec") {
+        return Some("s");
+    }
+
+    if name.ends_with("_in_days") {
+        return Some("days");
+    }
+
+    None
+}
+
+/// Encode a command as an array of bulk strings.
+fn command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+
+    for arg in args {
+        out.extend(format!("${}\r\n", arg.len()).into_bytes());
+        out.extend(arg.as_bytes());
+        out.extend(b"\r\n");
+    }
+
+    out
+}
+
+/// Read a single reply, returning the content of a status, integer or bulk string reply.
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Err(ErrorKind::Message("connection closed".to_owned()).into());
+    }
+
+    let line = line.trim_right();
+
+    if line.is_empty() {
+        return Err(ErrorKind::Message("empty reply".to_owned()).into());
+    }
+
+    let (kind, rest) = line.split_at(1);
+
+    match kind {
+        "+" | ":" => Ok(rest.to_owned()),
+        "-" => Err(ErrorKind::Message(format!("error reply: {}", rest)).into()),
+        "$" => {
+            let length = rest.parse::<i64>().map_err(|_| {
+                ErrorKind::Message(format!("bad bulk length: {}", rest))
+            })?;
+
+            if length < 0 {
+                return Err(ErrorKind::Message("nil reply".to_owned()).into());
+            }
+
+            let mut content = vec![0u8; length as usize + 2];
+            reader.read_exact(&mut content)?;
+            content.truncate(length as usize);
+            Ok(String::from_utf8_lossy(&content).into_owned())
+        }
+        _ => Err(ErrorKind::Message(format!("unexpected reply: {}", line)).into()),
+    }
+}
+
+struct Redis {
+    endpoint: Endpoint,
+    password: Option<String>,
+    timeout: Duration,
+    tags: BTreeMap<String, String>,
+    deltas: Deltas<Arc<MetricId>>,
+}
+
+impl Redis {
+    fn info(&self) -> Result<String> {
+        let mut reader = BufReader::new(self.endpoint.connect(self.timeout)?);
+
+        if let Some(ref password) = self.password {
+            reader.get_mut().write_all(&command(&["AUTH", password]))?;
+            read_reply(&mut reader)?;
+        }
+
+        reader.get_mut().write_all(&command(&["INFO"]))?;
+        read_reply(&mut reader)
+    }
+
+    fn base(&self) -> MetricIdBuilder {
+        let mut base = key("redis");
+
+        for (k, v) in &self.tags {
+            base = base.tag(k, v);
+        }
+
+        base.tag("instance", &self.endpoint.to_string())
+    }
+
+    /// Convert INFO output into gauges, and the rates of counters.
+    fn convert(&mut self, now: Instant, info: &str) -> Vec<(Arc<MetricId>, f64)> {
+        let base = self.base();
+        let mut readings = Vec::new();
+        let mut counters = Vec::new();
+
+        for field in parse_info(info) {
+            if field.section == "keyspace" {
+                if !field.name.starts_with("db") {
+                    continue;
+                }
+
+                let db = base.clone().tag("db", &field.name[2..]);
+
+                for (name, value) in parse_keyspace(&field.value) {
+                    let unit = match name.as_str() {
+                        "avg_ttl" => "ms",
+                        _ => "keys",
+                    };
+
+                    let id = db.clone()
+                        .tag("what", &format!("keyspace-{}", name.replace("_", "-")))
+                        .tag("unit", unit)
+                        .build();
+
+                    readings.push((Arc::new(id), value as f64));
+                }
+
+                continue;
+            }
+
+            // the server section is mostly build information, which only looks numeric.
+            if field.section == "server" && field.name != "uptime_in_seconds" {
+                continue;
+            }
+
+            let what = field.name.replace("_", "-");
+
+            if let Some(&(_, unit)) = COUNTERS.iter().find(|c| c.0 == field.name) {
+                if let Ok(value) = field.value.parse::<u64>() {
+                    let id = base.clone().tag("what", &what).tag("unit", unit).build();
+                    counters.push((Arc::new(id), value));
+                }
+
+                continue;
+            }
+
+            if let Ok(value) = field.value.parse::<f64>() {
+                let mut id = base.clone().tag("what", &what);
+
+                if let Some(unit) = gauge_unit(&field.name) {
+                    id = id.tag("unit", unit);
+                }
+
+                readings.push((Arc::new(id.build()), value));
+            }
+        }
+
+        readings.extend(self.deltas.rates(now, counters));
+        readings
+    }
+
+    /// Query the server, reporting whether it could be reached as `up`.
+    pub fn scan(&mut self, now: Instant) -> Vec<(Arc<MetricId>, f64)> {
+        let (mut readings, up) = match self.info() {
+            Ok(info) => (self.convert(now, &info), 1f64),
+            Err(e) => {
+                warn!("{}: redis info failed: {}", self.endpoint, e);
+                (Vec::new(), 0f64)
+            }
+        };
+
+        readings.push((Arc::new(self.base().tag("what", "up").build()), up));
+        readings
+    }
+}
+
+struct RedisInputInstance {
+    /// readings of the last query.
+    readings: Arc<Readings>,
+    background: Background,
+    next_update: Duration,
+}
+
+impl fmt::Debug for RedisInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RedisInputInstance")
+    }
+}
+
+impl RedisInputInstance {
+    pub fn new(id: &str, mut redis: Redis) -> Result<RedisInputInstance> {
+        let readings = Arc::new(Readings::new());
+        let results = readings.clone();
+
+        let background = Background::spawn(&format!("redis-{}", id), move || {
+            results.set(redis.scan(Instant::now()))
+        })?;
+
+        Ok(RedisInputInstance {
+            readings: readings,
+            background: background,
+            next_update: Duration::from_millis(1000),
+        })
+    }
+}
+
+impl InputInstance for RedisInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        self.readings.samples()
+    }
+
+    /// The server is queried on a thread of its own, so a slow or unreachable server never holds
+    /// up the updates of other inputs.
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(future::result(self.background.trigger()))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(RedisInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find;
+    use super::super::socket::test::serve_lines;
+
+    const INFO: &'static str = "# Server\r\n\
+        redis_version:4.0.1\r\n\
+        process_id:1234\r\n\
+        uptime_in_seconds:600\r\n\
+        \r\n\
+        # Memory\r\n\
+        used_memory:1048576\r\n\
+        used_memory_human:1.00M\r\n\
+        mem_fragmentation_ratio:1.5\r\n\
+        \r\n\
+        # Stats\r\n\
+        total_commands_processed:100\r\n\
+        \r\n\
+        # Keyspace\r\n\
+        db0:keys=10,expires=2,avg_ttl=3600\r\n";
+
+    fn respond(line: &str) -> Option<Vec<u8>> {
+        match line {
+            "AUTH" => Some(b"+OK\r\n".to_vec()),
+            "INFO" => Some(format!("${}\r\n{}\r\n", INFO.len(), INFO).into_bytes()),
+            _ => None,
+        }
+    }
+
+    fn redis(address: &str, password: Option<&str>) -> Redis {
+        Redis {
+            endpoint: Endpoint::Tcp(address.to_owned()),
+            password: password.map(ToOwned::to_owned),
+            timeout: Duration::from_secs(5),
+            tags: BTreeMap::new(),
+            deltas: Deltas::new(),
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        let (address, server) = serve_lines(respond);
+
+        let readings = redis(&address, Some("secret")).scan(Instant::now());
+        let received = server.join().unwrap();
+        assert_eq!(
+            vec!["*2", "$4", "AUTH", "$6", "secret", "*1", "$4", "INFO"],
+            received
+        );
+
+        let base = key("redis").tag("instance", &address);
+        let id = |what: &str, unit: &str| base.clone().tag("what", what).tag("unit", unit).build();
+
+        assert_eq!(Some(600f64), find(&readings, &id("uptime-in-seconds", "s")));
+        assert_eq!(Some(1048576f64), find(&readings, &id("used-memory", "bytes")));
+
+        let ratio = base.clone().tag("what", "mem-fragmentation-ratio").build();
+        assert_eq!(Some(1.5f64), find(&readings, &ratio));
+
+        let keys = base.clone()
+            .tag("db", "0")
+            .tag("what", "keyspace-keys")
+            .tag("unit", "keys")
+            .build();
+        assert_eq!(Some(10f64), find(&readings, &keys));
+
+        assert_eq!(Some(1f64), find(&readings, &base.clone().tag("what", "up").build()));
+
+        // build information and non-numeric fields are skipped, and counters need two updates.
+        assert_eq!(7, readings.len());
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut redis = redis("127.0.0.1:6379", None);
+        let now = Instant::now();
+
+        redis.convert(now, "# Stats\r\ntotal_commands_processed:100\r\n");
+        let readings = redis.convert(
+            now + Duration::from_secs(2),
+            "# Stats\r\ntotal_commands_processed:300\r\n",
+        );
+
+        let id = key("redis")
+            .tag("instance", "127.0.0.1:6379")
+            .tag("what", "total-commands-processed")
+            .tag("unit", "commands/s")
+            .build();
+        assert_eq!(Some(100f64), find(&readings, &id));
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let (address, server) = serve_lines(|line| match line {
+            "INFO" => Some(b"-NOAUTH Authentication required.\r\n".to_vec()),
+            _ => None,
+        });
+
+        let readings = redis(&address, None).scan(Instant::now());
+        server.join().unwrap();
+
+        let up = key("redis").tag("instance", &address).tag("what", "up").build();
+        assert_eq!(vec![(Arc::new(up), 0f64)], readings);
+    }
+}
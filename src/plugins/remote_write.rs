@@ -0,0 +1,425 @@
+//! Output plugin that pushes samples to a Prometheus remote write receiver, like Mimir, Thanos
+//! receive, or Prometheus itself.
+
+use errors::*;
+use plugin::*;
+use super::delivery::{Backoff, Worker};
+use super::http_client::{self, Url};
+use super::protobuf::Encoder;
+use super::snappy;
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Attempts to deliver a request before it is dropped.
+const ATTEMPTS: usize = 5;
+
+#[derive(Deserialize, Debug)]
+struct RemoteWriteOutputConfig {
+    /// Url of the remote write endpoint.
+    url: Option<String>,
+    /// Path to a PEM file with a CA to trust for https, in addition to the system's.
+    ca: Option<String>,
+    /// Number of queues sending in parallel, defaults to 4.
+    shards: Option<usize>,
+    /// Maximum number of samples per request, defaults to 500.
+    batch_size: Option<usize>,
+    /// Labels added to every series.
+    labels: Option<BTreeMap<String, String>>,
+    /// Headers added to every request, like the tenant of Mimir.
+    headers: Option<BTreeMap<String, String>>,
+    /// Timeout in seconds for sending a request, defaults to 30.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug)]
+struct RemoteWriteOutput {}
+
+impl Output for RemoteWriteOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: RemoteWriteOutputConfig = ctx.decode_config()?;
+
+        let url = config.url.ok_or_else(
+            || ErrorKind::MissingField("url".to_owned()),
+        )?;
+
+        let mut url = Url::parse(&url).map_err(|e| {
+            ErrorKind::ConfigField("url".to_owned(), e.to_string())
+        })?;
+
+        if let Some(ca) = config.ca {
+            url = url.with_ca(&ca).map_err(|e| {
+                ErrorKind::ConfigField("ca".to_owned(), e.to_string())
+            })?;
+        }
+
+        let shards = config.shards.unwrap_or(4);
+
+        if shards == 0 {
+            return Err(
+                ErrorKind::ConfigField("shards".to_owned(), "must be at least 1".to_owned())
+                    .into(),
+            );
+        }
+
+        let batch_size = config.batch_size.unwrap_or(500);
+        let timeout = Duration::from_secs(config.timeout.unwrap_or(30));
+
+        let headers: Vec<(String, String)> = config
+            .headers
+            .unwrap_or_else(BTreeMap::new)
+            .into_iter()
+            .collect();
+
+        let mut workers = Vec::new();
+
+        for shard in 0..shards {
+            let mut client = Client {
+                url: url.clone(),
+                batch_size: batch_size,
+                headers: headers.clone(),
+                timeout: timeout,
+                backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
+            };
+
+            let name = format!("remote-write-{}-{}", ctx.id, shard);
+            workers.push(Worker::spawn(&name, 16, move |series| client.deliver(series))?);
+        }
+
+        let labels = config
+            .labels
+            .unwrap_or_else(BTreeMap::new)
+            .iter()
+            .map(|(k, v)| (sanitize(k, false), v.clone()))
+            .collect();
+
+        Ok(Box::new(RemoteWriteOutputInstance {
+            labels: labels,
+            batches: Mutex::new((0..shards).map(|_| Vec::new()).collect()),
+            workers: workers,
+        }))
+    }
+}
+
+/// Replace characters which are not allowed in metric and label names.
+///
+/// Colons are only allowed in metric names.
+fn sanitize(input: &str, metric: bool) -> String {
+    let mut out: String = input
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() && (c.is_alphanumeric() || c == '_') => c,
+            ':' if metric => c,
+            _ => '_',
+        })
+        .collect();
+
+    if out.chars().next().map(|c| c.is_digit(10)).unwrap_or(true) {
+        out.insert(0, '_');
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TimeSeries {
+    /// labels, sorted by name.
+    labels: Vec<(String, String)>,
+    value: f64,
+    timestamp: i64,
+}
+
+impl TimeSeries {
+    fn encode(&self, e: &mut Encoder) {
+        for &(ref name, ref value) in &self.labels {
+            e.message(1, |l| {
+                l.string(1, name).string(2, value);
+            });
+        }
+
+        e.message(2, |s| {
+            s.double(1, self.value).int64(2, self.timestamp);
+        });
+    }
+
+    /// The shard of the series, which is the same for every sample so they are sent in order.
+    fn shard(&self, shards: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        self.labels.hash(&mut hasher);
+        (hasher.finish() % shards as u64) as usize
+    }
+}
+
+/// Build the series of a sample, with `__name__` from the key and labels from the tags and
+/// resource.
+///
+/// Configured labels take precedence, followed by tags and then resources.
+fn series(labels: &[(String, String)], sample: &Sample) -> Option<TimeSeries> {
+    if !sample.value.is_finite() {
+        return None;
+    }
+
+    let id = &sample.metric_id;
+    let mut all = vec![("__name__".to_owned(), sanitize(id.key().unwrap_or("sysmon"), true))];
+
+    for &(ref name, ref value) in labels.iter().chain(id.tags()).chain(id.resource()) {
+        let name = sanitize(name, false);
+
+        if value.is_empty() || name.starts_with("__") || all.iter().any(|l| l.0 == name) {
+            continue;
+        }
+
+        all.push((name, value.clone()));
+    }
+
+    all.sort();
+
+    Some(TimeSeries {
+        labels: all,
+        value: sample.value,
+        timestamp: sample.timestamp as i64,
+    })
+}
+
+/// Encode a snappy compressed `WriteRequest`.
+fn write_request(series: &[TimeSeries]) -> Vec<u8> {
+    let mut e = Encoder::new();
+
+    for s in series {
+        e.message(1, |ts| s.encode(ts));
+    }
+
+    snappy::compress(&e.into_bytes())
+}
+
+struct Client {
+    url: Url,
+    batch_size: usize,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    backoff: Backoff,
+}
+
+impl Client {
+    /// Post a single request, returning `false` if it should be retried.
+    fn post(&self, body: &[u8]) -> bool {
+        let mut headers = vec![
+            ("Content-Type", "application/x-protobuf"),
+            ("Content-Encoding", "snappy"),
+            ("X-Prometheus-Remote-Write-Version", "0.1.0"),
+        ];
+
+        headers.extend(self.headers.iter().map(|h| (h.0.as_str(), h.1.as_str())));
+
+        match http_client::post(&self.url, &headers, body, self.timeout) {
+            Ok(ref response) if response.is_success() => true,
+            Ok(ref response) if response.status >= 500 || response.status == 429 => {
+                warn!("{}: failed to send: {}", self.url.authority(), response.status);
+                false
+            }
+            // the receiver won't accept these samples, like when they are out of order.
+            Ok(ref response) => {
+                warn!(
+                    "{}: samples rejected: {}: {}",
+                    self.url.authority(),
+                    response.status,
+                    String::from_utf8_lossy(&response.body).trim()
+                );
+
+                true
+            }
+            Err(e) => {
+                warn!("{}: failed to send: {}", self.url.authority(), e);
+                false
+            }
+        }
+    }
+
+    fn deliver(&mut self, series: Vec<TimeSeries>) {
+        for chunk in series.chunks(self.batch_size.max(1)) {
+            let body = write_request(chunk);
+            let mut delivered = false;
+
+            for _ in 0..ATTEMPTS {
+                if self.post(&body) {
+                    self.backoff.reset();
+                    delivered = true;
+                    break;
+                }
+
+                thread::sleep(self.backoff.fail());
+            }
+
+            if !delivered {
+                warn!("{}: dropping {} samples", self.url.authority(), chunk.len());
+            }
+        }
+    }
+}
+
+struct RemoteWriteOutputInstance {
+    labels: Vec<(String, String)>,
+    /// series of the current poll cycle, per shard.
+    batches: Mutex<Vec<Vec<TimeSeries>>>,
+    workers: Vec<Worker<Vec<TimeSeries>>>,
+}
+
+impl OutputInstance for RemoteWriteOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        if let Some(series) = series(&self.labels, sample) {
+            let shard = series.shard(self.workers.len());
+            self.batches.lock()?[shard].push(series);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let mut batches = self.batches.lock()?;
+
+        for (batch, worker) in batches.iter_mut().zip(&self.workers) {
+            if !batch.is_empty() {
+                worker.send(mem::replace(batch, Vec::new()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(RemoteWriteOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use metric::*;
+    use super::super::http_client::test::serve;
+    use super::super::protobuf::{self, Field};
+    use super::super::snappy::test::decompress;
+    use std::sync::Arc;
+
+    fn sample(id: MetricIdBuilder, value: f64) -> Sample {
+        Sample {
+            metric_id: Arc::new(id.build()),
+            value: value,
+            timestamp: 1500000000123,
+        }
+    }
+
+    fn label(name: &str, value: &str) -> (String, String) {
+        (name.to_owned(), value.to_owned())
+    }
+
+    fn client(url: String) -> Client {
+        Client {
+            url: Url::parse(&url).unwrap(),
+            batch_size: 2,
+            headers: vec![label("X-Scope-OrgID", "tenant")],
+            timeout: Duration::from_secs(5),
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(100)),
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!("cpu_used", sanitize("cpu-used", false));
+        assert_eq!("node:cpu", sanitize("node:cpu", true));
+        assert_eq!("node_cpu", sanitize("node:cpu", false));
+        assert_eq!("_1m", sanitize("1m", false));
+        assert_eq!("_", sanitize("", false));
+    }
+
+    #[test]
+    fn test_series() {
+        let id = key("system")
+            .tag("what", "cpu-used")
+            .tag("unit", "%")
+            .tag("dc", "west")
+            .tag("empty", "")
+            .resource("hostname", "db1");
+
+        let labels = vec![label("dc", "east")];
+
+        assert_eq!(
+            Some(TimeSeries {
+                labels: vec![
+                    label("__name__", "system"),
+                    label("dc", "east"),
+                    label("hostname", "db1"),
+                    label("unit", "%"),
+                    label("what", "cpu-used"),
+                ],
+                value: 12.5,
+                timestamp: 1500000000123,
+            }),
+            series(&labels, &sample(id, 12.5))
+        );
+
+        assert_eq!(None, series(&labels, &sample(key("system"), ::std::f64::NAN)));
+    }
+
+    #[test]
+    fn test_shard() {
+        let a = series(&[], &sample(key("a").tag("what", "x"), 1f64)).unwrap();
+        let b = series(&[], &sample(key("a").tag("what", "x"), 2f64)).unwrap();
+
+        assert_eq!(a.shard(16), b.shard(16));
+        assert!(a.shard(16) < 16);
+        assert_eq!(0, a.shard(1));
+    }
+
+    #[test]
+    fn test_deliver() {
+        let (addr, server) = serve(vec![
+            b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 400 Bad Request\r\nContent-Length: 12\r\n\r\nout of order".to_vec(),
+        ]);
+
+        let series: Vec<TimeSeries> = (0..3)
+            .map(|i| {
+                series(&[], &sample(key("load").tag("what", &i.to_string()), i as f64)).unwrap()
+            })
+            .collect();
+
+        client(format!("http://{}/api/v1/push", addr)).deliver(series);
+
+        let requests = server.join().unwrap();
+        assert_eq!(3, requests.len());
+
+        let request = String::from_utf8_lossy(&requests[0]).into_owned();
+        assert!(request.starts_with("POST /api/v1/push HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Encoding: snappy\r\n"));
+        assert!(request.contains("X-Scope-OrgID: tenant\r\n"));
+
+        // the first request was retried, the last one was rejected and not retried.
+        let body = |request: &[u8]| {
+            let end = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+            decompress(&request[end + 4..])
+        };
+
+        assert_eq!(body(&requests[0]), body(&requests[1]));
+
+        let payload = body(&requests[1]);
+        let timeseries = protobuf::decode(&payload).unwrap();
+        assert_eq!(2, timeseries.len());
+
+        let fields = protobuf::decode(timeseries[1].1.as_bytes().unwrap()).unwrap();
+        let name = protobuf::decode(fields[0].1.as_bytes().unwrap()).unwrap();
+        assert_eq!(Some("__name__"), name[0].1.as_str());
+        assert_eq!(Some("load"), name[1].1.as_str());
+
+        let value = protobuf::decode(fields[2].1.as_bytes().unwrap()).unwrap();
+        assert_eq!((1, Field::Fixed64(1f64.to_bits())), value[0]);
+        assert_eq!((2, Field::Varint(1500000000123)), value[1]);
+
+        assert_eq!(1, protobuf::decode(&body(&requests[2])).unwrap().len());
+    }
+}
@@ -0,0 +1,617 @@
+//! Output plugin that sends samples as Riemann events, over TCP.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+use super::delivery::{Backoff, Worker};
+use super::protobuf::{self, Encoder};
+use super::utils::read_text;
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Attempts to deliver a batch before it is dropped.
+const ATTEMPTS: usize = 3;
+
+/// Largest acknowledgement accepted from the server.
+const MAX_ACK: usize = 1024 * 1024;
+
+#[derive(Deserialize, Debug)]
+struct ThresholdConfig {
+    /// Key of the samples to match, matching all keys if not specified.
+    key: Option<String>,
+    /// Value of the `what` tag of the samples to match, matching all if not specified.
+    what: Option<String>,
+    /// Value from which the state is warning.
+    warning: Option<f64>,
+    /// Value from which the state is critical.
+    critical: Option<f64>,
+    /// Whether lower values are worse, like for free space.
+    invert: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RiemannOutputConfig {
+    /// Address of the Riemann server, defaults to 127.0.0.1:5555.
+    address: Option<String>,
+    /// Host of all events, defaults to the host in the resource of samples, or the hostname.
+    host: Option<String>,
+    /// Time to live of events in seconds, defaults to twice the poll interval.
+    ttl: Option<f32>,
+    /// Thresholds from which the state of events is determined.
+    thresholds: Option<Vec<ThresholdConfig>>,
+    /// Riemann tags added to every event.
+    tags: Option<Vec<String>>,
+    /// Attributes added to every event.
+    attributes: Option<BTreeMap<String, String>>,
+    /// Timeout in seconds for connecting, sending and waiting for acknowledgements, defaults to 5.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug)]
+struct RiemannOutput {}
+
+impl Output for RiemannOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: RiemannOutputConfig = ctx.decode_config()?;
+
+        let poll_interval = ctx.poll_interval.as_secs() as f32 +
+            ctx.poll_interval.subsec_nanos() as f32 / 1e9;
+
+        let mut thresholds = Vec::new();
+
+        for t in config.thresholds.unwrap_or_else(Vec::new) {
+            if t.warning.is_none() && t.critical.is_none() {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "thresholds".to_owned(),
+                        "expected warning or critical".to_owned(),
+                    ).into(),
+                );
+            }
+
+            thresholds.push(Threshold {
+                key: t.key,
+                what: t.what,
+                warning: t.warning,
+                critical: t.critical,
+                invert: t.invert.unwrap_or(false),
+            });
+        }
+
+        let converter = Converter {
+            host: config.host,
+            fallback_host: read_text("/proc/sys/kernel/hostname").unwrap_or("localhost".to_owned()),
+            ttl: config.ttl.unwrap_or(poll_interval * 2f32),
+            thresholds: thresholds,
+            tags: config.tags.unwrap_or_else(Vec::new),
+            attributes: config.attributes.unwrap_or_else(BTreeMap::new).into_iter().collect(),
+        };
+
+        let mut client = Client {
+            address: config.address.unwrap_or("127.0.0.1:5555".to_owned()),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            stream: None,
+            backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
+        };
+
+        let worker = Worker::spawn(&format!("riemann-{}", ctx.id), 16, move |events| {
+            client.deliver(events)
+        })?;
+
+        Ok(Box::new(RiemannOutputInstance {
+            converter: converter,
+            batch: Mutex::new(Vec::new()),
+            worker: worker,
+        }))
+    }
+}
+
+/// Determines the state of matching samples.
+#[derive(Debug)]
+struct Threshold {
+    key: Option<String>,
+    what: Option<String>,
+    warning: Option<f64>,
+    critical: Option<f64>,
+    invert: bool,
+}
+
+impl Threshold {
+    fn matches(&self, id: &MetricId) -> bool {
+        let key = self.key.as_ref().map(|k| Some(k.as_str()) == id.key()).unwrap_or(true);
+        let what = self.what.as_ref().map(|w| Some(w.as_str()) == id.tag("what")).unwrap_or(
+            true,
+        );
+
+        key && what
+    }
+
+    fn state(&self, value: f64) -> &'static str {
+        let reached = |level: Option<f64>| match level {
+            Some(level) if self.invert => value <= level,
+            Some(level) => value >= level,
+            None => false,
+        };
+
+        if reached(self.critical) {
+            "critical"
+        } else if reached(self.warning) {
+            "warning"
+        } else {
+            "ok"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    time_micros: i64,
+    state: Option<&'static str>,
+    service: String,
+    host: String,
+    tags: Vec<String>,
+    ttl: f32,
+    attributes: Vec<(String, String)>,
+    metric: f64,
+}
+
+impl Event {
+    fn encode(&self, e: &mut Encoder) {
+        e.int64(1, self.time_micros / 1_000_000);
+
+        if let Some(state) = self.state {
+            e.string(2, state);
+        }
+
+        e.string(3, &self.service).string(4, &self.host);
+
+        for tag in &self.tags {
+            e.string(7, tag);
+        }
+
+        e.float(8, self.ttl);
+
+        for &(ref k, ref v) in &self.attributes {
+            e.message(9, |a| {
+                a.string(1, k).string(2, v);
+            });
+        }
+
+        e.int64(10, self.time_micros).double(14, self.metric);
+    }
+}
+
+fn encode_length(length: usize) -> [u8; 4] {
+    [(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]
+}
+
+fn decode_length(bytes: &[u8; 4]) -> usize {
+    bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+}
+
+/// Encode events as a length-prefixed `Msg` frame.
+fn frame(events: &[Event]) -> Vec<u8> {
+    let mut msg = Encoder::new();
+
+    for event in events {
+        msg.message(6, |e| event.encode(e));
+    }
+
+    let msg = msg.into_bytes();
+    let mut frame = Vec::with_capacity(msg.len() + 4);
+    frame.extend_from_slice(&encode_length(msg.len()));
+    frame.extend_from_slice(&msg);
+    frame
+}
+
+/// Converts samples into events.
+struct Converter {
+    host: Option<String>,
+    fallback_host: String,
+    ttl: f32,
+    thresholds: Vec<Threshold>,
+    tags: Vec<String>,
+    attributes: Vec<(String, String)>,
+}
+
+impl Converter {
+    /// Build an event, where the service is the key followed by the values of the tags.
+    ///
+    /// All tags are also added as attributes, so they can be matched on individually.
+    fn event(&self, id: &MetricId, value: f64, time_micros: i64) -> Option<Event> {
+        if !value.is_finite() {
+            return None;
+        }
+
+        let mut service = vec![id.key().unwrap_or("sysmon")];
+        let mut attributes = self.attributes.clone();
+        let mut host = None;
+
+        if let Some(what) = id.tag("what") {
+            service.push(what);
+        }
+
+        for &(ref k, ref v) in id.tags() {
+            if k != "what" && k != "unit" && !v.is_empty() {
+                service.push(v);
+            }
+
+            if !attributes.iter().any(|a| a.0 == *k) {
+                attributes.push((k.clone(), v.clone()));
+            }
+        }
+
+        for &(ref k, ref v) in id.resource() {
+            if k == "host" || k == "hostname" {
+                host = Some(v.as_str());
+                continue;
+            }
+
+            if !attributes.iter().any(|a| a.0 == *k) {
+                attributes.push((k.clone(), v.clone()));
+            }
+        }
+
+        let host = match self.host {
+            Some(ref host) => host.as_str(),
+            None => host.unwrap_or(&self.fallback_host),
+        };
+
+        let state = self.thresholds.iter().find(|t| t.matches(id)).map(|t| t.state(value));
+
+        Some(Event {
+            time_micros: time_micros,
+            state: state,
+            service: service.join(" "),
+            host: host.to_owned(),
+            tags: self.tags.clone(),
+            ttl: self.ttl,
+            attributes: attributes,
+            metric: value,
+        })
+    }
+}
+
+/// Check the `Msg` acknowledging a frame.
+fn check_ack(msg: &[u8]) -> Result<()> {
+    let mut ok = false;
+    let mut error = None;
+
+    for (field, value) in protobuf::decode(msg)? {
+        match field {
+            2 => ok = value.as_u64() == Some(1),
+            3 => error = value.as_str(),
+            _ => {}
+        }
+    }
+
+    match (ok, error) {
+        (true, _) => Ok(()),
+        (false, Some(error)) => Err(ErrorKind::Message(format!("rejected: {}", error)).into()),
+        (false, None) => Err(ErrorKind::Message("not acknowledged".to_owned()).into()),
+    }
+}
+
+struct Client {
+    address: String,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    backoff: Backoff,
+}
+
+impl Client {
+    fn connect(&self) -> Result<TcpStream> {
+        let addr = match self.address.to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => {
+                return Err(
+                    ErrorKind::Message(format!("no address for: {}", self.address)).into(),
+                )
+            }
+        };
+
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+
+    /// Send a frame and wait for its acknowledgement.
+    ///
+    /// Returns the acknowledgement, so that rejections can be told apart from failed connections.
+    fn send(&mut self, frame: &[u8]) -> Result<Vec<u8>> {
+        if self.stream.is_none() {
+            self.stream = Some(self.connect()?);
+        }
+
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => return Err(ErrorKind::Message("not connected".to_owned()).into()),
+        };
+
+        stream.write_all(frame)?;
+
+        let mut length = [0u8; 4];
+        stream.read_exact(&mut length)?;
+        let length = decode_length(&length);
+
+        if length > MAX_ACK {
+            return Err(ErrorKind::Message(format!("acknowledgement too large: {}", length)).into());
+        }
+
+        let mut msg = vec![0u8; length];
+        stream.read_exact(&mut msg)?;
+        Ok(msg)
+    }
+
+    fn deliver(&mut self, events: Vec<Event>) {
+        let frame = frame(&events);
+
+        for _ in 0..ATTEMPTS {
+            match self.send(&frame) {
+                Ok(ack) => {
+                    self.backoff.reset();
+
+                    // the server understood the frame, so sending it again won't help.
+                    if let Err(e) = check_ack(&ack) {
+                        warn!("{}: {} events: {}", self.address, events.len(), e);
+                    }
+
+                    return;
+                }
+                Err(e) => {
+                    warn!("{}: failed to send: {}", self.address, e);
+                    self.stream = None;
+                    thread::sleep(self.backoff.fail());
+                }
+            }
+        }
+
+        warn!("{}: dropping {} events", self.address, events.len());
+    }
+}
+
+struct RiemannOutputInstance {
+    converter: Converter,
+    /// events of the current poll cycle.
+    batch: Mutex<Vec<Event>>,
+    worker: Worker<Vec<Event>>,
+}
+
+impl OutputInstance for RiemannOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| {
+            ErrorKind::Message(e.to_string())
+        })?;
+
+        let time_micros = now.as_secs() as i64 * 1_000_000 + now.subsec_nanos() as i64 / 1_000;
+
+        if let Some(event) = self.converter.event(&sample.metric_id, sample.value, time_micros) {
+            self.batch.lock()?.push(event);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let events = mem::replace(&mut *self.batch.lock()?, Vec::new());
+
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        self.worker.send(events)
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(RiemannOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::protobuf::Field;
+    use std::net::TcpListener;
+
+    fn converter() -> Converter {
+        Converter {
+            host: None,
+            fallback_host: "example".to_owned(),
+            ttl: 20f32,
+            thresholds: vec![
+                Threshold {
+                    key: Some("disk".to_owned()),
+                    what: Some("used".to_owned()),
+                    warning: Some(80f64),
+                    critical: Some(90f64),
+                    invert: false,
+                },
+                Threshold {
+                    key: None,
+                    what: Some("free".to_owned()),
+                    warning: None,
+                    critical: Some(10f64),
+                    invert: true,
+                },
+            ],
+            tags: vec!["sysmon".to_owned()],
+            attributes: vec![("dc".to_owned(), "east".to_owned())],
+        }
+    }
+
+    fn client(address: String) -> Client {
+        Client {
+            address: address,
+            timeout: Duration::from_secs(5),
+            stream: None,
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(100)),
+        }
+    }
+
+    /// Decoded event, as seen by the stand-in server.
+    #[derive(Debug, PartialEq)]
+    struct Decoded {
+        service: String,
+        host: String,
+        state: Option<String>,
+        metric: f64,
+        ttl: f32,
+        attributes: Vec<(String, String)>,
+    }
+
+    fn decode_event(bytes: &[u8]) -> Decoded {
+        let mut event = Decoded {
+            service: String::new(),
+            host: String::new(),
+            state: None,
+            metric: 0f64,
+            ttl: 0f32,
+            attributes: Vec::new(),
+        };
+
+        for (field, value) in protobuf::decode(bytes).unwrap() {
+            match (field, value) {
+                (2, v) => event.state = v.as_str().map(ToOwned::to_owned),
+                (3, v) => event.service = v.as_str().unwrap().to_owned(),
+                (4, v) => event.host = v.as_str().unwrap().to_owned(),
+                (8, Field::Fixed32(v)) => event.ttl = f32::from_bits(v),
+                (9, v) => {
+                    let a = protobuf::decode(v.as_bytes().unwrap()).unwrap();
+                    event.attributes.push((
+                        a[0].1.as_str().unwrap().to_owned(),
+                        a[1].1.as_str().unwrap().to_owned(),
+                    ));
+                }
+                (14, Field::Fixed64(v)) => event.metric = f64::from_bits(v),
+                _ => {}
+            }
+        }
+
+        event
+    }
+
+    /// Serve a single connection, decoding every frame and answering it with the given ack.
+    fn serve(frames: usize, ack: Vec<u8>) -> (String, thread::JoinHandle<Vec<Vec<Decoded>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+
+            for _ in 0..frames {
+                let mut length = [0u8; 4];
+                stream.read_exact(&mut length).unwrap();
+                let mut msg = vec![0u8; decode_length(&length)];
+                stream.read_exact(&mut msg).unwrap();
+
+                received.push(
+                    protobuf::decode(&msg)
+                        .unwrap()
+                        .into_iter()
+                        .filter(|&(field, _)| field == 6)
+                        .map(|(_, event)| decode_event(event.as_bytes().unwrap()))
+                        .collect(),
+                );
+
+                stream.write_all(&encode_length(ack.len())).unwrap();
+                stream.write_all(&ack).unwrap();
+            }
+
+            received
+        });
+
+        (address, server)
+    }
+
+    #[test]
+    fn test_event() {
+        let id = key("disk")
+            .tag("what", "used")
+            .tag("unit", "%")
+            .tag("mount", "/")
+            .resource("hostname", "db1")
+            .build();
+
+        let event = converter().event(&id, 85f64, 1_500_000_000_000_000).unwrap();
+
+        assert_eq!("disk used /", event.service);
+        assert_eq!("db1", event.host);
+        assert_eq!(Some("warning"), event.state);
+        assert_eq!(
+            vec![
+                ("dc".to_owned(), "east".to_owned()),
+                ("what".to_owned(), "used".to_owned()),
+                ("unit".to_owned(), "%".to_owned()),
+                ("mount".to_owned(), "/".to_owned()),
+            ],
+            event.attributes
+        );
+
+        let free = key("memory").tag("what", "free").build();
+        let event = converter().event(&free, 5f64, 0).unwrap();
+        assert_eq!("example", event.host);
+        assert_eq!(Some("critical"), event.state);
+
+        let load = key("load").tag("what", "1m").build();
+        assert_eq!(None, converter().event(&load, 1f64, 0).unwrap().state);
+        assert_eq!(None, converter().event(&load, ::std::f64::NAN, 0));
+    }
+
+    #[test]
+    fn test_check_ack() {
+        let mut ok = Encoder::new();
+        ok.uint64(2, 1);
+        assert!(check_ack(&ok.into_bytes()).is_ok());
+
+        let mut rejected = Encoder::new();
+        rejected.uint64(2, 0).string(3, "bad event");
+        match *check_ack(&rejected.into_bytes()).unwrap_err().kind() {
+            ErrorKind::Message(ref m) => assert_eq!("rejected: bad event", m),
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_deliver() {
+        let mut ack = Encoder::new();
+        ack.uint64(2, 1);
+        let (address, server) = serve(2, ack.into_bytes());
+
+        let id = key("disk").tag("what", "used").tag("mount", "/").build();
+        let event = converter().event(&id, 95.5f64, 1_500_000_000_000_000).unwrap();
+
+        let mut client = client(address);
+        client.deliver(vec![event.clone(), event]);
+        client.deliver(vec![converter().event(&key("load").build(), 1f64, 0).unwrap()]);
+
+        let frames = server.join().unwrap();
+        assert_eq!(2, frames.len());
+        assert_eq!(2, frames[0].len());
+
+        assert_eq!(
+            Decoded {
+                service: "disk used /".to_owned(),
+                host: "example".to_owned(),
+                state: Some("critical".to_owned()),
+                metric: 95.5f64,
+                ttl: 20f32,
+                attributes: vec![
+                    ("dc".to_owned(), "east".to_owned()),
+                    ("what".to_owned(), "used".to_owned()),
+                    ("mount".to_owned(), "/".to_owned()),
+                ],
+            },
+            frames[0][0]
+        );
+
+        assert_eq!("load", frames[1][0].service);
+    }
+}
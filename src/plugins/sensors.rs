@@ -0,0 +1,333 @@
+//! Input plugin that reports thermal zones and hwmon sensors through sysfs.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::common::single_i64;
+use super::utils::{list_dir, read_file, read_text};
+
+use futures::*;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct SensorsInputConfig {
+    /// Root of the sysfs filesystem, defaults to /sys.
+    root: Option<String>,
+}
+
+#[derive(Debug)]
+struct SensorsInput {}
+
+impl Input for SensorsInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: SensorsInputConfig = ctx.decode_config()?;
+        let root = PathBuf::from(config.root.unwrap_or("/sys".to_owned()));
+        Ok(Box::new(SensorsInputInstance::new(root)))
+    }
+}
+
+/// The kinds of hwmon sensors that are reported, with the divisor to convert them into their unit.
+///
+/// See https://www.kernel.org/doc/Documentation/hwmon/sysfs-interface
+const HWMON_SENSORS: &'static [(&'static str, &'static str, &'static str, f64)] = &[
+    ("temp", "temperature", "C", 1e3),
+    ("fan", "fan", "rpm", 1f64),
+    ("in", "voltage", "V", 1e3),
+    ("power", "power", "W", 1e6),
+];
+
+/// Read a single integer from the given file.
+///
+/// Sensors that are present but not readable (e.g. powered down) are skipped.
+fn read_value(path: &Path) -> Option<i64> {
+    read_file(path).ok().and_then(|b| single_i64(&b).to_full_result().ok())
+}
+
+/// Identify the device of a hwmon chip, since several chips can have the same driver name.
+///
+/// This is the name the device links to, like `nvme0` or `0000:01:00.0`, which is stable across
+/// reboots unlike the number of the hwmon directory it falls back to.
+fn hwmon_device(hwmon: &Path) -> String {
+    let link = fs::read_link(hwmon.join("device")).ok();
+
+    link.as_ref()
+        .and_then(|l| l.file_name())
+        .or_else(|| hwmon.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+struct Sensors {
+    root: PathBuf,
+}
+
+impl Sensors {
+    pub fn new(root: PathBuf) -> Sensors {
+        Sensors { root: root }
+    }
+
+    fn scan_thermal(&self, readings: &mut Vec<(Arc<MetricId>, f64)>) -> Result<()> {
+        for zone in list_dir(&self.root.join("class/thermal"), "thermal_zone")? {
+            let value = match read_value(&zone.join("temp")) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let zone_name = zone.file_name().unwrap().to_string_lossy().into_owned();
+            let label = read_text(&zone.join("type")).unwrap_or(zone_name.clone());
+
+            let id = key("system")
+                .tag("what", "temperature")
+                .tag("unit", "C")
+                .tag("chip", &zone_name)
+                .tag("label", &label)
+                .build();
+
+            readings.push((Arc::new(id), value as f64 / 1e3));
+        }
+
+        Ok(())
+    }
+
+    fn scan_hwmon(&self, readings: &mut Vec<(Arc<MetricId>, f64)>) -> Result<()> {
+        for hwmon in list_dir(&self.root.join("class/hwmon"), "hwmon")? {
+            // older drivers expose their attributes through the device directory.
+            let dir = match hwmon.join("name").is_file() {
+                true => hwmon.clone(),
+                false => hwmon.join("device"),
+            };
+
+            let chip = match read_text(&dir.join("name")) {
+                Some(chip) => chip,
+                None => continue,
+            };
+
+            let device = hwmon_device(&hwmon);
+
+            for &(prefix, what, unit, divisor) in HWMON_SENSORS {
+                for input in list_dir(&dir, prefix)? {
+                    let file_name = input.file_name().unwrap().to_string_lossy().into_owned();
+
+                    if !file_name.ends_with("_input") {
+                        continue;
+                    }
+
+                    let sensor = &file_name[..file_name.len() - "_input".len()];
+
+                    // guard against prefixes matching other sensors, like `in` and `intrusion`.
+                    if !sensor[prefix.len()..].chars().all(|c| c.is_digit(10)) {
+                        continue;
+                    }
+
+                    let value = match read_value(&input) {
+                        Some(value) => value,
+                        None => continue,
+                    };
+
+                    let label = read_text(&dir.join(format!("{}_label", sensor)))
+                        .unwrap_or(sensor.to_owned());
+
+                    let id = key("system")
+                        .tag("what", what)
+                        .tag("unit", unit)
+                        .tag("chip", &chip)
+                        .tag("device", &device)
+                        .tag("label", &label)
+                        .build();
+
+                    readings.push((Arc::new(id), value as f64 / divisor));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn scan(&self) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let mut readings = Vec::new();
+        self.scan_thermal(&mut readings)?;
+        self.scan_hwmon(&mut readings)?;
+        Ok(readings)
+    }
+}
+
+struct Metrics {
+    sensors: Sensors,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        self.readings = self.sensors.scan()?;
+        Ok(())
+    }
+}
+
+struct SensorsInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for SensorsInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SensorsInputInstance")
+    }
+}
+
+impl SensorsInputInstance {
+    pub fn new(root: PathBuf) -> SensorsInputInstance {
+        SensorsInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                sensors: Sensors::new(root),
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for SensorsInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(SensorsInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{self, write_fixture};
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("sysmon-sensors-{}", name));
+        let _ = fs::remove_dir_all(&root);
+
+        for &(path, content) in files {
+            write_fixture(&root, path, content);
+        }
+
+        root
+    }
+
+    fn find(
+        readings: &[(Arc<MetricId>, f64)],
+        what: &str,
+        unit: &str,
+        chip: &str,
+        device: Option<&str>,
+        label: &str,
+    ) -> Option<f64> {
+        let mut expected = key("system").tag("what", what).tag("unit", unit).tag("chip", chip);
+
+        if let Some(device) = device {
+            expected = expected.tag("device", device);
+        }
+
+        test_helpers::find(readings, &expected.tag("label", label).build())
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = fixture(
+            "scan",
+            &[
+                ("class/thermal/thermal_zone0/type", "x86_pkg_temp\n"),
+                ("class/thermal/thermal_zone0/temp", "45000\n"),
+                ("class/hwmon/hwmon0/name", "coretemp\n"),
+                ("class/hwmon/hwmon0/temp1_input", "47000\n"),
+                ("class/hwmon/hwmon0/temp1_label", "Package id 0\n"),
+                ("class/hwmon/hwmon1/device/name", "nct6775\n"),
+                ("class/hwmon/hwmon1/device/fan2_input", "1200\n"),
+                ("class/hwmon/hwmon1/device/in0_input", "1150\n"),
+                ("class/hwmon/hwmon1/device/intrusion0_alarm", "0\n"),
+                ("class/hwmon/hwmon2/name", "acpi_power\n"),
+                ("class/hwmon/hwmon2/power1_input", "12500000\n"),
+            ],
+        );
+
+        // two chips of the same driver, told apart by the devices they link to.
+        fs::create_dir_all(root.join("devices/nvme0")).unwrap();
+        fs::create_dir_all(root.join("devices/nvme1")).unwrap();
+
+        let nvme = [("hwmon3", "nvme0", "38850\n"), ("hwmon4", "nvme1", "41850\n")];
+
+        for &(hwmon, device, temp) in &nvme {
+            let dir = root.join("class/hwmon").join(hwmon);
+            fs::create_dir_all(&dir).unwrap();
+            ::std::os::unix::fs::symlink(root.join("devices").join(device), dir.join("device"))
+                .unwrap();
+            File::create(dir.join("name")).unwrap().write_all(b"nvme\n").unwrap();
+            File::create(dir.join("temp1_input")).unwrap().write_all(temp.as_bytes()).unwrap();
+        }
+
+        let readings = Sensors::new(root).scan().unwrap();
+
+        assert_eq!(7, readings.len());
+        assert_eq!(
+            Some(45f64),
+            find(&readings, "temperature", "C", "thermal_zone0", None, "x86_pkg_temp")
+        );
+        assert_eq!(
+            Some(47f64),
+            find(&readings, "temperature", "C", "coretemp", Some("hwmon0"), "Package id 0")
+        );
+        assert_eq!(Some(1200f64), find(&readings, "fan", "rpm", "nct6775", Some("hwmon1"), "fan2"));
+        assert_eq!(
+            Some(1.15f64),
+            find(&readings, "voltage", "V", "nct6775", Some("hwmon1"), "in0")
+        );
+        assert_eq!(
+            Some(12.5f64),
+            find(&readings, "power", "W", "acpi_power", Some("hwmon2"), "power1")
+        );
+        assert_eq!(
+            Some(38.85f64),
+            find(&readings, "temperature", "C", "nvme", Some("nvme0"), "temp1")
+        );
+        assert_eq!(
+            Some(41.85f64),
+            find(&readings, "temperature", "C", "nvme", Some("nvme1"), "temp1")
+        );
+    }
+
+    #[test]
+    fn test_scan_empty() {
+        let root = fixture("empty", &[]);
+        assert_eq!(0, Sensors::new(root).scan().unwrap().len());
+    }
+}
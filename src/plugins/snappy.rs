@@ -0,0 +1,224 @@
+//! Snappy compression in the raw block format, as used by the Prometheus remote write protocol.
+//!
+//! Only compression is needed, so this is a simple greedy encoder which finds matches of four
+//! bytes or more through a hash table.
+
+/// Input is compressed in blocks of this size, so that all offsets fit in two bytes.
+const BLOCK_SIZE: usize = 1 << 16;
+
+const HASH_BITS: u32 = 14;
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+fn load(input: &[u8], pos: usize) -> u32 {
+    input[pos] as u32 | (input[pos + 1] as u32) << 8 | (input[pos + 2] as u32) << 16 |
+        (input[pos + 3] as u32) << 24
+}
+
+fn hash(value: u32) -> usize {
+    (value.wrapping_mul(0x1e35a7bd) >> (32 - HASH_BITS)) as usize
+}
+
+fn emit_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+
+    let n = literal.len() - 1;
+
+    if n < 60 {
+        out.push((n as u8) << 2);
+    } else if n < 1 << 8 {
+        out.push(60 << 2);
+        out.push(n as u8);
+    } else {
+        out.push(61 << 2);
+        out.push(n as u8);
+        out.push((n >> 8) as u8);
+    }
+
+    out.extend_from_slice(literal);
+}
+
+/// Emit a copy with a two byte offset, of 1 to 64 bytes.
+fn emit_copy2(out: &mut Vec<u8>, offset: usize, len: usize) {
+    out.push(2 | ((len - 1) as u8) << 2);
+    out.push(offset as u8);
+    out.push((offset >> 8) as u8);
+}
+
+/// Emit copies of at least four bytes, using the short form when possible.
+fn emit_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    while len >= 68 {
+        emit_copy2(out, offset, 64);
+        len -= 64;
+    }
+
+    // leave at least four bytes for the last copy.
+    if len > 64 {
+        emit_copy2(out, offset, 60);
+        len -= 60;
+    }
+
+    if len < 12 && offset < 2048 {
+        out.push(1 | ((len - 4) as u8) << 2 | ((offset >> 8) as u8) << 5);
+        out.push(offset as u8);
+    } else {
+        emit_copy2(out, offset, len);
+    }
+}
+
+fn compress_block(out: &mut Vec<u8>, block: &[u8]) {
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut pos = 0;
+
+    while pos + 4 <= block.len() {
+        let value = load(block, pos);
+        let h = hash(value);
+        // positions are stored plus one, so that zero means empty.
+        let candidate = table[h];
+        table[h] = pos + 1;
+
+        if candidate == 0 || load(block, candidate - 1) != value {
+            pos += 1;
+            continue;
+        }
+
+        let candidate = candidate - 1;
+        let mut len = 4;
+
+        while pos + len < block.len() && block[candidate + len] == block[pos + len] {
+            len += 1;
+        }
+
+        emit_literal(out, &block[literal_start..pos]);
+        emit_copy(out, pos - candidate, len);
+
+        pos += len;
+        literal_start = pos;
+    }
+
+    emit_literal(out, &block[literal_start..]);
+}
+
+/// Compress the input.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    push_varint(&mut out, input.len() as u64);
+
+    for block in input.chunks(BLOCK_SIZE) {
+        compress_block(&mut out, block);
+    }
+
+    out
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Decompress the input, as the receiving end would.
+    pub fn decompress(input: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let mut length = 0usize;
+        let mut shift = 0;
+
+        loop {
+            let byte = input[pos];
+            pos += 1;
+            length |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut out = Vec::with_capacity(length);
+
+        while pos < input.len() {
+            let tag = input[pos];
+            pos += 1;
+
+            let (len, offset) = match tag & 0x3 {
+                0 => {
+                    let mut n = (tag >> 2) as usize;
+
+                    if n >= 60 {
+                        let bytes = n - 59;
+                        n = (0..bytes).fold(0, |acc, i| acc | (input[pos + i] as usize) << (8 * i));
+                        pos += bytes;
+                    }
+
+                    out.extend_from_slice(&input[pos..pos + n + 1]);
+                    pos += n + 1;
+                    continue;
+                }
+                1 => {
+                    let offset = ((tag as usize) >> 5) << 8 | input[pos] as usize;
+                    pos += 1;
+                    (((tag >> 2) & 0x7) as usize + 4, offset)
+                }
+                2 => {
+                    let offset = input[pos] as usize | (input[pos + 1] as usize) << 8;
+                    pos += 2;
+                    ((tag >> 2) as usize + 1, offset)
+                }
+                _ => panic!("unsupported copy"),
+            };
+
+            let start = out.len() - offset;
+
+            for i in 0..len {
+                let byte = out[start + i];
+                out.push(byte);
+            }
+        }
+
+        assert_eq!(length, out.len());
+        out
+    }
+
+    #[test]
+    fn test_literal() {
+        assert_eq!(vec![3, 2 << 2, b'a', b'b', b'c'], compress(b"abc"));
+        assert_eq!(vec![0], compress(b""));
+    }
+
+    #[test]
+    fn test_copy() {
+        let input = b"abcdabcdabcdabcd";
+        let compressed = compress(input);
+
+        // a literal of four bytes, and a single overlapping copy of twelve.
+        assert_eq!(vec![16, 3 << 2, b'a', b'b', b'c', b'd', 2 | 11 << 2, 4, 0], compressed);
+        assert_eq!(input.to_vec(), decompress(&compressed));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut input = Vec::new();
+
+        for i in 0..20000 {
+            input.extend(format!("sample{{host=\"db{}\"}} {}\n", i % 7, i * 31 % 1000).bytes());
+        }
+
+        let compressed = compress(&input);
+        assert!(compressed.len() < input.len() / 3);
+        assert_eq!(input, decompress(&compressed));
+
+        let noise: Vec<u8> = (0..100000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+
+        assert_eq!(noise, decompress(&compress(&noise)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Blocking connections to local services, over TCP or Unix sockets.
+
+use errors::*;
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A connected stream, of either kind.
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Where a service is listening.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Build an endpoint from the `address` and `unix` configuration fields, where at most one
+    /// may be set. Defaults to the given TCP address.
+    pub fn from_config(
+        address: Option<String>,
+        unix: Option<String>,
+        default: &str,
+    ) -> Result<Endpoint> {
+        match (address, unix) {
+            (Some(_), Some(_)) => Err(
+                ErrorKind::ConfigField(
+                    "unix".to_owned(),
+                    "can't be combined with address".to_owned(),
+                ).into(),
+            ),
+            (Some(address), None) => Ok(Endpoint::Tcp(address)),
+            (None, Some(unix)) => Ok(Endpoint::Unix(PathBuf::from(unix))),
+            (None, None) => Ok(Endpoint::Tcp(default.to_owned())),
+        }
+    }
+
+    /// Connect to the endpoint, where the timeout applies to connecting and every read and
+    /// write.
+    pub fn connect(&self, timeout: Duration) -> Result<Box<Stream>> {
+        match *self {
+            Endpoint::Tcp(ref address) => {
+                let addr = match address.to_socket_addrs()?.next() {
+                    Some(addr) => addr,
+                    None => {
+                        return Err(
+                            ErrorKind::Message(format!("no address for: {}", address)).into(),
+                        )
+                    }
+                };
+
+                let stream = TcpStream::connect_timeout(&addr, timeout)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            Endpoint::Unix(ref path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Endpoint::Tcp(ref address) => write!(f, "{}", address),
+            Endpoint::Unix(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve a single connection on a local port, answering every line received with the
+    /// response produced for it, if any.
+    ///
+    /// Returns the address, and a handle resolving to the lines received.
+    pub fn serve_lines<F>(respond: F) -> (String, thread::JoinHandle<Vec<String>>)
+    where
+        F: Fn(&str) -> Option<Vec<u8>> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut received = Vec::new();
+
+            loop {
+                let mut line = String::new();
+
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+
+                let line = line.trim_right().to_owned();
+
+                if let Some(response) = respond(&line) {
+                    reader.get_mut().write_all(&response).unwrap();
+                }
+
+                received.push(line);
+            }
+
+            received
+        });
+
+        (addr.to_string(), handle)
+    }
+}
@@ -0,0 +1,327 @@
+//! Input plugin that accepts metrics pushed over the StatsD protocol.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::statsd::*;
+use super::listener::{bind_tcp, bind_udp, bind_unix_datagram};
+use super::utils::lines;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Deserialize, Debug)]
+struct StatsdInputConfig {
+    /// UDP address to bind, defaults to 127.0.0.1:8125.
+    bind: Option<SocketAddr>,
+    /// Optional TCP address to bind.
+    tcp: Option<SocketAddr>,
+    /// Optional path to a Unix datagram socket to bind.
+    unix: Option<String>,
+    /// Percentiles to report for timers and histograms, defaults to 50, 90 and 99.
+    percentiles: Option<Vec<f64>>,
+    /// Tags added to every sample.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct StatsdInput {}
+
+impl Input for StatsdInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: StatsdInputConfig = ctx.decode_config()?;
+
+        let percentiles = config.percentiles.unwrap_or(vec![50f64, 90f64, 99f64]);
+
+        if let Some(p) = percentiles.iter().find(|p| **p < 0f64 || **p > 100f64) {
+            return Err(
+                ErrorKind::ConfigField(
+                    "percentiles".to_owned(),
+                    format!("expected a value between 0 and 100, got: {}", p),
+                ).into(),
+            );
+        }
+
+        let aggregator = Arc::new(Mutex::new(Aggregator::new(
+            ctx.id,
+            config.tags.unwrap_or_else(BTreeMap::new),
+            percentiles,
+        )));
+
+        let default_addr = "127.0.0.1:8125".parse::<SocketAddr>().map_err(|e| {
+            ErrorKind::Message(e.to_string())
+        })?;
+
+        let ref mut core = ctx.core.try_borrow_mut()?;
+        let handle = core.handle();
+
+        bind_udp(
+            &handle,
+            &config.bind.unwrap_or(default_addr),
+            ingest(aggregator.clone()),
+        )?;
+
+        if let Some(tcp) = config.tcp {
+            bind_tcp(&handle, &tcp, ingest(aggregator.clone()))?;
+        }
+
+        if let Some(unix) = config.unix {
+            bind_unix_datagram(&handle, &PathBuf::from(unix), ingest(aggregator.clone()))?;
+        }
+
+        Ok(Box::new(StatsdInputInstance { aggregator: aggregator }))
+    }
+}
+
+/// Build a callback which feeds received data into the aggregator.
+fn ingest(aggregator: Arc<Mutex<Aggregator>>) -> impl Fn(&[u8]) + Clone + 'static {
+    move |buffer: &[u8]| match aggregator.lock() {
+        Ok(mut aggregator) => aggregator.ingest(buffer),
+        Err(e) => warn!("failed to lock aggregator: {}", e),
+    }
+}
+
+/// Aggregates received metrics between polls.
+///
+/// Counters are summed, gauges keep their last value, timers and histograms are reported as
+/// percentiles, and sets as the number of unique values.
+struct Aggregator {
+    id: String,
+    tags: BTreeMap<String, String>,
+    percentiles: Vec<f64>,
+    counters: HashMap<MetricIdBuilder, f64>,
+    gauges: HashMap<MetricIdBuilder, f64>,
+    timers: HashMap<MetricIdBuilder, Distribution>,
+    sets: HashMap<MetricIdBuilder, HashSet<String>>,
+    malformed: u64,
+}
+
+impl Aggregator {
+    pub fn new(id: &str, tags: BTreeMap<String, String>, percentiles: Vec<f64>) -> Aggregator {
+        Aggregator {
+            id: id.to_owned(),
+            tags: tags,
+            percentiles: percentiles,
+            counters: HashMap::new(),
+            gauges: HashMap::new(),
+            timers: HashMap::new(),
+            sets: HashMap::new(),
+            malformed: 0,
+        }
+    }
+
+    fn metric_id(&self, line: &StatsdLine) -> MetricIdBuilder {
+        let mut builder = key(&line.name);
+
+        for (k, v) in &self.tags {
+            builder = builder.tag(k, v);
+        }
+
+        for &(ref k, ref v) in &line.tags {
+            builder = builder.tag(k, v);
+        }
+
+        builder
+    }
+
+    /// Ingest a buffer of newline-separated metrics.
+    pub fn ingest(&mut self, buffer: &[u8]) {
+        for line in lines(buffer) {
+            let parsed = parse_statsd_line(&line).to_full_result().map_err(Into::into);
+
+            if let Err(e) = parsed.and_then(|line| self.record(&line)) {
+                debug!("{}: malformed line: {:?}: {}", self.id, String::from_utf8_lossy(&line), e);
+                self.malformed += 1;
+            }
+        }
+    }
+
+    fn record(&mut self, line: &StatsdLine) -> Result<()> {
+        let id = self.metric_id(line);
+        let sample_rate = line.sample_rate.unwrap_or(1f64);
+
+        if sample_rate <= 0f64 || sample_rate > 1f64 {
+            return Err(ErrorKind::Message(format!("bad sample rate: {}", sample_rate)).into());
+        }
+
+        if line.kind == StatsdKind::Set {
+            self.sets.entry(id).or_insert_with(HashSet::new).insert(
+                line.value.clone(),
+            );
+            return Ok(());
+        }
+
+        let value = line.value.parse::<f64>().map_err(|e| {
+            ErrorKind::Message(e.to_string())
+        })?;
+
+        match line.kind {
+            StatsdKind::Counter => {
+                *self.counters.entry(id).or_insert(0f64) += value / sample_rate;
+            }
+            StatsdKind::Gauge => {
+                // a leading sign modifies the current value, instead of replacing it.
+                if line.value.starts_with('+') || line.value.starts_with('-') {
+                    *self.gauges.entry(id).or_insert(0f64) += value;
+                } else {
+                    self.gauges.insert(id, value);
+                }
+            }
+            StatsdKind::Timer |
+            StatsdKind::Histogram |
+            StatsdKind::Distribution => {
+                self.timers.entry(id).or_insert_with(Distribution::new).record(
+                    value,
+                    1f64 / sample_rate,
+                );
+            }
+            StatsdKind::Set => {}
+        }
+
+        Ok(())
+    }
+
+    /// Build samples for everything aggregated since the last flush, and reset.
+    ///
+    /// Gauges are retained between flushes.
+    pub fn flush(&mut self) -> Samples {
+        let mut samples = Vec::new();
+
+        for (id, value) in self.counters.drain() {
+            samples.push(Sample::new(Arc::new(id.build()), value));
+        }
+
+        for (id, value) in &self.gauges {
+            samples.push(Sample::new(Arc::new(id.build()), *value));
+        }
+
+        for (id, values) in self.sets.drain() {
+            samples.push(Sample::new(Arc::new(id.build()), values.len() as f64));
+        }
+
+        for (id, timer) in self.timers.drain() {
+            for (stat, value) in timer.stats(&self.percentiles) {
+                let id = id.clone().tag("stat", &stat).build();
+                samples.push(Sample::new(Arc::new(id), value));
+            }
+        }
+
+        let malformed = key("statsd")
+            .tag("instance", &self.id)
+            .tag("what", "malformed-lines")
+            .build();
+
+        samples.push(Sample::new(Arc::new(malformed), self.malformed as f64));
+        self.malformed = 0;
+
+        samples
+    }
+}
+
+struct StatsdInputInstance {
+    aggregator: Arc<Mutex<Aggregator>>,
+}
+
+impl fmt::Debug for StatsdInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StatsdInputInstance")
+    }
+}
+
+impl InputInstance for StatsdInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        Ok(self.aggregator.lock()?.flush())
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(StatsdInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::find_sample;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+
+    fn aggregator() -> Aggregator {
+        Aggregator::new("statsd", BTreeMap::new(), vec![50f64, 99f64])
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut a = aggregator();
+
+        a.ingest(b"hits:1|c\nhits:2|c|@0.5\nhits:1|c|#env:prod\n");
+        a.ingest(b"temp:20|g\ntemp:+2|g\nusers:alice|s\nusers:bob|s\nusers:alice|s\n");
+        a.ingest(b"latency:10|ms\nlatency:20|ms\nlatency:30|ms\nbogus\n");
+
+        let samples = a.flush();
+
+        assert_eq!(Some(5f64), find_sample(&samples, &key("hits").build()));
+        assert_eq!(Some(1f64), find_sample(&samples, &key("hits").tag("env", "prod").build()));
+        assert_eq!(Some(22f64), find_sample(&samples, &key("temp").build()));
+        assert_eq!(Some(2f64), find_sample(&samples, &key("users").build()));
+
+        let latency = |stat: &str| key("latency").tag("stat", stat).build();
+        assert_eq!(Some(3f64), find_sample(&samples, &latency("count")));
+        assert_eq!(Some(10f64), find_sample(&samples, &latency("min")));
+        assert_eq!(Some(30f64), find_sample(&samples, &latency("max")));
+        assert_eq!(Some(20f64), find_sample(&samples, &latency("mean")));
+
+        let malformed = key("statsd")
+            .tag("instance", "statsd")
+            .tag("what", "malformed-lines")
+            .build();
+        assert_eq!(Some(1f64), find_sample(&samples, &malformed));
+
+        // percentiles are approximated by the histogram.
+        for i in 1..101 {
+            a.ingest(format!("latency:{}|ms\n", i).as_bytes());
+        }
+
+        let samples = a.flush();
+        let p50 = find_sample(&samples, &latency("p50")).unwrap();
+        let p99 = find_sample(&samples, &latency("p99")).unwrap();
+        assert!((p50 - 50f64).abs() < 2f64, "p50 = {}", p50);
+        assert!((p99 - 99f64).abs() < 2f64, "p99 = {}", p99);
+
+        // counters and timers are reset, gauges are retained.
+        let samples = a.flush();
+        assert_eq!(None, find_sample(&samples, &key("hits").build()));
+        assert_eq!(Some(22f64), find_sample(&samples, &key("temp").build()));
+        assert_eq!(Some(0f64), find_sample(&samples, &malformed));
+    }
+
+    #[test]
+    fn test_udp() {
+        let mut core = Core::new().unwrap();
+        let a = Arc::new(Mutex::new(aggregator()));
+
+        let addr = bind_udp(
+            &core.handle(),
+            &"127.0.0.1:0".parse().unwrap(),
+            ingest(a.clone()),
+        ).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"hits:3|c\nhits:4|c", &addr).unwrap();
+
+        for _ in 0..100 {
+            core.turn(Some(Duration::from_millis(10)));
+
+            if !a.lock().unwrap().counters.is_empty() {
+                break;
+            }
+        }
+
+        let samples = a.lock().unwrap().flush();
+        assert_eq!(Some(7f64), find_sample(&samples, &key("hits").build()));
+    }
+}
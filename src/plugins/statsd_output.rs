@@ -0,0 +1,477 @@
+//! Output plugin that emits samples as StatsD lines, over UDP or a Unix datagram socket.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Deserialize, Debug)]
+struct StatsdOutputConfig {
+    /// UDP address to send to, defaults to 127.0.0.1:8125.
+    address: Option<String>,
+    /// Path to a Unix datagram socket to send to, instead of UDP.
+    unix: Option<String>,
+    /// Prefix of every metric name.
+    prefix: Option<String>,
+    /// Add tags in the DogStatsD format, instead of folding them into the name.
+    dogstatsd: Option<bool>,
+    /// Maximum size of a datagram in bytes, defaults to 1432.
+    mtu: Option<usize>,
+    /// Tags added to every line, only used with dogstatsd.
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug)]
+struct StatsdOutput {}
+
+impl Output for StatsdOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: StatsdOutputConfig = ctx.decode_config()?;
+
+        let socket = match (config.address, config.unix) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "unix".to_owned(),
+                        "can't be combined with address".to_owned(),
+                    ).into(),
+                )
+            }
+            (None, Some(unix)) => Socket::unix(PathBuf::from(unix))?,
+            (address, None) => {
+                let address = address.unwrap_or("127.0.0.1:8125".to_owned());
+
+                let addr = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    ErrorKind::ConfigField("address".to_owned(), "no address found".to_owned())
+                })?;
+
+                Socket::udp(addr)?
+            }
+        };
+
+        let mtu = config.mtu.unwrap_or(1432);
+
+        if mtu == 0 {
+            return Err(
+                ErrorKind::ConfigField("mtu".to_owned(), "must be at least 1".to_owned()).into(),
+            );
+        }
+
+        let dogstatsd = config.dogstatsd.unwrap_or(false);
+
+        if !dogstatsd && config.tags.is_some() {
+            return Err(
+                ErrorKind::ConfigField("tags".to_owned(), "requires dogstatsd".to_owned()).into(),
+            );
+        }
+
+        let converter = Converter {
+            prefix: config.prefix.map(|p| sanitize(&p)),
+            dogstatsd: dogstatsd,
+            tags: config
+                .tags
+                .unwrap_or_else(BTreeMap::new)
+                .iter()
+                .map(|(k, v)| (sanitize(k), sanitize(v)))
+                .collect(),
+        };
+
+        Ok(Box::new(StatsdOutputInstance {
+            converter: converter,
+            mtu: mtu,
+            socket: socket,
+            state: Mutex::new(State {
+                lines: Vec::new(),
+                rates: Vec::new(),
+                last_flush: None,
+            }),
+        }))
+    }
+}
+
+/// Replace characters which are part of the StatsD syntax.
+fn sanitize(input: &str) -> String {
+    let out: String = input
+        .chars()
+        .map(|c| match c {
+            ':' | '|' | '@' | '#' | ',' => '_',
+            c if c.is_whitespace() || c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if out.is_empty() { "_".to_owned() } else { out }
+}
+
+/// Converts samples into StatsD lines.
+struct Converter {
+    prefix: Option<String>,
+    dogstatsd: bool,
+    /// configured tags, which take precedence over the tags of samples.
+    tags: Vec<(String, String)>,
+}
+
+impl Converter {
+    /// Build the name of a metric, and its DogStatsD tags.
+    ///
+    /// Without DogStatsD, the values of all tags but `unit` are folded into the name, so that
+    /// differently tagged samples don't end up in the same metric.
+    fn name(&self, id: &MetricId) -> (String, Vec<(String, String)>) {
+        let mut parts = Vec::new();
+
+        if let Some(ref prefix) = self.prefix {
+            parts.push(prefix.clone());
+        }
+
+        parts.push(sanitize(id.key().unwrap_or("sysmon")));
+
+        if let Some(what) = id.tag("what") {
+            parts.push(sanitize(what));
+        }
+
+        let mut tags = self.tags.clone();
+
+        for &(ref k, ref v) in id.tags().iter().chain(id.resource()) {
+            if k == "what" || v.is_empty() {
+                continue;
+            }
+
+            if self.dogstatsd {
+                let k = sanitize(k);
+
+                if !tags.iter().any(|t| t.0 == k) {
+                    let v = sanitize(&strip_rate(&k, v));
+                    tags.push((k, v));
+                }
+            } else if k != "unit" {
+                parts.push(sanitize(v).replace('.', "_"));
+            }
+        }
+
+        (parts.join("."), tags)
+    }
+
+    /// Build the line, or lines, for a single value of the given type.
+    fn lines(&self, id: &MetricId, value: f64, kind: &str) -> Option<String> {
+        if !value.is_finite() {
+            return None;
+        }
+
+        let (name, tags) = self.name(id);
+
+        let mut suffix = String::new();
+
+        if !tags.is_empty() {
+            let tags: Vec<String> = tags.iter()
+                .map(|&(ref k, ref v)| format!("{}:{}", k, v))
+                .collect();
+
+            suffix = format!("|#{}", tags.join(","));
+        }
+
+        // a negative value would modify the current value of a gauge instead of replacing it.
+        if kind == "g" && value < 0f64 {
+            return Some(format!("{0}:0|g{1}\n{0}:{2}|g{1}", name, suffix, value));
+        }
+
+        Some(format!("{}:{}|{}{}", name, value, kind, suffix))
+    }
+}
+
+/// Counters are sent as deltas, so the unit of their rate loses its `/s`.
+fn strip_rate(key: &str, value: &str) -> String {
+    if key == "unit" && value.ends_with("/s") {
+        value[..value.len() - 2].to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Pack lines into as few datagrams as possible, without exceeding the mtu.
+///
+/// Lines which are larger than the mtu are sent in a datagram of their own.
+fn pack(lines: &[String], mtu: usize) -> Vec<Vec<u8>> {
+    let mut datagrams = Vec::new();
+    let mut current: Vec<u8> = Vec::new();
+
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > mtu {
+            datagrams.push(mem::replace(&mut current, Vec::new()));
+        }
+
+        if !current.is_empty() {
+            current.push(b'\n');
+        }
+
+        current.extend_from_slice(line.as_bytes());
+    }
+
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+
+    datagrams
+}
+
+enum Socket {
+    Udp(UdpSocket, SocketAddr),
+    Unix(UnixDatagram, PathBuf),
+}
+
+impl Socket {
+    fn udp(addr: SocketAddr) -> Result<Socket> {
+        let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket::Udp(socket, addr))
+    }
+
+    fn unix(path: PathBuf) -> Result<Socket> {
+        let socket = UnixDatagram::unbound()?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket::Unix(socket, path))
+    }
+
+    fn send(&self, datagram: &[u8]) -> io::Result<usize> {
+        match *self {
+            Socket::Udp(ref socket, ref addr) => socket.send_to(datagram, addr),
+            Socket::Unix(ref socket, ref path) => socket.send_to(datagram, path),
+        }
+    }
+
+    fn destination(&self) -> String {
+        match *self {
+            Socket::Udp(_, ref addr) => addr.to_string(),
+            Socket::Unix(_, ref path) => path.display().to_string(),
+        }
+    }
+}
+
+struct State {
+    /// gauge lines of the current poll cycle.
+    lines: Vec<String>,
+    /// counter rates of the current poll cycle, sent as deltas since the last flush.
+    rates: Vec<(Arc<MetricId>, f64)>,
+    last_flush: Option<Instant>,
+}
+
+struct StatsdOutputInstance {
+    converter: Converter,
+    mtu: usize,
+    socket: Socket,
+    state: Mutex<State>,
+}
+
+impl StatsdOutputInstance {
+    /// Collect the lines of the current poll cycle.
+    ///
+    /// Deltas can only be calculated once the time since the previous flush is known, so no
+    /// counters are sent on the first one.
+    fn take_lines(&self, now: Instant) -> Result<Vec<String>> {
+        let mut state = self.state.lock()?;
+        let mut lines = mem::replace(&mut state.lines, Vec::new());
+        let rates = mem::replace(&mut state.rates, Vec::new());
+
+        if let Some(last_flush) = state.last_flush {
+            let elapsed = now.duration_since(last_flush);
+            let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+            lines.extend(rates.iter().filter_map(|&(ref id, rate)| {
+                self.converter.lines(id, rate * seconds, "c")
+            }));
+        }
+
+        state.last_flush = Some(now);
+        Ok(lines)
+    }
+}
+
+impl OutputInstance for StatsdOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        let mut state = self.state.lock()?;
+
+        if is_rate(&sample.metric_id) {
+            state.rates.push((sample.metric_id.clone(), sample.value));
+        } else if let Some(line) = self.converter.lines(&sample.metric_id, sample.value, "g") {
+            state.lines.push(line);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let lines = self.take_lines(Instant::now())?;
+
+        for datagram in pack(&lines, self.mtu) {
+            // sending never blocks polling, datagrams are dropped instead.
+            if let Err(e) = self.socket.send(&datagram) {
+                warn!("{}: failed to send: {}", self.socket.destination(), e);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(StatsdOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+
+    fn converter(dogstatsd: bool) -> Converter {
+        Converter {
+            prefix: Some("host".to_owned()),
+            dogstatsd: dogstatsd,
+            tags: if dogstatsd {
+                vec![("dc".to_owned(), "east".to_owned())]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    fn instance(socket: Socket, mtu: usize) -> StatsdOutputInstance {
+        StatsdOutputInstance {
+            converter: Converter {
+                prefix: None,
+                dogstatsd: false,
+                tags: Vec::new(),
+            },
+            mtu: mtu,
+            socket: socket,
+            state: Mutex::new(State {
+                lines: Vec::new(),
+                rates: Vec::new(),
+                last_flush: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let id = key("disk")
+            .tag("what", "read")
+            .tag("unit", "bytes/s")
+            .tag("device", "sda1.0")
+            .build();
+
+        assert_eq!(
+            Some("host.disk.read.sda1_0:10|c".to_owned()),
+            converter(false).lines(&id, 10f64, "c")
+        );
+
+        assert_eq!(
+            Some("host.disk.read:10|c|#dc:east,unit:bytes,device:sda1.0".to_owned()),
+            converter(true).lines(&id, 10f64, "c")
+        );
+
+        let temp = key("sensor").tag("what", "temp").build();
+
+        assert_eq!(
+            Some("host.sensor.temp:0|g\nhost.sensor.temp:-1.5|g".to_owned()),
+            converter(false).lines(&temp, -1.5f64, "g")
+        );
+
+        assert_eq!(None, converter(false).lines(&temp, ::std::f64::NAN, "g"));
+        assert_eq!("a_b_c_d_e_f", sanitize("a:b|c@d#e f"));
+    }
+
+    #[test]
+    fn test_pack() {
+        let lines: Vec<String> = vec!["a:1|g", "b:2|g", "c:3|g", "long:1234567890|g"]
+            .into_iter()
+            .map(|l| l.to_owned())
+            .collect();
+
+        assert_eq!(
+            vec![
+                b"a:1|g\nb:2|g".to_vec(),
+                b"c:3|g".to_vec(),
+                b"long:1234567890|g".to_vec(),
+            ],
+            pack(&lines, 12)
+        );
+
+        assert_eq!(1, pack(&lines, 1432).len());
+    }
+
+    #[test]
+    fn test_counter_deltas() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let instance = instance(Socket::udp(socket.local_addr().unwrap()).unwrap(), 1432);
+
+        let rate = key("net").tag("what", "rx").tag("unit", "bytes/s").build();
+        let gauge = key("load").tag("what", "1m").build();
+
+        let now = Instant::now();
+
+        instance.feed(&Sample::new(Arc::new(rate.clone()), 100f64)).unwrap();
+        assert_eq!(Vec::<String>::new(), instance.take_lines(now).unwrap());
+
+        instance.feed(&Sample::new(Arc::new(rate), 100f64)).unwrap();
+        instance.feed(&Sample::new(Arc::new(gauge), 0.5f64)).unwrap();
+
+        assert_eq!(
+            vec!["load.1m:0.5|g".to_owned(), "net.rx:200|c".to_owned()],
+            instance.take_lines(now + Duration::from_secs(2)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let instance = instance(Socket::udp(server.local_addr().unwrap()).unwrap(), 16);
+
+        for i in 0..3 {
+            let id = key("load").tag("what", &i.to_string()).build();
+            instance.feed(&Sample::new(Arc::new(id), 1f64)).unwrap();
+        }
+
+        instance.flush().unwrap();
+
+        let mut buffer = [0u8; 64];
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(b"load.0:1|g", &buffer[..n]);
+
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(b"load.1:1|g", &buffer[..n]);
+    }
+
+    #[test]
+    fn test_unix() {
+        let path = env::temp_dir().join(format!("sysmon-statsd-output-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let instance = instance(Socket::unix(path.clone()).unwrap(), 1432);
+        let id = key("load").tag("what", "1m").build();
+        instance.feed(&Sample::new(Arc::new(id.clone()), 1f64)).unwrap();
+        instance.feed(&Sample::new(Arc::new(id), 2f64)).unwrap();
+        instance.flush().unwrap();
+
+        let mut buffer = [0u8; 64];
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(b"load.1m:1|g\nload.1m:2|g", &buffer[..n]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,706 @@
+//! Output plugin that sends samples as RFC 5424 syslog messages over UDP, TCP or a Unix socket,
+//! or as entries through the native protocol of the systemd journal.
+
+use errors::*;
+use plugin::*;
+use metric::*;
+use super::delivery::{Backoff, Worker};
+use super::utils::read_text;
+
+use std::io::Write;
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use time::{self, Timespec};
+
+/// Attempts to deliver a message before the rest of the batch is dropped.
+const ATTEMPTS: usize = 3;
+
+/// Example enterprise number reserved for documentation by RFC 5612.
+const DEFAULT_ENTERPRISE_ID: u32 = 32473;
+
+const FACILITIES: &[&str] = &[
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SEVERITIES: &[&str] = &[
+    "emerg",
+    "alert",
+    "crit",
+    "err",
+    "warning",
+    "notice",
+    "info",
+    "debug",
+];
+
+#[derive(Deserialize, Debug)]
+struct SyslogOutputConfig {
+    /// How messages are sent, one of udp, tcp, unix or journald, defaults to udp.
+    transport: Option<String>,
+    /// Address of the server for udp and tcp, defaults to 127.0.0.1:514.
+    address: Option<String>,
+    /// Path of the socket for unix and journald, defaults to /dev/log and
+    /// /run/systemd/journal/socket.
+    path: Option<String>,
+    /// Facility of all messages, defaults to local0.
+    facility: Option<String>,
+    /// Severity of all messages, defaults to info.
+    severity: Option<String>,
+    /// Name of the application in messages, defaults to sysmon.
+    app_name: Option<String>,
+    /// Hostname of all messages, defaults to the host in the resource of samples, or the hostname.
+    hostname: Option<String>,
+    /// Enterprise number of the structured data, defaults to 32473.
+    enterprise_id: Option<u32>,
+    /// Timeout in seconds for connecting and sending, defaults to 5.
+    timeout: Option<u64>,
+}
+
+#[derive(Debug)]
+struct SyslogOutput {}
+
+/// Find the code of a facility or severity by name.
+fn lookup(field: &str, names: &[&str], name: &str) -> Result<u8> {
+    match names.iter().position(|n| *n == name) {
+        Some(code) => Ok(code as u8),
+        None => Err(
+            ErrorKind::ConfigField(
+                field.to_owned(),
+                format!("expected one of {}, got: {}", names.join(", "), name),
+            ).into(),
+        ),
+    }
+}
+
+impl Output for SyslogOutput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<OutputInstance>> {
+        let config: SyslogOutputConfig = ctx.decode_config()?;
+
+        let transport = config.transport.unwrap_or("udp".to_owned());
+
+        let transport = match (transport.as_str(), config.address, config.path) {
+            ("udp", address, None) => {
+                Transport::Udp(address.unwrap_or("127.0.0.1:514".to_owned()))
+            }
+            ("tcp", address, None) => {
+                Transport::Tcp(address.unwrap_or("127.0.0.1:514".to_owned()))
+            }
+            ("unix", None, path) => {
+                Transport::Unix(PathBuf::from(path.unwrap_or("/dev/log".to_owned())))
+            }
+            ("journald", None, path) => {
+                Transport::Journald(PathBuf::from(
+                    path.unwrap_or("/run/systemd/journal/socket".to_owned()),
+                ))
+            }
+            ("udp", _, Some(_)) |
+            ("tcp", _, Some(_)) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "path".to_owned(),
+                        format!("can't be used with {}", transport),
+                    ).into(),
+                )
+            }
+            ("unix", Some(_), _) |
+            ("journald", Some(_), _) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "address".to_owned(),
+                        format!("can't be used with {}", transport),
+                    ).into(),
+                )
+            }
+            (transport, _, _) => {
+                return Err(
+                    ErrorKind::ConfigField(
+                        "transport".to_owned(),
+                        format!("expected udp, tcp, unix or journald, got: {}", transport),
+                    ).into(),
+                )
+            }
+        };
+
+        let converter = Converter {
+            facility: lookup(
+                "facility",
+                FACILITIES,
+                config.facility.as_ref().map(String::as_str).unwrap_or("local0"),
+            )?,
+            severity: lookup(
+                "severity",
+                SEVERITIES,
+                config.severity.as_ref().map(String::as_str).unwrap_or("info"),
+            )?,
+            app_name: header_field(&config.app_name.unwrap_or("sysmon".to_owned()), 48),
+            hostname: config.hostname.map(|h| header_field(&h, 255)),
+            fallback_hostname: header_field(
+                &read_text("/proc/sys/kernel/hostname").unwrap_or("-".to_owned()),
+                255,
+            ),
+            enterprise_id: config.enterprise_id.unwrap_or(DEFAULT_ENTERPRISE_ID),
+        };
+
+        let journald = match transport {
+            Transport::Journald(_) => true,
+            _ => false,
+        };
+
+        let mut client = Client {
+            transport: transport,
+            timeout: Duration::from_secs(config.timeout.unwrap_or(5)),
+            connection: None,
+            backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
+        };
+
+        let worker = Worker::spawn(&format!("syslog-{}", ctx.id), 16, move |messages| {
+            client.deliver(messages)
+        })?;
+
+        Ok(Box::new(SyslogOutputInstance {
+            converter: converter,
+            journald: journald,
+            batch: Mutex::new(Vec::new()),
+            worker: worker,
+        }))
+    }
+}
+
+/// Limit a header field to printable ASCII without spaces, of at most the given length.
+///
+/// Empty fields are replaced by the nil value.
+fn header_field(input: &str, max: usize) -> String {
+    let out: String = input
+        .chars()
+        .map(|c| if c > ' ' && c <= '~' { c } else { '_' })
+        .take(max)
+        .collect();
+
+    if out.is_empty() { "-".to_owned() } else { out }
+}
+
+/// Build the name of a structured data parameter, which excludes a few more characters.
+fn param_name(input: &str) -> String {
+    header_field(input, 32)
+        .chars()
+        .map(|c| match c {
+            '=' | ']' | '"' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Escape the value of a structured data parameter.
+fn param_value(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        if c == '"' || c == '\\' || c == ']' {
+            out.push('\\');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+/// Build the name of a journal field, which is limited to upper case letters, digits and
+/// underscores.
+fn journal_field(prefix: &str, input: &str) -> String {
+    let name: String = input
+        .chars()
+        .map(|c| if c.is_ascii() && c.is_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+
+    let mut field = format!("{}{}", prefix, name);
+    field.truncate(64);
+    field
+}
+
+/// Append a field in the native journal protocol.
+///
+/// Values containing newlines are written with an explicit length instead.
+fn push_journal_field(out: &mut Vec<u8>, name: &str, value: &str) {
+    out.extend_from_slice(name.as_bytes());
+
+    if value.contains('\n') {
+        let length = value.len() as u64;
+        out.push(b'\n');
+        out.extend((0..8).map(|i| (length >> (8 * i)) as u8));
+    } else {
+        out.push(b'=');
+    }
+
+    out.extend_from_slice(value.as_bytes());
+    out.push(b'\n');
+}
+
+/// Converts samples into syslog messages, or journal entries.
+struct Converter {
+    facility: u8,
+    severity: u8,
+    app_name: String,
+    hostname: Option<String>,
+    fallback_hostname: String,
+    enterprise_id: u32,
+}
+
+impl Converter {
+    /// Describe a sample, as the key followed by the values of the tags, the value and its unit.
+    fn text(&self, id: &MetricId, value: f64) -> String {
+        let mut parts = vec![id.key().unwrap_or("sysmon").to_owned()];
+
+        if let Some(what) = id.tag("what") {
+            parts.push(what.to_owned());
+        }
+
+        for &(ref k, ref v) in id.tags() {
+            if k != "what" && k != "unit" && !v.is_empty() {
+                parts.push(v.clone());
+            }
+        }
+
+        parts.push(value.to_string());
+
+        if let Some(unit) = id.tag("unit") {
+            if !unit.is_empty() {
+                parts.push(unit.to_owned());
+            }
+        }
+
+        parts.join(" ").replace(|c: char| c.is_control(), " ")
+    }
+
+    /// Build a structured data element, which is left out if it has no parameters.
+    fn element(&self, out: &mut String, name: &str, params: &[(String, String)]) {
+        if params.is_empty() {
+            return;
+        }
+
+        out.push_str(&format!("[{}@{}", name, self.enterprise_id));
+
+        for &(ref k, ref v) in params {
+            out.push_str(&format!(" {}=\"{}\"", param_name(k), param_value(v)));
+        }
+
+        out.push(']');
+    }
+
+    /// Build an RFC 5424 message, with the value, tags and resource as structured data.
+    fn message(&self, id: &MetricId, value: f64, timestamp: u64) -> Result<Option<Vec<u8>>> {
+        if !value.is_finite() {
+            return Ok(None);
+        }
+
+        let time = time::at_utc(Timespec::new(
+            (timestamp / 1000) as i64,
+            (timestamp % 1000 * 1_000_000) as i32,
+        ));
+
+        let time = time.strftime("%Y-%m-%dT%H:%M:%S").map_err(|e| {
+            ErrorKind::Message(e.to_string())
+        })?;
+
+        let hostname = match self.hostname {
+            Some(ref hostname) => hostname.clone(),
+            None => {
+                id.resource()
+                    .iter()
+                    .find(|r| r.0 == "host" || r.0 == "hostname")
+                    .map(|r| header_field(&r.1, 255))
+                    .unwrap_or_else(|| self.fallback_hostname.clone())
+            }
+        };
+
+        let mut data = String::new();
+        self.element(&mut data, "sample", &[("value".to_owned(), value.to_string())]);
+        self.element(&mut data, "tags", id.tags());
+        self.element(&mut data, "resource", id.resource());
+
+        let message = format!(
+            "<{}>1 {}.{:03}Z {} {} - {} {} {}",
+            self.facility as u32 * 8 + self.severity as u32,
+            time,
+            timestamp % 1000,
+            hostname,
+            self.app_name,
+            header_field(id.key().unwrap_or(""), 32),
+            data,
+            self.text(id, value)
+        );
+
+        Ok(Some(message.into_bytes()))
+    }
+
+    /// Build a journal entry, with a field for every tag.
+    fn entry(&self, id: &MetricId, value: f64, timestamp: u64) -> Option<Vec<u8>> {
+        if !value.is_finite() {
+            return None;
+        }
+
+        let mut out = Vec::new();
+        push_journal_field(&mut out, "MESSAGE", &self.text(id, value));
+        push_journal_field(&mut out, "PRIORITY", &self.severity.to_string());
+        push_journal_field(&mut out, "SYSLOG_FACILITY", &self.facility.to_string());
+        push_journal_field(&mut out, "SYSLOG_IDENTIFIER", &self.app_name);
+        push_journal_field(&mut out, "SYSMON_KEY", id.key().unwrap_or(""));
+        push_journal_field(&mut out, "SYSMON_VALUE", &value.to_string());
+        push_journal_field(&mut out, "SYSMON_TIMESTAMP", &timestamp.to_string());
+
+        for &(ref k, ref v) in id.tags() {
+            push_journal_field(&mut out, &journal_field("SYSMON_TAG_", k), v);
+        }
+
+        for &(ref k, ref v) in id.resource() {
+            push_journal_field(&mut out, &journal_field("SYSMON_RESOURCE_", k), v);
+        }
+
+        Some(out)
+    }
+}
+
+/// Where messages are sent.
+#[derive(Debug)]
+enum Transport {
+    Udp(String),
+    Tcp(String),
+    Unix(PathBuf),
+    Journald(PathBuf),
+}
+
+impl Transport {
+    fn connect(&self, timeout: Duration) -> Result<Connection> {
+        match *self {
+            Transport::Udp(ref address) |
+            Transport::Tcp(ref address) => {
+                let addr = match address.to_socket_addrs()?.next() {
+                    Some(addr) => addr,
+                    None => {
+                        return Err(
+                            ErrorKind::Message(format!("no address for: {}", address)).into(),
+                        )
+                    }
+                };
+
+                if let Transport::Tcp(_) = *self {
+                    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(Connection::Tcp(stream));
+                }
+
+                let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            }
+            Transport::Unix(ref path) |
+            Transport::Journald(ref path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                socket.set_write_timeout(Some(timeout))?;
+                Ok(Connection::Unix(socket))
+            }
+        }
+    }
+
+    fn destination(&self) -> String {
+        match *self {
+            Transport::Udp(ref address) |
+            Transport::Tcp(ref address) => address.clone(),
+            Transport::Unix(ref path) |
+            Transport::Journald(ref path) => path.display().to_string(),
+        }
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Unix(UnixDatagram),
+}
+
+impl Connection {
+    /// Send a single message, which is framed by octet counting over TCP, as in RFC 6587.
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        match *self {
+            Connection::Udp(ref socket) => {
+                socket.send(message)?;
+            }
+            Connection::Tcp(ref mut stream) => {
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(message);
+                stream.write_all(&frame)?;
+            }
+            Connection::Unix(ref socket) => {
+                socket.send(message)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Client {
+    transport: Transport,
+    timeout: Duration,
+    connection: Option<Connection>,
+    backoff: Backoff,
+}
+
+impl Client {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        if self.connection.is_none() {
+            self.connection = Some(self.transport.connect(self.timeout)?);
+        }
+
+        match self.connection {
+            Some(ref mut connection) => connection.send(message),
+            None => Err(ErrorKind::Message("not connected".to_owned()).into()),
+        }
+    }
+
+    /// Send messages in order, retrying from the first one which failed to send.
+    fn deliver(&mut self, messages: Vec<Vec<u8>>) {
+        let mut pending = &messages[..];
+        let mut failures = 0;
+
+        while let Some(message) = pending.first() {
+            match self.send(message) {
+                Ok(()) => {
+                    self.backoff.reset();
+                    pending = &pending[1..];
+                }
+                Err(e) => {
+                    warn!("{}: failed to send: {}", self.transport.destination(), e);
+                    self.connection = None;
+                    failures += 1;
+
+                    if failures >= ATTEMPTS {
+                        warn!(
+                            "{}: dropping {} messages",
+                            self.transport.destination(),
+                            pending.len()
+                        );
+
+                        return;
+                    }
+
+                    thread::sleep(self.backoff.fail());
+                }
+            }
+        }
+    }
+}
+
+struct SyslogOutputInstance {
+    converter: Converter,
+    /// whether entries for the journal are built, instead of syslog messages.
+    journald: bool,
+    /// messages of the current poll cycle.
+    batch: Mutex<Vec<Vec<u8>>>,
+    worker: Worker<Vec<Vec<u8>>>,
+}
+
+impl OutputInstance for SyslogOutputInstance {
+    fn feed(&self, sample: &Sample) -> Result<()> {
+        let id = &sample.metric_id;
+
+        let message = if self.journald {
+            self.converter.entry(id, sample.value, sample.timestamp)
+        } else {
+            self.converter.message(id, sample.value, sample.timestamp)?
+        };
+
+        if let Some(message) = message {
+            self.batch.lock()?.push(message);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        let messages = mem::replace(&mut *self.batch.lock()?, Vec::new());
+
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        self.worker.send(messages)
+    }
+}
+
+pub fn output() -> Result<Box<Output>> {
+    Ok(Box::new(SyslogOutput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::process;
+
+    fn converter() -> Converter {
+        Converter {
+            facility: 16,
+            severity: 6,
+            app_name: "sysmon".to_owned(),
+            hostname: None,
+            fallback_hostname: "example".to_owned(),
+            enterprise_id: DEFAULT_ENTERPRISE_ID,
+        }
+    }
+
+    fn client(transport: Transport) -> Client {
+        Client {
+            transport: transport,
+            timeout: Duration::from_secs(5),
+            connection: None,
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(10)),
+        }
+    }
+
+    fn disk() -> MetricId {
+        key("disk")
+            .tag("what", "used")
+            .tag("unit", "%")
+            .tag("mount", "/srv \"data\"")
+            .resource("hostname", "db1")
+            .build()
+    }
+
+    #[test]
+    fn test_message() {
+        let message = converter().message(&disk(), 95.5f64, 1500000000123).unwrap().unwrap();
+
+        assert_eq!(
+            "<134>1 2017-07-14T02:40:00.123Z db1 sysmon - disk [sample@32473 value=\"95.5\"]\
+             [tags@32473 what=\"used\" unit=\"%\" mount=\"/srv \\\"data\\\"\"]\
+             [resource@32473 hostname=\"db1\"] disk used /srv \"data\" 95.5 %",
+            String::from_utf8(message).unwrap()
+        );
+
+        let message = converter().message(&key("load").build(), 1f64, 0).unwrap().unwrap();
+
+        assert_eq!(
+            "<134>1 1970-01-01T00:00:00.000Z example sysmon - load [sample@32473 value=\"1\"] \
+             load 1",
+            String::from_utf8(message).unwrap()
+        );
+
+        assert_eq!(None, converter().message(&disk(), ::std::f64::NAN, 0).unwrap());
+        assert_eq!("a_b", header_field("a b", 255));
+        assert_eq!("-", header_field("", 255));
+        assert_eq!("a_b_c_", param_name("a=b]c\""));
+    }
+
+    #[test]
+    fn test_entry() {
+        let id = key("exec").tag("what", "output").tag("line", "a\nb").build();
+        let entry = converter().entry(&id, 1f64, 1500000000123).unwrap();
+
+        let mut expected = b"MESSAGE=exec output a b 1\n\
+                             PRIORITY=6\n\
+                             SYSLOG_FACILITY=16\n\
+                             SYSLOG_IDENTIFIER=sysmon\n\
+                             SYSMON_KEY=exec\n\
+                             SYSMON_VALUE=1\n\
+                             SYSMON_TIMESTAMP=1500000000123\n\
+                             SYSMON_TAG_WHAT=output\n\
+                             SYSMON_TAG_LINE\n"
+            .to_vec();
+
+        expected.extend_from_slice(&[3, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(b"a\nb\n");
+
+        assert_eq!(expected, entry);
+        assert_eq!("SYSMON_TAG_IO_WAIT", journal_field("SYSMON_TAG_", "io-wait"));
+    }
+
+    #[test]
+    fn test_deliver_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+
+        client(Transport::Udp(address)).deliver(vec![b"first".to_vec(), b"second".to_vec()]);
+
+        let mut buffer = [0u8; 64];
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(b"first", &buffer[..n]);
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(b"second", &buffer[..n]);
+    }
+
+    #[test]
+    fn test_deliver_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            received
+        });
+
+        {
+            let mut client = client(Transport::Tcp(address));
+            client.deliver(vec![b"first".to_vec(), b"second one".to_vec()]);
+        }
+
+        assert_eq!("5 first10 second one", server.join().unwrap());
+    }
+
+    #[test]
+    fn test_deliver_journald() {
+        let dir = env::temp_dir().join(format!("sysmon-syslog-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("socket");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let entry = converter().entry(&disk(), 95.5f64, 0).unwrap();
+        client(Transport::Journald(path.clone())).deliver(vec![entry.clone()]);
+
+        let mut buffer = [0u8; 1024];
+        let n = server.recv(&mut buffer).unwrap();
+        assert_eq!(entry, buffer[..n].to_vec());
+
+        // nothing is listening anymore, so the message is dropped after a few attempts.
+        drop(server);
+        client(Transport::Unix(path)).deliver(vec![b"lost".to_vec()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! Input plugin that reports general information about the system.
+
+use metric::*;
+use plugin::*;
+use errors::*;
+use parsers::common::single_u64;
+use parsers::os_release::*;
+use parsers::stat::*;
+use parsers::uptime::*;
+use parsers::utmp::*;
+use super::utils::{read_optional_file, read_text};
+
+use futures::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+struct SystemInputConfig {
+    /// Path to the utmp database, defaults to /var/run/utmp.
+    utmp: Option<String>,
+    /// Path to the os-release file, defaults to /etc/os-release or /usr/lib/os-release.
+    os_release: Option<String>,
+}
+
+#[derive(Debug)]
+struct SystemInput {}
+
+impl Input for SystemInput {
+    fn setup(&self, ctx: PluginContext) -> Result<Box<InputInstance>> {
+        let config: SystemInputConfig = ctx.decode_config()?;
+
+        let utmp = PathBuf::from(config.utmp.unwrap_or("/var/run/utmp".to_owned()));
+
+        let os_release = match config.os_release {
+            Some(os_release) => vec![PathBuf::from(os_release)],
+            None => vec![
+                PathBuf::from("/etc/os-release"),
+                PathBuf::from("/usr/lib/os-release"),
+            ],
+        };
+
+        Ok(Box::new(SystemInputInstance::new(System {
+            proc_root: PathBuf::from("/proc"),
+            utmp: utmp,
+            os_release: os_release,
+        })))
+    }
+}
+
+/// Fields from os-release which are added as resources to the info series.
+const OS_RELEASE_RESOURCES: &'static [(&'static str, &'static str)] = &[
+    ("ID", "os"),
+    ("VERSION_ID", "os_version"),
+    ("PRETTY_NAME", "os_name"),
+];
+
+struct System {
+    /// root of the proc filesystem.
+    proc_root: PathBuf,
+    utmp: PathBuf,
+    /// candidate os-release files, the first one which exists is used.
+    os_release: Vec<PathBuf>,
+}
+
+impl System {
+    /// Build the static info series, which carries information about the system as resources.
+    fn info(&self) -> Result<MetricId> {
+        let mut info = key("system").tag("what", "info");
+
+        if let Some(release) = read_text(self.proc_root.join("sys/kernel/osrelease")) {
+            info = info.resource("kernel", &release);
+        }
+
+        if let Some(hostname) = read_text(self.proc_root.join("sys/kernel/hostname")) {
+            info = info.resource("hostname", &hostname);
+        }
+
+        for path in &self.os_release {
+            let content = match read_optional_file(path)? {
+                Some(content) => content,
+                None => continue,
+            };
+
+            let values = parse_os_release(&content).to_full_result()?;
+
+            for &(field, resource) in OS_RELEASE_RESOURCES {
+                if let Some(&(_, ref value)) = values.iter().find(|v| v.0 == field) {
+                    info = info.resource(resource, value);
+                }
+            }
+
+            break;
+        }
+
+        Ok(info.build())
+    }
+
+    pub fn scan(&self) -> Result<Vec<(Arc<MetricId>, f64)>> {
+        let system = key("system");
+
+        let id = |what: &str, unit: &str| {
+            Arc::new(system.clone().tag("what", what).tag("unit", unit).build())
+        };
+
+        let mut readings = Vec::new();
+
+        if let Some(content) = read_optional_file(self.proc_root.join("uptime"))? {
+            let uptime = parse_uptime(&content).to_full_result()?;
+            readings.push((id("uptime", "s"), uptime.uptime));
+            readings.push((id("idle-time", "s"), uptime.idle));
+        }
+
+        if let Some(content) = read_optional_file(self.proc_root.join("stat"))? {
+            if let Some(btime) = parse_stat_btime(&content).to_full_result()? {
+                readings.push((id("boot-time", "s"), btime as f64));
+            }
+        }
+
+        let entropy = self.proc_root.join("sys/kernel/random/entropy_avail");
+
+        if let Some(content) = read_optional_file(entropy)? {
+            let entropy = single_u64(&content).to_full_result()?;
+            readings.push((id("entropy-available", "bits"), entropy as f64));
+        }
+
+        if let Some(content) = read_optional_file(&self.utmp)? {
+            // a record which is being written can be cut short, so only complete ones are read.
+            let complete = content.len() - content.len() % UTMP_SIZE;
+            let records = parse_utmp(&content[..complete]).to_full_result()?;
+
+            let users = records
+                .iter()
+                .filter(|r| r.kind == USER_PROCESS && !r.user.is_empty())
+                .count();
+
+            readings.push((id("users", "sessions"), users as f64));
+        }
+
+        readings.push((Arc::new(self.info()?), 1f64));
+        Ok(readings)
+    }
+}
+
+struct Metrics {
+    system: System,
+    readings: Vec<(Arc<MetricId>, f64)>,
+}
+
+impl Metrics {
+    pub fn update(&mut self) -> Result<()> {
+        self.readings = self.system.scan()?;
+        Ok(())
+    }
+}
+
+struct SystemInputInstance {
+    metrics: Arc<Mutex<Metrics>>,
+    next_update: Duration,
+}
+
+impl fmt::Debug for SystemInputInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SystemInputInstance")
+    }
+}
+
+impl SystemInputInstance {
+    pub fn new(system: System) -> SystemInputInstance {
+        SystemInputInstance {
+            next_update: Duration::from_millis(1000),
+            metrics: Arc::new(Mutex::new(Metrics {
+                system: system,
+                readings: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl InputInstance for SystemInputInstance {
+    fn poll(&self) -> Result<Samples> {
+        let ref m = self.metrics.lock()?;
+
+        Ok(
+            m.readings
+                .iter()
+                .map(|&(ref id, value)| Sample::new(id.clone(), value))
+                .collect(),
+        )
+    }
+
+    fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        let m = self.metrics.clone();
+
+        Box::new(future::lazy(move || {
+            let result: Result<()> = m.lock().map_err(Into::into).and_then(
+                |mut locked| locked.update(),
+            );
+
+            future::result(result)
+        }))
+    }
+
+    fn next_update(&self) -> Duration {
+        self.next_update
+    }
+}
+
+pub fn input() -> Result<Box<Input>> {
+    Ok(Box::new(SystemInput {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugins::test_helpers::{find, find_system, write_fixture};
+
+    use std::env;
+    use std::fs;
+
+    fn utmp_record(kind: i16, user: &str) -> Vec<u8> {
+        let mut out = vec![0u8; UTMP_SIZE];
+        out[0] = kind as u8;
+        out[44..44 + user.len()].copy_from_slice(user.as_bytes());
+        out
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = env::temp_dir().join("sysmon-system-scan");
+        let _ = fs::remove_dir_all(&root);
+
+        write_fixture(&root, "proc/uptime", b"350735.47 234388.90\n");
+        write_fixture(&root, "proc/stat", b"cpu  1 2 3 4 5 6 7 0 0 0\nbtime 1493801542\n");
+        write_fixture(&root, "proc/sys/kernel/random/entropy_avail", b"3018\n");
+        write_fixture(&root, "proc/sys/kernel/osrelease", b"4.9.0-3-amd64\n");
+        write_fixture(&root, "proc/sys/kernel/hostname", b"db1\n");
+        write_fixture(&root, "os-release", b"ID=debian\nVERSION_ID=\"9\"\n");
+
+        // two sessions, and a record which is still being written.
+        let mut utmp = Vec::new();
+        utmp.extend(utmp_record(2, "reboot"));
+        utmp.extend(utmp_record(USER_PROCESS, "alice"));
+        utmp.extend(utmp_record(USER_PROCESS, "bob"));
+        utmp.extend(&utmp_record(USER_PROCESS, "carol")[..100]);
+        write_fixture(&root, "utmp", &utmp);
+
+        let system = System {
+            proc_root: root.join("proc"),
+            utmp: root.join("utmp"),
+            os_release: vec![root.join("missing"), root.join("os-release")],
+        };
+
+        let readings = system.scan().unwrap();
+
+        assert_eq!(Some(350735.47f64), find_system(&readings, "uptime", "s"));
+        assert_eq!(Some(234388.90f64), find_system(&readings, "idle-time", "s"));
+        assert_eq!(Some(1493801542f64), find_system(&readings, "boot-time", "s"));
+        assert_eq!(Some(3018f64), find_system(&readings, "entropy-available", "bits"));
+        assert_eq!(Some(2f64), find_system(&readings, "users", "sessions"));
+
+        let info = key("system")
+            .tag("what", "info")
+            .resource("kernel", "4.9.0-3-amd64")
+            .resource("hostname", "db1")
+            .resource("os", "debian")
+            .resource("os_version", "9")
+            .build();
+
+        assert_eq!(Some(1f64), find(&readings, &info));
+    }
+}
@@ -0,0 +1,139 @@
+//! Reading of the validity of X.509 certificates, like the ones presented by TLS servers.
+
+use errors::*;
+
+/// Read a single DER element, returning its tag, content and the remaining input.
+fn der_element(input: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let bad = || Error::from(ErrorKind::Message("bad der encoding".to_owned()));
+
+    if input.len() < 2 {
+        return Err(bad());
+    }
+
+    let tag = input[0];
+
+    let (length, header) = match input[1] {
+        n if n < 0x80 => (n as usize, 2),
+        n => {
+            let count = (n & 0x7f) as usize;
+
+            if count == 0 || count > 4 || input.len() < 2 + count {
+                return Err(bad());
+            }
+
+            let mut length = 0usize;
+
+            for b in &input[2..2 + count] {
+                length = (length << 8) | *b as usize;
+            }
+
+            (length, 2 + count)
+        }
+    };
+
+    if input.len() < header + length {
+        return Err(bad());
+    }
+
+    Ok((tag, &input[header..header + length], &input[header + length..]))
+}
+
+/// Days since the epoch for the given civil date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parse an ASN.1 UTCTime or GeneralizedTime into seconds since the epoch.
+fn parse_time(tag: u8, content: &[u8]) -> Result<i64> {
+    let text = String::from_utf8_lossy(content);
+    let bad = || Error::from(ErrorKind::Message(format!("bad time: {}", text)));
+
+    if content.iter().any(|b| *b >= 0x80) {
+        return Err(bad());
+    }
+
+    let (year, rest) = match tag {
+        0x17 if text.len() >= 12 => {
+            let year = text[0..2].parse::<i64>().map_err(|_| bad())?;
+            (if year < 50 { 2000 + year } else { 1900 + year }, &text[2..])
+        }
+        0x18 if text.len() >= 14 => (text[0..4].parse::<i64>().map_err(|_| bad())?, &text[4..]),
+        _ => return Err(bad()),
+    };
+
+    let field = |i: usize| rest[i..i + 2].parse::<i64>().map_err(|_| bad());
+
+    let days = days_from_civil(year, field(0)?, field(2)?);
+    Ok(days * 86400 + field(4)? * 3600 + field(6)? * 60 + field(8)?)
+}
+
+/// Get the end of the validity period of a DER-encoded X.509 certificate, in seconds since the
+/// epoch.
+pub fn not_after(certificate: &[u8]) -> Result<i64> {
+    let (_, certificate, _) = der_element(certificate)?;
+    let (_, tbs, _) = der_element(certificate)?;
+
+    let (tag, _, mut rest) = der_element(tbs)?;
+
+    // skip the optional explicit version, and the serial number.
+    if tag == 0xa0 {
+        rest = der_element(rest)?.2;
+    }
+
+    let (_, _signature, rest) = der_element(rest)?;
+    let (_, _issuer, rest) = der_element(rest)?;
+    let (_, validity, _) = der_element(rest)?;
+
+    let (_, _not_before, rest) = der_element(validity)?;
+    let (tag, not_after, _) = der_element(rest)?;
+
+    parse_time(tag, not_after)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+
+        if content.len() < 0x80 {
+            out.push(content.len() as u8);
+        } else {
+            out.push(0x82);
+            out.push((content.len() >> 8) as u8);
+            out.push(content.len() as u8);
+        }
+
+        out.extend(content);
+        out
+    }
+
+    /// Build a skeleton certificate, which only has the fields needed to find its validity.
+    fn certificate(not_after: &str) -> Vec<u8> {
+        let mut validity = der(0x17, b"170101000000Z");
+        validity.extend(der(0x18, not_after.as_bytes()));
+
+        let mut tbs = der(0xa0, &der(0x02, &[2]));
+        tbs.extend(der(0x02, &[1]));
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(der(0x30, &validity));
+
+        der(0x30, &der(0x30, &tbs))
+    }
+
+    #[test]
+    fn test_not_after() {
+        let cert = certificate("20500101000000Z");
+        assert_eq!(2524608000, not_after(&cert).unwrap());
+        assert_eq!(946684800, parse_time(0x17, b"000101000000Z").unwrap());
+        assert!(not_after(&cert[..cert.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use errors::*;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Read the full content of a (typically procfs or sysfs) file.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Read a single line of text from the given file, if it exists.
+pub fn read_text<P: AsRef<Path>>(path: P) -> Option<String> {
+    read_file(path).ok().map(|b| String::from_utf8_lossy(&b).trim().to_owned())
+}
+
+/// Read the full content of a file, or `None` if it does not exist.
+///
+/// Used for files which are only present when the corresponding kernel feature is available.
+pub fn read_optional_file<P: AsRef<Path>>(path: P) -> Result<Option<Vec<u8>>> {
+    match File::open(path) {
+        Ok(mut file) => {
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer)?;
+            Ok(Some(buffer))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// List all entries in the given directory starting with prefix, in sorted order.
+///
+/// A directory that does not exist is considered empty.
+pub fn list_dir(dir: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut paths = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+
+        if entry.file_name().to_string_lossy().starts_with(prefix) {
+            paths.push(entry.path());
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// Split a buffer into lines, each terminated with a line ending as expected by the parsers.
+///
+/// Blank lines are skipped.
+pub fn lines(input: &[u8]) -> Vec<Vec<u8>> {
+    input
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.iter().all(|b| (*b as char).is_whitespace()))
+        .map(|line| {
+            let mut line = line.to_vec();
+            line.push(b'\n');
+            line
+        })
+        .collect()
+}
@@ -39,6 +39,12 @@ impl Runnable for Poller {
             }
         }
 
+        for instance in self.output.iter() {
+            if let Err(err) = instance.flush() {
+                return Box::new(future::err(err));
+            }
+        }
+
         Box::new(future::ok(()))
     }
 }
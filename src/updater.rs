@@ -4,26 +4,40 @@ use plugin::InputInstance;
 use scheduler::Runnable;
 use futures_cpupool::CpuPool;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
 struct InputInstanceState {
     /// Only permit one update at a time.
     in_progress: Arc<AtomicBool>,
+    /// Number of runs left to skip before the next update is due.
+    skip: AtomicUsize,
     instance: Arc<Box<InputInstance>>,
 }
 
 pub struct Updater {
     states: Vec<InputInstanceState>,
     pool: Arc<CpuPool>,
+    /// Interval at which the updater is run.
+    interval: Duration,
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
 }
 
 impl Updater {
-    pub fn new(input: Arc<Vec<Arc<Box<InputInstance>>>>, pool: Arc<CpuPool>) -> Updater {
+    pub fn new(
+        input: Arc<Vec<Arc<Box<InputInstance>>>>,
+        pool: Arc<CpuPool>,
+        interval: Duration,
+    ) -> Updater {
         let states: Vec<_> = input
             .iter()
             .map(|i| {
                 InputInstanceState {
                     in_progress: Arc::new(AtomicBool::new(false)),
+                    skip: AtomicUsize::new(0),
                     instance: i.clone(),
                 }
             })
@@ -32,7 +46,37 @@ impl Updater {
         Updater {
             states: states,
             pool: pool,
+            interval: interval,
+        }
+    }
+
+    /// Number of runs between the updates of an instance, which wants to be updated every
+    /// `next_update`.
+    ///
+    /// Rounded up to whole runs, so that an instance is never updated more often than it asks for.
+    fn runs(&self, next_update: Duration) -> usize {
+        let interval = millis(self.interval);
+
+        if interval == 0 {
+            return 1;
+        }
+
+        let runs = (millis(next_update) + interval - 1) / interval;
+        ::std::cmp::max(runs, 1) as usize
+    }
+
+    /// Check if an update of the instance is due, counting down the runs to skip otherwise.
+    fn is_due(&self, state: &InputInstanceState) -> bool {
+        let skip = state.skip.load(Ordering::Relaxed);
+
+        if skip > 0 {
+            state.skip.store(skip - 1, Ordering::Relaxed);
+            return false;
         }
+
+        let runs = self.runs(state.instance.next_update());
+        state.skip.store(runs - 1, Ordering::Relaxed);
+        true
     }
 }
 
@@ -40,6 +84,7 @@ impl Runnable for Updater {
     fn run(&self) -> Box<Future<Item = (), Error = Error>> {
         let futures: Vec<_> = self.states
             .iter()
+            .filter(|state| self.is_due(state))
             .map(|state| {
                 let in_progress = state.in_progress.clone();
                 let should_update =
@@ -70,3 +115,66 @@ impl Drop for Updater {
         info!("Dropping Updater");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plugin::Samples;
+    use std::fmt;
+
+    struct Counting {
+        updates: Arc<AtomicUsize>,
+        next_update: Duration,
+    }
+
+    impl fmt::Debug for Counting {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Counting")
+        }
+    }
+
+    impl InputInstance for Counting {
+        fn poll(&self) -> Result<Samples> {
+            Ok(Vec::new())
+        }
+
+        fn update(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+            self.updates.fetch_add(1, Ordering::Relaxed);
+            Box::new(future::ok(()))
+        }
+
+        fn next_update(&self) -> Duration {
+            self.next_update
+        }
+    }
+
+    #[test]
+    fn test_next_update() {
+        let counts: Vec<_> = (0..3).map(|_| Arc::new(AtomicUsize::new(0))).collect();
+        let next_updates = [0, 1000, 2500];
+
+        let input: Vec<Arc<Box<InputInstance>>> = counts
+            .iter()
+            .zip(next_updates.iter())
+            .map(|(updates, &next_update)| {
+                Arc::new(Box::new(Counting {
+                    updates: updates.clone(),
+                    next_update: Duration::from_millis(next_update),
+                }) as Box<InputInstance>)
+            })
+            .collect();
+
+        let updater = Updater::new(
+            Arc::new(input),
+            Arc::new(CpuPool::new(1)),
+            Duration::from_millis(1000),
+        );
+
+        for _ in 0..6 {
+            updater.run().wait().unwrap();
+        }
+
+        let counts: Vec<_> = counts.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+        assert_eq!(vec![6, 6, 2], counts);
+    }
+}